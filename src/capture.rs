@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::gif;
+use crate::palette::Palette;
+use crate::screenshot::{self, ImageFormat, Screen};
+
+// Records the screen once per 60 Hz frame, to an animated GIF or to a directory of
+// numbered images. With dedupe on, a frame that's the same as the one before it
+// isn't written again; GIFs just show the previous frame for longer.
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// GIF delays are in hundredths of a second, and browsers play anything shorter
+// than 2/100 slowly, so frames that would be shown for less are merged into the next one
+const MINIMUM_DELAY: u64 = 2;
+
+enum Output {
+    Gif {
+        writer: BufWriter<File>,
+        // the frame waiting to be written, and the frame number it was first shown on
+        pending: Option<(Vec<u8>, u64)>,
+    },
+    Frames {
+        directory: String,
+        format: ImageFormat,
+        written: usize,
+    },
+}
+
+pub struct Capture {
+    output: Output,
+    scale: usize,
+    palette: Palette,
+    dedupe: bool,
+    frames: u64,
+    last_frame: Option<Vec<u8>>,
+}
+
+// hundredths of a second since the start, at 60 frames a second
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + 30) / 60
+}
+
+impl Capture {
+    pub fn to_gif(filepath: &str, scale: usize, palette: Palette) -> Result<Self, String> {
+        let file = File::create(filepath)
+            .map_err(|error| format!("Unable to create {filepath}: {error}"))?;
+        let mut capture = Capture {
+            output: Output::Gif {
+                writer: BufWriter::new(file),
+                pending: None,
+            },
+            scale: scale.max(1),
+            palette,
+            dedupe: false,
+            frames: 0,
+            last_frame: None,
+        };
+        capture.write_gif_header()?;
+        Ok(capture)
+    }
+
+    // frame00000.png, frame00001.png, ... in directory
+    pub fn to_frames(
+        directory: &str,
+        format: ImageFormat,
+        scale: usize,
+        palette: Palette,
+    ) -> Result<Self, String> {
+        std::fs::create_dir_all(directory)
+            .map_err(|error| format!("Unable to create {directory}: {error}"))?;
+        Ok(Capture {
+            output: Output::Frames {
+                directory: directory.to_owned(),
+                format,
+                written: 0,
+            },
+            scale: scale.max(1),
+            palette,
+            dedupe: false,
+            frames: 0,
+            last_frame: None,
+        })
+    }
+
+    pub fn dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    // called once per frame with the framebuffer
+    pub fn add_frame(&mut self, framebuffer: &[u8]) -> Result<(), String> {
+        let frame = self.frames;
+        self.frames += 1;
+        let repeated = self.last_frame.as_deref() == Some(framebuffer);
+        if self.dedupe && repeated {
+            return Ok(());
+        }
+        self.last_frame = Some(framebuffer.to_vec());
+
+        match &mut self.output {
+            Output::Gif { pending, .. } => {
+                let previous = pending.replace((framebuffer.to_vec(), frame));
+                if let Some((pixels, start)) = previous {
+                    if centiseconds(frame) - centiseconds(start) < MINIMUM_DELAY {
+                        // too short to show, so the new frame takes its place
+                        self.replace_pending_start(start);
+                        return Ok(());
+                    }
+                    self.write_gif_frame(&pixels, centiseconds(frame) - centiseconds(start))?;
+                }
+                Ok(())
+            }
+            Output::Frames {
+                directory,
+                format,
+                written,
+            } => {
+                let extension = match format {
+                    ImageFormat::Png => "png",
+                    ImageFormat::Ppm => "ppm",
+                };
+                let filepath = format!("{directory}/frame{written:05}.{extension}");
+                *written += 1;
+                let screen = Screen {
+                    framebuffer,
+                    width: WIDTH,
+                };
+                screenshot::save(&filepath, &screen, self.scale, &self.palette)
+            }
+        }
+    }
+
+    fn replace_pending_start(&mut self, start: u64) {
+        if let Output::Gif {
+            pending: Some((_, pending_start)),
+            ..
+        } = &mut self.output
+        {
+            *pending_start = start;
+        }
+    }
+
+    // writes out anything still waiting, and the end of the GIF
+    fn finish(&mut self) -> Result<(), String> {
+        let frames = self.frames;
+        if let Output::Gif { pending, .. } = &mut self.output {
+            if let Some((pixels, start)) = pending.take() {
+                let delay = (centiseconds(frames) - centiseconds(start)).max(MINIMUM_DELAY);
+                self.write_gif_frame(&pixels, delay)?;
+            }
+            self.write(&[0x3B])?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        match &mut self.output {
+            Output::Gif { writer, .. } => writer
+                .write_all(bytes)
+                .map_err(|error| format!("Unable to write GIF: {error}")),
+            Output::Frames { .. } => Ok(()),
+        }
+    }
+
+    fn write_gif_header(&mut self) -> Result<(), String> {
+        let width = (WIDTH * self.scale) as u16;
+        let height = (HEIGHT * self.scale) as u16;
+        let mut header = b"GIF89a".to_vec();
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        // a global colour table of 2 entries, background colour 0, square pixels
+        header.extend_from_slice(&[0x80, 0, 0]);
+        header.extend_from_slice(&self.palette.background);
+        header.extend_from_slice(&self.palette.foreground);
+        // loop forever
+        header.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+        self.write(&header)
+    }
+
+    fn write_gif_frame(&mut self, framebuffer: &[u8], delay: u64) -> Result<(), String> {
+        let screen = Screen {
+            framebuffer,
+            width: WIDTH,
+        };
+        let pixels: Vec<u8> = screen.scaled_rows(self.scale).concat();
+        let width = (screen.width * self.scale) as u16;
+        let height = (screen.height() * self.scale) as u16;
+
+        // graphic control extension, for the delay
+        let mut data = vec![0x21, 0xF9, 0x04, 0x00];
+        data.extend_from_slice(&(delay.min(u16::MAX as u64) as u16).to_le_bytes());
+        data.extend_from_slice(&[0x00, 0x00]);
+        // image descriptor covering the whole screen, using the global colour table
+        data.push(0x2C);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.push(0x00);
+        gif::write_image_data(&mut data, &pixels, LZW_MINIMUM_CODE_SIZE);
+        self.write(&data)
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        // quitting panics, and the GIF should still be usable
+        if let Err(error) = self.finish() {
+            eprintln!("{error}");
+        }
+    }
+}
+
+// GIF doesn't allow less than 2, even with only two colours
+const LZW_MINIMUM_CODE_SIZE: u32 = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gif() {
+        let filepath = std::env::temp_dir().join("chip8_capture_test.gif");
+        let filepath = filepath.to_str().unwrap();
+        let blank = vec![0; 64 * 32];
+        let mut lit = blank.clone();
+        lit[0] = 1;
+        {
+            let mut capture = Capture::to_gif(filepath, 1, Palette::default())
+                .unwrap()
+                .dedupe(true);
+            for frame in [&blank, &blank, &blank, &lit, &lit, &blank] {
+                capture.add_frame(frame).unwrap();
+            }
+        }
+        let data = std::fs::read(filepath).unwrap();
+        std::fs::remove_file(filepath).unwrap();
+        assert_eq!(&data[..6], b"GIF89a");
+        assert_eq!(&data[6..10], &[64, 0, 32, 0]);
+        assert_eq!(data.last(), Some(&0x3B));
+        // one frame for each change, with the delay of each graphic control extension
+        // rounded so the total is 6/60 of a second
+        let delays: Vec<u16> = (0..data.len() - 5)
+            .filter(|i| data[*i..*i + 3] == [0x21, 0xF9, 0x04])
+            .map(|i| u16::from_le_bytes([data[i + 4], data[i + 5]]))
+            .collect();
+        assert_eq!(delays, vec![5, 3, 2]);
+    }
+
+    #[test]
+    fn test_frames() {
+        let directory = std::env::temp_dir().join("chip8_capture_test_frames");
+        let directory = directory.to_str().unwrap();
+        {
+            let mut capture =
+                Capture::to_frames(directory, ImageFormat::Ppm, 1, Palette::default()).unwrap();
+            for _ in 0..3 {
+                capture.add_frame(&[0; 64 * 32]).unwrap();
+            }
+        }
+        let mut names: Vec<String> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        std::fs::remove_dir_all(directory).unwrap();
+        assert_eq!(
+            names,
+            vec!["frame00000.ppm", "frame00001.ppm", "frame00002.ppm"]
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use crate::config;
+use crate::font;
+use crate::gif::{self, Image};
+use crate::json::Json;
+use crate::octo;
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+
+// Octo's cartridges: GIFs with a program and its settings hidden in the label. The colour
+// table has each colour 16 times over, too close together to tell apart, and the low 4
+// bits of each pixel's colour index carry half a byte, high half first. The bytes are a
+// 4 byte big endian length, then JSON with Octo source and Octo's options:
+//
+//   {"program": ": main ...", "options": {"tickrate": 20, "fillColor": "#FFCC00", ...}}
+//
+// A ROM is exported as source that is only its bytes, so it assembles back exactly.
+
+const WIDTH: usize = 256;
+const MINIMUM_HEIGHT: usize = 64;
+const LABEL_SCALE: usize = 2;
+const LABEL_MARGIN: usize = 8;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cartridge {
+    pub program: Vec<u8>,
+    pub tick_rate: Option<u32>,
+    pub quirks: Quirks,
+    pub palette: Option<Palette>,
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", color[0], color[1], color[2])
+}
+
+// Octo's names for the quirks this emulator has
+const QUIRKS: [&str; 3] = ["logicQuirks", "jumpQuirks", "clipQuirks"];
+
+fn quirk_flags(quirks: Quirks) -> [bool; 3] {
+    [
+        quirks.logic_resets_vf,
+        quirks.jump_uses_vx,
+        quirks.clip_sprites,
+    ]
+}
+
+// what goes in the payload for the program
+fn source(program: &[u8]) -> String {
+    let mut source = ": main\n".to_owned();
+    for line in program.chunks(16) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("0x{byte:02X}")).collect();
+        source.push_str(&bytes.join(" "));
+        source.push('\n');
+    }
+    source
+}
+
+// a colour for each shade of the label and each half byte it can carry
+fn colour_table(shades: [[u8; 3]; 2]) -> Vec<[u8; 3]> {
+    let mut table = Vec::new();
+    for shade in 0..16 {
+        let [red, green, blue] = shades[shade.min(1)];
+        for bits in 0..16u8 {
+            table.push([
+                red ^ (bits & 1),
+                green ^ (bits >> 1 & 1),
+                blue ^ (bits >> 2),
+            ]);
+        }
+    }
+    table
+}
+
+// the title in the top left, with a pixel per font pixel at LABEL_SCALE
+fn label(title: &str, height: usize) -> Vec<bool> {
+    let mut pixels = vec![false; WIDTH * height];
+    for (x, y) in font::text_pixels(title) {
+        for dy in 0..LABEL_SCALE {
+            for dx in 0..LABEL_SCALE {
+                let x = LABEL_MARGIN + x as usize * LABEL_SCALE + dx;
+                let y = LABEL_MARGIN + y as usize * LABEL_SCALE + dy;
+                if x < WIDTH && y < height {
+                    pixels[y * WIDTH + x] = true;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+impl Cartridge {
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let image = gif::decode(data)?;
+        let bytes: Vec<u8> = image
+            .pixels
+            .chunks_exact(2)
+            .map(|pair| (pair[0] & 0xF) << 4 | (pair[1] & 0xF))
+            .collect();
+        let length = match bytes.get(..4) {
+            Some(&[a, b, c, d]) => u32::from_be_bytes([a, b, c, d]) as usize,
+            _ => return Err("Not a cartridge".to_owned()),
+        };
+        let payload = bytes
+            .get(4..4 + length)
+            .ok_or("Not a cartridge, or its data is truncated")?;
+        let payload = String::from_utf8_lossy(payload);
+        let json = Json::parse(&payload).map_err(|error| format!("Cartridge data: {error}"))?;
+
+        let source = json
+            .get("program")
+            .and_then(Json::as_str)
+            .ok_or("Cartridge has no program")?;
+        let program =
+            octo::assemble(source).map_err(|error| format!("Cartridge program: {error}"))?;
+
+        let options = json.get("options");
+        let option = |name| options.and_then(|options| options.get(name));
+        let tick_rate = match option("tickrate") {
+            None => None,
+            Some(tick_rate) => match tick_rate.as_u64() {
+                Some(tick_rate) if (1..=u32::MAX as u64).contains(&tick_rate) => {
+                    Some(tick_rate as u32)
+                }
+                _ => return Err("Cartridge has an invalid tick rate".to_owned()),
+            },
+        };
+        let enabled = |name| option(name) == Some(&Json::Bool(true));
+        let quirks = Quirks {
+            logic_resets_vf: enabled(QUIRKS[0]),
+            jump_uses_vx: enabled(QUIRKS[1]),
+            clip_sprites: enabled(QUIRKS[2]),
+        };
+        let colours: Option<Vec<&str>> =
+            ["backgroundColor", "fillColor", "fillColor2", "blendColor"]
+                .iter()
+                .map(|name| option(name).and_then(Json::as_str))
+                .collect();
+        let palette = match colours {
+            Some(colours) => Some(
+                Palette::parse(&colours.join(","))
+                    .map_err(|error| format!("Cartridge colours: {error}"))?,
+            ),
+            None => None,
+        };
+
+        Ok(Cartridge {
+            program,
+            tick_rate,
+            quirks,
+            palette,
+        })
+    }
+
+    pub fn load(filepath: &str) -> Result<Self, String> {
+        let data = std::fs::read(filepath)
+            .map_err(|error| format!("Unable to read {filepath}: {error}"))?;
+        Cartridge::decode(&data)
+    }
+
+    // a GIF with the title on its label
+    pub fn encode(&self, title: &str) -> Vec<u8> {
+        let mut options = Vec::new();
+        if let Some(tick_rate) = self.tick_rate {
+            options.push(("tickrate", Json::from(tick_rate as u64)));
+        }
+        for (name, enabled) in QUIRKS.iter().zip(quirk_flags(self.quirks)) {
+            options.push((name, Json::from(enabled)));
+        }
+        let palette = self.palette.unwrap_or_default();
+        options.extend([
+            ("backgroundColor", Json::from(hex(palette.background))),
+            ("fillColor", Json::from(hex(palette.foreground))),
+            ("fillColor2", Json::from(hex(palette.plane2))),
+            ("blendColor", Json::from(hex(palette.both))),
+        ]);
+        let payload = Json::object(vec![
+            ("program", Json::from(source(&self.program))),
+            ("options", Json::object(options)),
+        ])
+        .to_string();
+
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(payload.as_bytes());
+        let height = (bytes.len() * 2).div_ceil(WIDTH).max(MINIMUM_HEIGHT);
+        let label = label(title, height);
+        let nybbles = bytes.iter().flat_map(|byte| [byte >> 4, byte & 0xF]);
+        let pixels = label
+            .iter()
+            .zip(nybbles.chain(std::iter::repeat(0)))
+            .map(|(lit, nybble)| (*lit as u8) << 4 | nybble)
+            .collect();
+        gif::encode(&Image {
+            width: WIDTH,
+            height,
+            palette: colour_table([palette.background, palette.foreground]),
+            pixels,
+        })
+    }
+
+    pub fn save(&self, filepath: &str, title: &str) -> Result<(), String> {
+        std::fs::write(filepath, self.encode(title))
+            .map_err(|error| format!("Unable to write {filepath}: {error}"))
+    }
+
+    // the settings as options, with the palette as the four colours --palette takes
+    pub fn options(&self) -> Vec<(String, String)> {
+        let palette = self.palette.map(|palette| {
+            let colours = [
+                palette.background,
+                palette.foreground,
+                palette.plane2,
+                palette.both,
+            ];
+            let colours: Vec<String> = colours.into_iter().map(hex).collect();
+            colours.join(",")
+        });
+        config::program_options(self.quirks, self.tick_rate, palette)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let cartridge = Cartridge {
+            program: (0..=255).chain(0..100).collect(),
+            tick_rate: Some(15),
+            quirks: Quirks {
+                logic_resets_vf: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+            },
+            // four colours, since a cartridge has no grid colour to keep
+            palette: Some(Palette::parse("#1A1000,#FFB000,#996A00,#FFD780").unwrap()),
+        };
+        let data = cartridge.encode("Round trip");
+        let image = gif::decode(&data).unwrap();
+        assert_eq!(image.width, 256);
+        // the label is drawn in the foreground colour, give or take the hidden bits
+        let lit = image.pixels[(LABEL_MARGIN + 1) * WIDTH + LABEL_MARGIN] as usize;
+        assert_eq!(lit >> 4, 1);
+        let color = image.palette[lit];
+        let foreground = [0xFF, 0xB0, 0x00];
+        assert!((0..3).all(|channel| color[channel].abs_diff(foreground[channel]) < 8));
+
+        assert_eq!(Cartridge::decode(&data).unwrap(), cartridge);
+        assert_eq!(
+            cartridge.options(),
+            vec![
+                ("quirks".to_owned(), "vf-reset,clip".to_owned()),
+                ("tick-rate".to_owned(), "15".to_owned()),
+                (
+                    "palette".to_owned(),
+                    "#1A1000,#FFB000,#996A00,#FFD780".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_octo_source() {
+        // a payload written the way Octo writes one, with options this emulator ignores
+        let payload = r#"{"program": ": main\n  v0 := 5\n  loop again", "options":
+            {"tickrate": 20, "shiftQuirks": true, "jumpQuirks": true, "fontStyle": "octo"}}"#;
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(payload.as_bytes());
+        let pixels = bytes
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0xF])
+            .collect();
+        let data = gif::encode(&Image {
+            width: bytes.len() * 2,
+            height: 1,
+            palette: colour_table([[0; 3], [255; 3]]),
+            pixels,
+        });
+        let cartridge = Cartridge::decode(&data).unwrap();
+        assert_eq!(cartridge.program, vec![0x60, 0x05, 0x12, 0x02]);
+        assert_eq!(cartridge.tick_rate, Some(20));
+        assert!(cartridge.quirks.jump_uses_vx && !cartridge.quirks.clip_sprites);
+        assert_eq!(cartridge.palette, None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let image = |pixels: Vec<u8>| {
+            gif::encode(&Image {
+                width: pixels.len(),
+                height: 1,
+                palette: colour_table([[0; 3], [255; 3]]),
+                pixels,
+            })
+        };
+        assert_eq!(
+            Cartridge::decode(&image(vec![0; 8])).unwrap_err(),
+            "Cartridge data: Expected a value but the text ended"
+        );
+        assert!(Cartridge::decode(&image(vec![0, 0, 0, 0, 0, 0, 0xF, 0xF]))
+            .unwrap_err()
+            .contains("truncated"));
+        assert!(Cartridge::decode(b"not a gif").is_err());
+    }
+}
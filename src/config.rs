@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use crate::quirks::Quirks;
+
+// A config file of "name = value" lines, giving defaults for the command line options
+// of the same name (without the leading --). Lines starting with # are comments, so
+// that colours like #FFB000 can still be values.
+//
+//   # ~/.config/chip8-emulator/config
+//   palette = #1A1000,#FFB000
+//   scale = 4
+
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                entries.push((name.trim().to_owned(), value.trim().to_owned()))
+            }
+            _ => return Err(format!("{}: Expected 'name = value'", index + 1)),
+        }
+    }
+    Ok(entries)
+}
+
+pub fn load(filepath: &str) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(filepath)
+        .map_err(|error| format!("Unable to read {filepath}: {error}"))?;
+    parse(&text).map_err(|error| format!("{filepath}:{error}"))
+}
+
+// a program's settings, from the ROM database or a cartridge, as "name = value" options
+// for the command line and config to override
+pub fn program_options(
+    quirks: Quirks,
+    tick_rate: Option<u32>,
+    palette: Option<String>,
+) -> Vec<(String, String)> {
+    let mut options = vec![("quirks".to_owned(), quirks.to_string())];
+    if let Some(tick_rate) = tick_rate {
+        options.push(("tick-rate".to_owned(), tick_rate.to_string()));
+    }
+    if let Some(palette) = palette {
+        options.push(("palette".to_owned(), palette));
+    }
+    options
+}
+
+// where the config is read from when --config isn't given
+pub fn default_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/chip8-emulator/config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse("# palette\npalette = #1A1000,#FFB000\n\n  scale=4\n").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("palette".to_owned(), "#1A1000,#FFB000".to_owned()),
+                ("scale".to_owned(), "4".to_owned())
+            ]
+        );
+        assert_eq!(
+            parse("palette amber").unwrap_err(),
+            "1: Expected 'name = value'"
+        );
+    }
+
+    #[test]
+    fn test_program_options() {
+        assert_eq!(
+            program_options(Quirks::default(), None, None),
+            vec![("quirks".to_owned(), Quirks::default().to_string())]
+        );
+        let options = program_options(Quirks::default(), Some(20), Some("amber".to_owned()));
+        assert_eq!(options[1], ("tick-rate".to_owned(), "20".to_owned()));
+        assert_eq!(options[2], ("palette".to_owned(), "amber".to_owned()));
+    }
+}
@@ -0,0 +1,787 @@
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::time::Instant;
+
+use crate::emulator::{Chip8, FRAME_DURATION};
+use crate::hex::{from_hex, to_hex};
+use crate::json::Json;
+use crate::key::Key;
+use crate::register::Register;
+
+// A headless emulator driven over a socket, for test rigs and other scripts. Each line
+// is a JSON-RPC 2.0 request, answered with a line holding the response:
+//
+//   {"jsonrpc": "2.0", "id": 1, "method": "read_memory", "params": {"address": 512, "length": 4}}
+//   {"jsonrpc":"2.0","id":1,"result":[96,1,162,10]}
+//
+// Clients that subscribe to events are sent notifications (requests without an id)
+// when they happen, e.g. {"jsonrpc":"2.0","method":"breakpoint","params":{"pc":518}}, or
+// {"jsonrpc":"2.0","method":"sound","params":{"on":true}} when the sound timer starts.
+// A program that reaches an instruction it can't run is halted there, with the reason
+// as an "error" in the halted event's params.
+
+// error codes from the JSON-RPC spec, and one for everything that goes wrong after that
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+const EVENTS: [&str; 3] = ["breakpoint", "halted", "sound"];
+
+type RpcResult = Result<Json, (i64, String)>;
+
+fn invalid_params(message: &str) -> (i64, String) {
+    (INVALID_PARAMS, message.to_owned())
+}
+
+fn server_error(message: String) -> (i64, String) {
+    (SERVER_ERROR, message)
+}
+
+// a whole number parameter no bigger than max
+fn number(params: &Json, name: &str, max: u64) -> Result<u64, (i64, String)> {
+    params
+        .get(name)
+        .and_then(Json::as_u64)
+        .filter(|number| *number <= max)
+        .ok_or_else(|| invalid_params(&format!("'{name}' should be a number from 0 to {max}")))
+}
+
+fn string<'a>(params: &'a Json, name: &str) -> Result<&'a str, (i64, String)> {
+    params
+        .get(name)
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid_params(&format!("'{name}' should be a string")))
+}
+
+fn registers(chip8: &Chip8) -> Json {
+    let memory = chip8.memory();
+    let v: Vec<u64> = (0..16)
+        .map(|number| memory.peek8(Register::v_register_from(number) as usize) as u64)
+        .collect();
+    let stack: Vec<u64> = chip8
+        .stack()
+        .iter()
+        .map(|address| *address as u64)
+        .collect();
+    Json::object(vec![
+        ("pc", Json::from(chip8.pc() as u64)),
+        ("i", Json::from(memory.peek16(Register::IR as usize) as u64)),
+        ("v", Json::from(v)),
+        ("dt", Json::from(chip8.delay_timer() as u64)),
+        ("st", Json::from(chip8.sound_timer() as u64)),
+        ("stack", Json::from(stack)),
+    ])
+}
+
+// pc, i, v0-vf or dt, where pc has to be an even address with room for an instruction
+fn set_register(chip8: &mut Chip8, name: &str, value: u64) -> Result<(), (i64, String)> {
+    let valid = |register| match register {
+        Register::PC => value <= 0xFFE && value.is_multiple_of(2),
+        Register::IR => value <= 0xFFF,
+        _ => value <= 0xFF,
+    };
+    let Some(register) = Register::from_name(name).filter(|register| valid(*register)) else {
+        return Err(invalid_params(&format!(
+            "Can't set register '{name}' to {value}"
+        )));
+    };
+    match register {
+        Register::DELAY_TIMER => chip8.set_delay_timer(value as u32),
+        Register::PC | Register::IR => chip8.set16(register as usize, value as u16),
+        _ => chip8.set8(register as usize, value as u8),
+    }
+    Ok(())
+}
+
+// lets the server take TCP and Unix socket clients the same way
+trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, String),
+}
+
+impl Listener {
+    fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => Ok(Box::new(listener.accept()?.0)),
+        }
+    }
+}
+
+// the socket file would stop the next server from starting
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            std::fs::remove_file(path).unwrap_or(());
+        }
+    }
+}
+
+struct Client {
+    connection: Box<dyn Connection>,
+    input: Vec<u8>,
+    events: BTreeSet<String>,
+    closed: bool,
+}
+
+impl Client {
+    // blocking, since lines are small and the client is waiting for them
+    fn write_line(&mut self, line: &str) {
+        let result = self
+            .connection
+            .set_nonblocking(false)
+            .and_then(|_| self.connection.write_all(format!("{line}\n").as_bytes()))
+            .and_then(|_| self.connection.set_nonblocking(true));
+        if result.is_err() {
+            self.closed = true;
+        }
+    }
+
+    // everything that's arrived since last time, as whole lines
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.connection.read(&mut buffer) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(length) => self.input.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => {
+                    if error.kind() != io::ErrorKind::WouldBlock {
+                        self.closed = true;
+                    }
+                    break;
+                }
+            }
+        }
+        let mut lines = Vec::new();
+        while let Some(end) = self.input.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_owned());
+        }
+        lines
+    }
+}
+
+pub struct ControlServer {
+    listener: Listener,
+    clients: Vec<Client>,
+    chip8: Option<Chip8>,
+    running: bool,
+    breakpoints: BTreeSet<u16>,
+    // lets the instruction we stopped on run when resuming, instead of breaking again
+    resuming: bool,
+    // whether the sound timer was running, for the sound event
+    sounding: bool,
+    quit: bool,
+}
+
+impl ControlServer {
+    // a port number listens on loopback TCP, anything else is the path of a Unix socket
+    pub fn listen(address: &str, chip8: Option<Chip8>) -> Result<Self, String> {
+        let listener = match address.parse::<u16>() {
+            Ok(port) => TcpListener::bind(("127.0.0.1", port))
+                .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+                .map(Listener::Tcp),
+            #[cfg(unix)]
+            Err(_) => UnixListener::bind(address)
+                .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+                .map(|listener| Listener::Unix(listener, address.to_owned())),
+            #[cfg(not(unix))]
+            Err(_) => Err(io::Error::other("Unix sockets aren't supported here")),
+        }
+        .map_err(|error| format!("Unable to listen on {address}: {error}"))?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            chip8,
+            running: false,
+            breakpoints: BTreeSet::new(),
+            resuming: false,
+            sounding: false,
+            quit: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.listener {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Unix(..) => None,
+        }
+    }
+
+    // at 60 frames a second while running, until a client asks to quit
+    pub fn run(&mut self) {
+        let mut next_frame = Instant::now();
+        while !self.quit {
+            self.tick();
+            next_frame += FRAME_DURATION;
+            let now = Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        while let Ok(connection) = self.listener.accept() {
+            if connection.set_nonblocking(true).is_ok() {
+                self.clients.push(Client {
+                    connection,
+                    input: Vec::new(),
+                    events: BTreeSet::new(),
+                    closed: false,
+                });
+            }
+        }
+
+        // taken out so requests can change the emulator while a client is borrowed
+        let mut clients = std::mem::take(&mut self.clients);
+        for client in &mut clients {
+            for line in client.read_lines() {
+                if line.is_empty() {
+                    continue;
+                }
+                if let Some(response) = self.handle_line(&mut client.events, &line) {
+                    client.write_line(&response.to_string());
+                }
+            }
+        }
+        clients.retain(|client| !client.closed);
+        self.clients = clients;
+
+        if self.running {
+            self.run_frame();
+        }
+
+        // after requests too, since stepping or loading a state can start or stop it
+        let sounding = self
+            .chip8
+            .as_ref()
+            .is_some_and(|chip8| chip8.sound_timer() > 0);
+        if sounding != self.sounding {
+            self.sounding = sounding;
+            self.notify("sound", Json::object(vec![("on", Json::from(sounding))]));
+        }
+    }
+
+    // like Chip8::run_frame, but checking for breakpoints before every instruction
+    fn run_frame(&mut self) {
+        let Some(chip8) = self.chip8.as_mut() else {
+            return;
+        };
+        chip8.tick_timers();
+        for _ in 0..chip8.cycles_per_frame() {
+            let pc = chip8.pc();
+            let resuming = std::mem::take(&mut self.resuming);
+            if !resuming && self.breakpoints.contains(&pc) {
+                self.running = false;
+                self.notify(
+                    "breakpoint",
+                    Json::object(vec![("pc", Json::from(pc as u64))]),
+                );
+                return;
+            }
+            match chip8.try_cycle() {
+                Ok(-1) => {
+                    self.running = false;
+                    self.notify("halted", Json::object(vec![("pc", Json::from(pc as u64))]));
+                    return;
+                }
+                Ok(_) => {}
+                // a program that can't go on stops where it is, rather than taking the
+                // server down with it
+                Err(error) => {
+                    self.running = false;
+                    self.notify(
+                        "halted",
+                        Json::object(vec![
+                            ("pc", Json::from(pc as u64)),
+                            ("error", Json::from(error)),
+                        ]),
+                    );
+                    return;
+                }
+            }
+        }
+    }
+
+    fn notify(&mut self, event: &str, params: Json) {
+        let notification = Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from(event)),
+            ("params", params),
+        ]);
+        for client in &mut self.clients {
+            if client.events.contains(event) {
+                client.write_line(&notification.to_string());
+            }
+        }
+    }
+
+    // the response to a line, or None if it was a notification
+    fn handle_line(&mut self, events: &mut BTreeSet<String>, line: &str) -> Option<Json> {
+        let (id, result) = match Json::parse(line) {
+            Ok(request) => {
+                let id = request.get("id").cloned();
+                let params = request.get("params").cloned().unwrap_or(Json::Null);
+                let result = match request.get("method").and_then(Json::as_str) {
+                    Some(method) => self.call(events, method, &params),
+                    None => Err((INVALID_REQUEST, "Missing method".to_owned())),
+                };
+                (id?, result)
+            }
+            Err(error) => (Json::Null, Err((PARSE_ERROR, error))),
+        };
+        let outcome = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => (
+                "error",
+                Json::object(vec![
+                    ("code", Json::Number(code as f64)),
+                    ("message", Json::from(message)),
+                ]),
+            ),
+        };
+        Some(Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", id),
+            outcome,
+        ]))
+    }
+
+    fn chip8(&mut self) -> Result<&mut Chip8, (i64, String)> {
+        self.chip8
+            .as_mut()
+            .ok_or_else(|| server_error("No ROM loaded".to_owned()))
+    }
+
+    fn call(&mut self, events: &mut BTreeSet<String>, method: &str, params: &Json) -> RpcResult {
+        match method {
+            "load" => {
+                let path = string(params, "path")?;
+                let chip8 = Chip8::load_from_path(path)
+                    .map_err(|error| server_error(format!("{path}: {error}")))?;
+                self.chip8 = Some(chip8);
+                self.running = false;
+                Ok(Json::Null)
+            }
+            "step" => {
+                let count = match params.get("count") {
+                    Some(_) => number(params, "count", u32::MAX as u64)?,
+                    None => 1,
+                };
+                let chip8 = self.chip8()?;
+                let mut halted = false;
+                for _ in 0..count {
+                    if chip8.try_cycle().map_err(server_error)? == -1 {
+                        halted = true;
+                        break;
+                    }
+                }
+                Ok(Json::object(vec![
+                    ("pc", Json::from(chip8.pc() as u64)),
+                    ("halted", Json::from(halted)),
+                ]))
+            }
+            "run" => {
+                self.chip8()?;
+                self.running = true;
+                self.resuming = true;
+                Ok(Json::Null)
+            }
+            "pause" => {
+                self.running = false;
+                Ok(Json::Null)
+            }
+            "press" | "release" => {
+                let key = Key::try_from(number(params, "key", 0xF)? as u8).map_err(server_error)?;
+                self.chip8()?.set_key(key, method == "press");
+                Ok(Json::Null)
+            }
+            "read_memory" => {
+                let address = number(params, "address", 4095)? as usize;
+                let length = number(params, "length", 4096 - address as u64)? as usize;
+                let memory = self.chip8()?.memory().data();
+                let bytes: Vec<u64> = memory[address..address + length]
+                    .iter()
+                    .map(|byte| *byte as u64)
+                    .collect();
+                Ok(Json::from(bytes))
+            }
+            "write_memory" => {
+                let address = number(params, "address", 4095)? as usize;
+                let bytes: Vec<u8> = params
+                    .get("data")
+                    .and_then(Json::as_array)
+                    .and_then(|values| {
+                        values
+                            .iter()
+                            .map(|value| value.as_u64().filter(|byte| *byte <= 0xFF))
+                            .map(|byte| byte.map(|byte| byte as u8))
+                            .collect()
+                    })
+                    .ok_or_else(|| invalid_params("'data' should be an array of bytes"))?;
+                if address + bytes.len() > 4096 {
+                    return Err(invalid_params("'data' goes past the end of memory"));
+                }
+                let chip8 = self.chip8()?;
+                for (offset, byte) in bytes.iter().enumerate() {
+                    chip8.set8(address + offset, *byte);
+                }
+                Ok(Json::Null)
+            }
+            "registers" => Ok(registers(self.chip8()?)),
+            "set_register" => {
+                let name = string(params, "name")?;
+                let value = number(params, "value", u16::MAX as u64)?;
+                set_register(self.chip8()?, name, value)?;
+                Ok(Json::Null)
+            }
+            "framebuffer" => {
+                let pixels: Vec<u64> = self
+                    .chip8()?
+                    .memory()
+                    .framebuffer()
+                    .iter()
+                    .map(|pixel| *pixel as u64)
+                    .collect();
+                Ok(Json::object(vec![
+                    ("width", Json::from(64u64)),
+                    ("height", Json::from(32u64)),
+                    ("pixels", Json::from(pixels)),
+                ]))
+            }
+            // the same bytes as a save state file, in hex
+            "save_state" => Ok(Json::from(to_hex(&self.chip8()?.save_state()))),
+            "load_state" => {
+                let state = from_hex(string(params, "state")?)
+                    .ok_or_else(|| invalid_params("'state' should be hex"))?;
+                self.chip8()?.load_state(&state).map_err(server_error)?;
+                Ok(Json::Null)
+            }
+            "break" | "delete" => {
+                let address = number(params, "address", 4095)? as u16;
+                if method == "break" {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                Ok(Json::Null)
+            }
+            "subscribe" | "unsubscribe" => {
+                let names = params
+                    .get("events")
+                    .and_then(Json::as_array)
+                    .and_then(|names| names.iter().map(Json::as_str).collect::<Option<Vec<_>>>())
+                    .ok_or_else(|| invalid_params("'events' should be an array of names"))?;
+                for name in names {
+                    if !EVENTS.contains(&name) {
+                        return Err(invalid_params(&format!(
+                            "Unknown event '{name}', expected one of {}",
+                            EVENTS.join(", ")
+                        )));
+                    }
+                    if method == "subscribe" {
+                        events.insert(name.to_owned());
+                    } else {
+                        events.remove(name);
+                    }
+                }
+                Ok(Json::Null)
+            }
+            "quit" => {
+                self.quit = true;
+                Ok(Json::Null)
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{method}'"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const PROGRAM: &str = r#"
+    200: 6001 // LD V0, 1
+    202: 7001 // ADD V0, 1
+    204: A300 // LD I, 300
+    206: 1202 // JP 202
+    300: AB
+    "#;
+
+    struct TestClient {
+        stream: TcpStream,
+        input: Vec<u8>,
+    }
+
+    impl TestClient {
+        fn connect(server: &ControlServer) -> Self {
+            let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(1)))
+                .unwrap();
+            Self {
+                stream,
+                input: Vec::new(),
+            }
+        }
+
+        // the next line from the server, ticking it until there is one
+        fn receive(&mut self, server: &mut ControlServer) -> Json {
+            for _ in 0..10000 {
+                if let Some(end) = self.input.iter().position(|byte| *byte == b'\n') {
+                    let line: Vec<u8> = self.input.drain(..=end).collect();
+                    return Json::parse(&String::from_utf8(line).unwrap()).unwrap();
+                }
+                server.tick();
+                let mut buffer = [0; 65536];
+                if let Ok(length) = self.stream.read(&mut buffer) {
+                    self.input.extend_from_slice(&buffer[..length]);
+                }
+            }
+            panic!("No response from the server");
+        }
+
+        fn call(&mut self, server: &mut ControlServer, method: &str, params: &str) -> Json {
+            let request =
+                format!("{{\"jsonrpc\": \"2.0\", \"id\": 7, \"method\": \"{method}\", \"params\": {params}}}\n");
+            self.stream.write_all(request.as_bytes()).unwrap();
+            let response = self.receive(server);
+            assert_eq!(response.get("id"), Some(&Json::Number(7.0)));
+            response
+        }
+
+        fn result(&mut self, server: &mut ControlServer, method: &str, params: &str) -> Json {
+            let response = self.call(server, method, params);
+            response.get("result").cloned().expect("Expected a result")
+        }
+
+        fn error(&mut self, server: &mut ControlServer, method: &str, params: &str) -> i64 {
+            match self.call(server, method, params).get("error") {
+                Some(error) => match error.get("code") {
+                    Some(Json::Number(code)) => *code as i64,
+                    _ => panic!("Error without a code"),
+                },
+                None => panic!("Expected an error"),
+            }
+        }
+    }
+
+    fn server() -> ControlServer {
+        ControlServer::listen("0", Some(Chip8::load_from_text(PROGRAM))).unwrap()
+    }
+
+    #[test]
+    fn test_memory_and_registers() {
+        let mut server = server();
+        let mut client = TestClient::connect(&server);
+
+        assert_eq!(
+            client.result(
+                &mut server,
+                "read_memory",
+                r#"{"address": 512, "length": 3}"#
+            ),
+            Json::from(vec![0x60u64, 0x01, 0x70])
+        );
+        client.result(
+            &mut server,
+            "write_memory",
+            r#"{"address": 769, "data": [1, 2]}"#,
+        );
+        assert_eq!(
+            client.result(
+                &mut server,
+                "read_memory",
+                r#"{"address": 768, "length": 3}"#
+            ),
+            Json::from(vec![0xABu64, 1, 2])
+        );
+        let step = client.result(&mut server, "step", r#"{"count": 3}"#);
+        assert_eq!(step.get("pc"), Some(&Json::from(0x206u64)));
+        client.result(&mut server, "set_register", r#"{"name": "VF", "value": 9}"#);
+        let registers = client.result(&mut server, "registers", "null");
+        assert_eq!(registers.get("i"), Some(&Json::from(0x300u64)));
+        let v = registers.get("v").and_then(Json::as_array).unwrap();
+        assert_eq!((&v[0], &v[15]), (&Json::from(2u64), &Json::from(9u64)));
+
+        let state = client.result(&mut server, "save_state", "null");
+        client.result(&mut server, "step", "{}");
+        let params = format!("{{\"state\": {state}}}");
+        client.result(&mut server, "load_state", &params);
+        let registers = client.result(&mut server, "registers", "null");
+        assert_eq!(registers.get("pc"), Some(&Json::from(0x206u64)));
+
+        let framebuffer = client.result(&mut server, "framebuffer", "null");
+        let pixels = framebuffer.get("pixels").and_then(Json::as_array).unwrap();
+        assert_eq!(pixels.len(), 64 * 32);
+    }
+
+    #[test]
+    fn test_breakpoint_event() {
+        let mut server = server();
+        let mut client = TestClient::connect(&server);
+        client.result(&mut server, "subscribe", r#"{"events": ["breakpoint"]}"#);
+        client.result(&mut server, "break", r#"{"address": 518}"#);
+        client.result(&mut server, "press", r#"{"key": 10}"#);
+        client.result(&mut server, "run", "{}");
+        let notification = client.receive(&mut server);
+        assert_eq!(notification.get("id"), None);
+        assert_eq!(
+            notification.get("method").and_then(Json::as_str),
+            Some("breakpoint")
+        );
+        assert_eq!(
+            notification
+                .get("params")
+                .and_then(|params| params.get("pc")),
+            Some(&Json::from(518u64))
+        );
+        assert!(!server.running);
+    }
+
+    #[test]
+    fn test_sound_event() {
+        let mut server = server();
+        let mut client = TestClient::connect(&server);
+        client.result(&mut server, "subscribe", r#"{"events": ["sound"]}"#);
+        // LD V0, 2; LD ST, V0; JP 204
+        client.result(
+            &mut server,
+            "write_memory",
+            r#"{"address": 512, "data": [96, 2, 240, 24, 18, 4]}"#,
+        );
+        client.result(&mut server, "step", r#"{"count": 2}"#);
+        let sound = |notification: Json| {
+            assert_eq!(
+                notification.get("method").and_then(Json::as_str),
+                Some("sound")
+            );
+            notification
+                .get("params")
+                .and_then(|params| params.get("on"))
+                .cloned()
+        };
+        assert_eq!(sound(client.receive(&mut server)), Some(Json::from(true)));
+        let registers = client.result(&mut server, "registers", "null");
+        assert_eq!(registers.get("st"), Some(&Json::from(2u64)));
+        client.result(&mut server, "run", "{}");
+        assert_eq!(sound(client.receive(&mut server)), Some(Json::from(false)));
+    }
+
+    #[test]
+    fn test_invalid_instruction() {
+        let mut server = server();
+        let mut client = TestClient::connect(&server);
+        client.result(&mut server, "subscribe", r#"{"events": ["halted"]}"#);
+        // LD V0, 1; then FFFF, which isn't an instruction
+        client.result(
+            &mut server,
+            "write_memory",
+            r#"{"address": 512, "data": [96, 1, 255, 255]}"#,
+        );
+        assert_eq!(
+            client.error(&mut server, "step", r#"{"count": 2}"#),
+            SERVER_ERROR
+        );
+        let registers = client.result(&mut server, "registers", "null");
+        assert_eq!(registers.get("pc"), Some(&Json::from(0x202u64)));
+
+        client.result(&mut server, "run", "{}");
+        let notification = client.receive(&mut server);
+        assert_eq!(
+            notification.get("method").and_then(Json::as_str),
+            Some("halted")
+        );
+        let params = notification.get("params").unwrap();
+        assert_eq!(params.get("pc"), Some(&Json::from(0x202u64)));
+        assert!(params.get("error").and_then(Json::as_str).is_some());
+        assert!(!server.running);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = server();
+        let mut client = TestClient::connect(&server);
+        assert_eq!(client.error(&mut server, "dance", "{}"), METHOD_NOT_FOUND);
+        assert_eq!(
+            client.error(
+                &mut server,
+                "read_memory",
+                r#"{"address": 4095, "length": 2}"#
+            ),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            client.error(&mut server, "press", r#"{"key": 16}"#),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            client.error(&mut server, "subscribe", r#"{"events": ["beep"]}"#),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            client.error(&mut server, "load", r#"{"path": "missing.ch8"}"#),
+            SERVER_ERROR
+        );
+
+        for params in [
+            r#"{"name": "pc", "value": 4095}"#,
+            r#"{"name": "pc", "value": 513}"#,
+            r#"{"name": "i", "value": 4096}"#,
+            r#"{"name": "v0", "value": 256}"#,
+        ] {
+            assert_eq!(
+                client.error(&mut server, "set_register", params),
+                INVALID_PARAMS
+            );
+        }
+        client.result(
+            &mut server,
+            "set_register",
+            r#"{"name": "pc", "value": 4094}"#,
+        );
+
+        client.stream.write_all(b"{oops\n").unwrap();
+        let response = client.receive(&mut server);
+        assert_eq!(response.get("id"), Some(&Json::Null));
+        assert!(response.get("error").is_some());
+    }
+}
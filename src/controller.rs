@@ -0,0 +1,182 @@
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::{GameControllerSubsystem, Sdl};
+use std::collections::HashMap;
+
+use crate::frontend::InputEvent;
+use crate::key::Key;
+
+// Gamepads, through SDL's game controller mappings so the buttons are in the same place
+// on every make of pad. By default the D-pad is on 5/7/8/9, which moves in most games,
+// with A and B on 6 and 4.
+
+// the names SDL uses in controller mappings
+fn button_from_name(name: &str) -> Option<Button> {
+    let button = match name.to_lowercase().as_str() {
+        "a" => Button::A,
+        "b" => Button::B,
+        "x" => Button::X,
+        "y" => Button::Y,
+        "back" => Button::Back,
+        "guide" => Button::Guide,
+        "start" => Button::Start,
+        "leftstick" => Button::LeftStick,
+        "rightstick" => Button::RightStick,
+        "leftshoulder" => Button::LeftShoulder,
+        "rightshoulder" => Button::RightShoulder,
+        "dpup" => Button::DPadUp,
+        "dpdown" => Button::DPadDown,
+        "dpleft" => Button::DPadLeft,
+        "dpright" => Button::DPadRight,
+        _ => return None,
+    };
+    Some(button)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ButtonMap {
+    buttons: HashMap<Button, Key>,
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        let buttons = [
+            (Button::DPadUp, Key::Code5),
+            (Button::DPadLeft, Key::Code7),
+            (Button::DPadDown, Key::Code8),
+            (Button::DPadRight, Key::Code9),
+            (Button::A, Key::Code6),
+            (Button::B, Key::Code4),
+        ];
+        ButtonMap {
+            buttons: HashMap::from(buttons),
+        }
+    }
+}
+
+impl ButtonMap {
+    // like Keymap::bind, e.g. "5" and "dpup y"
+    pub fn bind(&mut self, key: &str, names: &str) -> Result<(), String> {
+        let key = u8::from_str_radix(key, 16)
+            .map_err(|_| format!("Invalid keypad key '{key}'"))
+            .and_then(Key::try_from)?;
+        let buttons = names
+            .split_whitespace()
+            .map(|name| button_from_name(name).ok_or(format!("Unknown button name '{name}'")))
+            .collect::<Result<Vec<Button>, String>>()?;
+        self.buttons.retain(|_, bound| *bound != key);
+        for button in buttons {
+            self.buttons.insert(button, key);
+        }
+        Ok(())
+    }
+
+    // the keypad event for a controller button event, if the button is bound
+    pub fn input_event(&self, event: &Event) -> Option<InputEvent> {
+        let (button, pressed) = match event {
+            Event::ControllerButtonDown { button, .. } => (button, true),
+            Event::ControllerButtonUp { button, .. } => (button, false),
+            _ => return None,
+        };
+        let key = self.buttons.get(button)?;
+        Some(InputEvent::Key(*key, pressed))
+    }
+}
+
+// the controllers plugged in, which have to stay open for SDL to send their events
+pub struct Controllers {
+    subsystem: Option<GameControllerSubsystem>,
+    open: HashMap<u32, GameController>,
+    buttons: ButtonMap,
+}
+
+impl Controllers {
+    // controllers are optional, so the window still works if SDL can't provide them
+    pub fn new(sdl_context: &Sdl, buttons: ButtonMap) -> Self {
+        let subsystem = sdl_context
+            .game_controller()
+            .map_err(|error| eprintln!("Unable to initialise game controllers: {error}"))
+            .ok();
+        Self {
+            subsystem,
+            open: HashMap::new(),
+            buttons,
+        }
+    }
+
+    // SDL sends an added event for each controller already plugged in at startup too
+    pub fn handle_event(&mut self, event: &Event) -> Option<InputEvent> {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                let subsystem = self.subsystem.as_ref()?;
+                match subsystem.open(*which) {
+                    Ok(controller) => {
+                        self.open.insert(controller.instance_id(), controller);
+                    }
+                    Err(error) => eprintln!("Unable to open controller {which}: {error}"),
+                }
+                None
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.open.remove(which);
+                None
+            }
+            _ => self.buttons.input_event(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button(button: Button, pressed: bool) -> Event {
+        if pressed {
+            Event::ControllerButtonDown {
+                timestamp: 0,
+                which: 0,
+                button,
+            }
+        } else {
+            Event::ControllerButtonUp {
+                timestamp: 0,
+                which: 0,
+                button,
+            }
+        }
+    }
+
+    #[test]
+    fn test_default() {
+        let buttons = ButtonMap::default();
+        assert_eq!(
+            buttons.input_event(&button(Button::DPadLeft, true)),
+            Some(InputEvent::Key(Key::Code7, true))
+        );
+        assert_eq!(
+            buttons.input_event(&button(Button::DPadLeft, false)),
+            Some(InputEvent::Key(Key::Code7, false))
+        );
+        assert_eq!(buttons.input_event(&button(Button::Start, true)), None);
+        assert_eq!(buttons.input_event(&Event::Quit { timestamp: 0 }), None);
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut buttons = ButtonMap::default();
+        buttons.bind("5", "DPUp y").unwrap();
+        buttons.bind("e", "start").unwrap();
+        assert_eq!(
+            buttons.input_event(&button(Button::Y, true)),
+            Some(InputEvent::Key(Key::Code5, true))
+        );
+        assert_eq!(
+            buttons.input_event(&button(Button::Start, true)),
+            Some(InputEvent::Key(Key::CodeE, true))
+        );
+        assert_eq!(
+            buttons.bind("5", "turbo").unwrap_err(),
+            "Unknown button name 'turbo'"
+        );
+    }
+}
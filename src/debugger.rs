@@ -0,0 +1,768 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::emulator::Chip8;
+use crate::instruction::Instruction;
+use crate::register::Register;
+
+const PROMPT: &str = "(chip8-dbg) ";
+
+const HELP: &str = "\
+Commands:
+  s, step [n]            execute n instructions (default 1)
+  n, next                step over a CALL
+  finish                 run until the current subroutine returns
+  c, continue            run until a breakpoint or watchpoint is hit
+  until <addr>           run until PC reaches addr
+  back [n]               go back n instructions (default 1)
+  p, pause               stop running
+  b, break <addr|op>     break at an address, or on every instruction of a kind (e.g. DRW)
+  d, delete <addr|op>    remove a breakpoint
+  watch [r|w|rw] <loc>   watch an address, a range (300-30F), a V register or I
+  unwatch <loc>          remove a watchpoint
+  info                   list breakpoints and watchpoints
+  regs                   show registers, timers and the stack
+  x <addr> [len]         dump memory
+  l, list [n]            disassemble n instructions from PC
+  q, quit                exit the emulator";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize, // inclusive
+    pub kind: WatchKind,
+    pub name: String,
+}
+
+impl Watchpoint {
+    fn matches(&self, address: usize, write: bool) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::ReadWrite => true,
+        };
+        kind_matches && (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    OpcodeBreakpoint(u16, String),
+    Watchpoint {
+        address: usize,
+        write: bool,
+        name: String,
+    },
+    Reached(u16),
+    Halted,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint(pc) => write!(f, "Breakpoint at {:03X}", pc),
+            StopReason::OpcodeBreakpoint(pc, op) => write!(f, "{} breakpoint at {:03X}", op, pc),
+            StopReason::Watchpoint {
+                address,
+                write,
+                name,
+            } => {
+                let access = if *write { "written" } else { "read" };
+                write!(f, "Watchpoint {}: {:03X} {}", name, address, access)
+            }
+            StopReason::Reached(pc) => write!(f, "Reached {:03X}", pc),
+            StopReason::Halted => write!(f, "Program halted"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mode {
+    Paused,
+    Running,
+    RunTo(u16),
+    // step over a CALL: run until it returns to the next instruction
+    StepOver { return_address: u16, depth: usize },
+    // run until the stack is shallower than it was
+    StepOut { depth: usize },
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    opcode_breakpoints: BTreeSet<String>,
+    watchpoints: Vec<Watchpoint>,
+    mode: Mode,
+    // lets the instruction we stopped on run when resuming, instead of breaking again
+    resuming: bool,
+    commands: Option<Receiver<String>>,
+    quit: bool,
+}
+
+// addresses are hex, with or without a 0x prefix, like in the text format
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches("0x");
+    match u16::from_str_radix(digits, 16) {
+        Ok(address) if address < 4096 => Ok(address),
+        _ => Err(format!("Invalid address '{text}'")),
+    }
+}
+
+// a watch location: V0-VF, I, a single address or an inclusive range like 300-30F
+fn parse_location(text: &str) -> Result<(usize, usize), String> {
+    let upper = text.to_ascii_uppercase();
+    if upper == "I" {
+        let address = Register::IR as usize;
+        return Ok((address, address + 1));
+    }
+    if let Some(digit) = upper.strip_prefix('V') {
+        if let Ok(number) = u8::from_str_radix(digit, 16) {
+            if digit.len() == 1 {
+                let address = Register::v_register_from(number) as usize;
+                return Ok((address, address));
+            }
+        }
+    }
+    match text.split_once('-') {
+        Some((start, end)) => {
+            let start = parse_address(start)? as usize;
+            let end = parse_address(end)? as usize;
+            if end < start {
+                return Err(format!("Invalid range '{text}'"));
+            }
+            Ok((start, end))
+        }
+        None => {
+            let address = parse_address(text)? as usize;
+            Ok((address, address))
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            mode: Mode::Paused,
+            resuming: false,
+            commands: None,
+            quit: false,
+        }
+    }
+
+    // reads commands from stdin on a separate thread, so the emulator
+    // can keep drawing the window while waiting for input
+    pub fn with_terminal_prompt() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        println!("Debugger attached, paused at 200. Type 'help' for a list of commands.");
+        print_prompt();
+
+        Self {
+            commands: Some(receiver),
+            ..Debugger::new()
+        }
+    }
+
+    // for breakpoints set by clicking in the window, so the program keeps going until one
+    pub fn running() -> Self {
+        Self {
+            mode: Mode::Running,
+            ..Debugger::new()
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    // adds a breakpoint, or removes it if there already is one, returning whether it's set
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.breakpoints.remove(&address) {
+            return false;
+        }
+        self.breakpoints.insert(address)
+    }
+
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    pub fn add_opcode_breakpoint(&mut self, class: &str) {
+        self.opcode_breakpoints.insert(class.to_ascii_uppercase());
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    // Called once per iteration of the emulator loop instead of Chip8::cycle.
+    // Handles any pending commands, then executes an instruction unless paused.
+    // Returns -1 once the program should stop, like Chip8::cycle.
+    pub fn tick(&mut self, chip8: &mut Chip8) -> i8 {
+        let mut lines = Vec::new();
+        if let Some(commands) = &self.commands {
+            loop {
+                match commands.try_recv() {
+                    Ok(line) => lines.push(line),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        // stdin was closed, so nobody can resume a paused program
+                        if self.mode == Mode::Paused {
+                            self.quit = true;
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        for line in lines {
+            let output = self.execute_command(chip8, &line);
+            if !output.is_empty() {
+                println!("{output}");
+            }
+            if self.is_paused() {
+                print_prompt();
+            }
+        }
+
+        if self.quit {
+            return -1;
+        }
+        if self.is_paused() {
+            return 0;
+        }
+
+        match self.run_cycle(chip8) {
+            Some(StopReason::Halted) => {
+                println!("{}", StopReason::Halted);
+                -1
+            }
+            Some(reason) => {
+                println!("{reason}");
+                println!("{}", current_instruction(chip8));
+                print_prompt();
+                0
+            }
+            None => 0,
+        }
+    }
+
+    // executes one instruction, returning why the debugger stopped (if it did)
+    fn run_cycle(&mut self, chip8: &mut Chip8) -> Option<StopReason> {
+        let resuming = std::mem::take(&mut self.resuming);
+
+        if !resuming {
+            if let Some(reason) = self.check_breakpoints(chip8) {
+                self.mode = Mode::Paused;
+                return Some(reason);
+            }
+        }
+
+        let reason = self.step_instruction(chip8);
+        if reason.is_some() {
+            self.mode = Mode::Paused;
+            return reason;
+        }
+
+        let new_pc = chip8.pc();
+        let depth = chip8.stack().len();
+        let reached = match self.mode {
+            Mode::RunTo(address) => new_pc == address,
+            Mode::StepOver {
+                return_address,
+                depth: call_depth,
+            } => new_pc == return_address && depth <= call_depth,
+            Mode::StepOut { depth: start_depth } => depth < start_depth,
+            Mode::Running | Mode::Paused => false,
+        };
+        if reached {
+            self.mode = Mode::Paused;
+            return Some(StopReason::Reached(new_pc));
+        }
+
+        None
+    }
+
+    fn check_breakpoints(&self, chip8: &Chip8) -> Option<StopReason> {
+        let pc = chip8.pc();
+        if self.breakpoints.contains(&pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        if let Ok(instruction) = Instruction::try_decode(chip8.get16(pc as usize)) {
+            let class = instruction.kind();
+            if self.opcode_breakpoints.contains(&class) {
+                return Some(StopReason::OpcodeBreakpoint(pc, class));
+            }
+        }
+        None
+    }
+
+    // executes a single instruction while tracking memory for the watchpoints
+    fn step_instruction(&mut self, chip8: &mut Chip8) -> Option<StopReason> {
+        let pc = chip8.pc() as usize;
+        chip8.memory().start_tracking();
+        let result = chip8.cycle();
+        let accesses = chip8.memory().stop_tracking();
+
+        if result == -1 {
+            return Some(StopReason::Halted);
+        }
+
+        for access in accesses {
+            // fetching the instruction (and skipping the next one) isn't interesting
+            let is_fetch = access.address <= Register::PC as usize + 1
+                || (!access.write && (pc..pc + 4).contains(&access.address));
+            if is_fetch {
+                continue;
+            }
+            if let Some(watchpoint) = self
+                .watchpoints
+                .iter()
+                .find(|watchpoint| watchpoint.matches(access.address, access.write))
+            {
+                return Some(StopReason::Watchpoint {
+                    address: access.address,
+                    write: access.write,
+                    name: watchpoint.name.clone(),
+                });
+            }
+        }
+
+        None
+    }
+
+    fn resume(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.resuming = true;
+    }
+
+    pub fn execute_command(&mut self, chip8: &mut Chip8, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help" | "h"] => Ok(HELP.to_owned()),
+            ["s" | "step"] => self.step(chip8, 1),
+            ["s" | "step", count] => match count.parse::<usize>() {
+                Ok(count) => self.step(chip8, count),
+                Err(_) => Err(format!("Invalid count '{count}'")),
+            },
+            ["n" | "next"] => {
+                let pc = chip8.pc();
+                match Instruction::try_decode(chip8.get16(pc as usize)) {
+                    Ok(Instruction::CALL(_)) => {
+                        self.resume(Mode::StepOver {
+                            return_address: pc + 2,
+                            depth: chip8.stack().len(),
+                        });
+                        Ok(String::new())
+                    }
+                    _ => self.step(chip8, 1),
+                }
+            }
+            ["finish"] => {
+                let depth = chip8.stack().len();
+                if depth == 0 {
+                    Err("Not inside a subroutine".to_owned())
+                } else {
+                    self.resume(Mode::StepOut { depth });
+                    Ok(String::new())
+                }
+            }
+            ["back"] => self.reverse_step(chip8, 1),
+            ["back", count] => match count.parse::<u64>() {
+                Ok(count) => self.reverse_step(chip8, count),
+                Err(_) => Err(format!("Invalid count '{count}'")),
+            },
+            ["c" | "continue"] => {
+                self.resume(Mode::Running);
+                Ok(String::new())
+            }
+            ["until", address] => parse_address(address).map(|address| {
+                self.resume(Mode::RunTo(address));
+                String::new()
+            }),
+            ["p" | "pause"] => {
+                self.mode = Mode::Paused;
+                Ok(current_instruction(chip8))
+            }
+            ["b" | "break", target] => match parse_address(target) {
+                Ok(address) => {
+                    self.add_breakpoint(address);
+                    Ok(format!("Breakpoint at {:03X}", address))
+                }
+                Err(_) => {
+                    self.add_opcode_breakpoint(target);
+                    Ok(format!(
+                        "Breakpoint on every {}",
+                        target.to_ascii_uppercase()
+                    ))
+                }
+            },
+            ["d" | "delete", target] => {
+                let removed = match parse_address(target) {
+                    Ok(address) => self.breakpoints.remove(&address),
+                    Err(_) => self.opcode_breakpoints.remove(&target.to_ascii_uppercase()),
+                };
+                if removed {
+                    Ok(format!("Deleted breakpoint {target}"))
+                } else {
+                    Err(format!("No breakpoint {target}"))
+                }
+            }
+            ["watch", location] => self.watch(WatchKind::Write, location),
+            ["watch", kind, location] => {
+                let kind = match *kind {
+                    "r" => Ok(WatchKind::Read),
+                    "w" => Ok(WatchKind::Write),
+                    "rw" => Ok(WatchKind::ReadWrite),
+                    _ => Err(format!("Invalid watch kind '{kind}', expected r, w or rw")),
+                };
+                kind.and_then(|kind| self.watch(kind, location))
+            }
+            ["unwatch", location] => {
+                let count = self.watchpoints.len();
+                self.watchpoints
+                    .retain(|watchpoint| !watchpoint.name.eq_ignore_ascii_case(location));
+                if self.watchpoints.len() < count {
+                    Ok(format!("Deleted watchpoint {location}"))
+                } else {
+                    Err(format!("No watchpoint {location}"))
+                }
+            }
+            ["info"] => Ok(self.info()),
+            ["regs"] => Ok(registers(chip8)),
+            ["x", address] => parse_address(address).map(|address| dump(chip8, address, 16)),
+            ["x", address, length] => match (parse_address(address), length.parse::<usize>()) {
+                (Ok(address), Ok(length)) => Ok(dump(chip8, address, length)),
+                _ => Err(format!("Invalid memory range '{address} {length}'")),
+            },
+            ["l" | "list"] => Ok(list(chip8, 8)),
+            ["l" | "list", count] => match count.parse::<usize>() {
+                Ok(count) => Ok(list(chip8, count)),
+                Err(_) => Err(format!("Invalid count '{count}'")),
+            },
+            ["q" | "quit"] => {
+                self.quit = true;
+                Ok(String::new())
+            }
+            _ => Err(format!(
+                "Unknown command '{line}', type 'help' for a list of commands"
+            )),
+        };
+
+        result.unwrap_or_else(|error| error)
+    }
+
+    fn step(&mut self, chip8: &mut Chip8, count: usize) -> Result<String, String> {
+        self.mode = Mode::Paused;
+        for _ in 0..count {
+            if let Some(reason) = self.step_instruction(chip8) {
+                if reason == StopReason::Halted {
+                    self.quit = true;
+                }
+                return Ok(format!("{reason}\n{}", current_instruction(chip8)));
+            }
+        }
+        Ok(current_instruction(chip8))
+    }
+
+    // restores the nearest snapshot and executes forward to the target cycle
+    fn reverse_step(&mut self, chip8: &mut Chip8, count: u64) -> Result<String, String> {
+        self.mode = Mode::Paused;
+        let target = chip8
+            .cycles()
+            .checked_sub(count)
+            .ok_or("Can't go back past the start of the program")?;
+        chip8.rewind_to(target)?;
+        Ok(current_instruction(chip8))
+    }
+
+    fn watch(&mut self, kind: WatchKind, location: &str) -> Result<String, String> {
+        let (start, end) = parse_location(location)?;
+        self.add_watchpoint(Watchpoint {
+            start,
+            end,
+            kind,
+            name: location.to_owned(),
+        });
+        Ok(format!("Watching {location}"))
+    }
+
+    fn info(&self) -> String {
+        let mut lines = Vec::new();
+        for address in &self.breakpoints {
+            lines.push(format!("break {:03X}", address));
+        }
+        for class in &self.opcode_breakpoints {
+            lines.push(format!("break {class}"));
+        }
+        for watchpoint in &self.watchpoints {
+            lines.push(format!("watch {:?} {}", watchpoint.kind, watchpoint.name));
+        }
+        if lines.is_empty() {
+            lines.push("No breakpoints or watchpoints".to_owned());
+        }
+        lines.join("\n")
+    }
+}
+
+fn print_prompt() {
+    print!("{PROMPT}");
+    io::stdout().flush().unwrap_or(());
+}
+
+fn current_instruction(chip8: &Chip8) -> String {
+    list(chip8, 1)
+}
+
+fn list(chip8: &Chip8, count: usize) -> String {
+    let mut lines = Vec::new();
+    let mut address = chip8.pc() as usize;
+    for _ in 0..count {
+        if address + 1 >= 4096 {
+            break;
+        }
+        let word = chip8.get16(address);
+        let mnemonic = match Instruction::try_decode(word) {
+            Ok(instruction) => instruction.to_string(),
+            Err(_) => "???".to_owned(),
+        };
+        let marker = if address == chip8.pc() as usize {
+            "=>"
+        } else {
+            "  "
+        };
+        lines.push(format!(
+            "{marker} {:03X}: {:04X}  {}",
+            address, word, mnemonic
+        ));
+        address += 2;
+    }
+    lines.join("\n")
+}
+
+fn registers(chip8: &Chip8) -> String {
+    let v_registers: Vec<String> = (0..16)
+        .map(|number| {
+            let register = Register::v_register_from(number);
+            format!("{}={:02X}", register, chip8.get8(register as usize))
+        })
+        .collect();
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    format!(
+        "{}\n{}\nPC={:03X} I={:03X} DT={:02X} SP={}\nstack: [{}]",
+        v_registers[..8].join(" "),
+        v_registers[8..].join(" "),
+        chip8.pc(),
+        chip8.get16(Register::IR as usize),
+        chip8.delay_timer(),
+        chip8.stack().len(),
+        stack.join(", ")
+    )
+}
+
+fn dump(chip8: &Chip8, start: u16, length: usize) -> String {
+    let end = (start as usize + length).min(4096);
+    (start as usize..end)
+        .step_by(16)
+        .map(|row| {
+            let bytes: Vec<String> = (row..(row + 16).min(end))
+                .map(|address| format!("{:02X}", chip8.get8(address)))
+                .collect();
+            format!("{:03X}: {}", row, bytes.join(" "))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewind::RewindBuffer;
+
+    const PROGRAM: &str = r#"
+    200: 6001 // LD V0, 1
+    202: 2300 // CALL 300
+    204: 6203 // LD V2, 3
+    206: A400 // LD I, 400
+    208: D015 // DRW V0, V1, 5
+    20A: 6304 // LD V3, 4
+    300: 6102 // LD V1, 2
+    302: 7101 // ADD V1, 1
+    304: 00EE // RET
+    "#;
+
+    // runs the debugger like the emulator loop would, until it pauses or stops
+    fn run_until_paused(debugger: &mut Debugger, chip8: &mut Chip8) -> i8 {
+        for _ in 0..1000 {
+            if debugger.tick(chip8) == -1 {
+                return -1;
+            }
+            if debugger.is_paused() {
+                return 0;
+            }
+        }
+        panic!("Debugger never paused");
+    }
+
+    #[test]
+    fn test_step() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "step 2");
+        assert_eq!(chip8.pc(), 0x300);
+        assert_eq!(chip8.stack(), &[0x204]);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "break 302");
+        debugger.execute_command(&mut chip8, "continue");
+        run_until_paused(&mut debugger, &mut chip8);
+        assert_eq!(chip8.pc(), 0x302);
+
+        // continuing doesn't stop at the same breakpoint again
+        debugger.execute_command(&mut chip8, "break 20A");
+        debugger.execute_command(&mut chip8, "c");
+        run_until_paused(&mut debugger, &mut chip8);
+        assert_eq!(chip8.pc(), 0x20A);
+    }
+
+    #[test]
+    fn test_opcode_breakpoint() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "break drw");
+        debugger.execute_command(&mut chip8, "continue");
+        run_until_paused(&mut debugger, &mut chip8);
+        assert_eq!(chip8.pc(), 0x208);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "step");
+        debugger.execute_command(&mut chip8, "next");
+        run_until_paused(&mut debugger, &mut chip8);
+        assert_eq!(chip8.pc(), 0x204);
+        assert_eq!(chip8.get8(Register::V1 as usize), 3);
+
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        debugger.execute_command(&mut chip8, "step 3");
+        debugger.execute_command(&mut chip8, "finish");
+        run_until_paused(&mut debugger, &mut chip8);
+        assert_eq!(chip8.pc(), 0x204);
+        assert!(chip8.stack().is_empty());
+    }
+
+    #[test]
+    fn test_run_to_address() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "until 206");
+        run_until_paused(&mut debugger, &mut chip8);
+        assert_eq!(chip8.pc(), 0x206);
+    }
+
+    #[test]
+    fn test_register_watchpoint() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "watch V1");
+        debugger.execute_command(&mut chip8, "continue");
+        run_until_paused(&mut debugger, &mut chip8);
+        // stops after LD V1, 2 has written to V1
+        assert_eq!(chip8.pc(), 0x302);
+    }
+
+    #[test]
+    fn test_memory_read_watchpoint() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "watch r 402-404");
+        debugger.execute_command(&mut chip8, "continue");
+        run_until_paused(&mut debugger, &mut chip8);
+        // stops after the DRW reads the sprite
+        assert_eq!(chip8.pc(), 0x20A);
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "continue");
+        assert_eq!(run_until_paused(&mut debugger, &mut chip8), -1);
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM).setup_rewind(RewindBuffer::new(10, 4));
+        chip8.record_snapshot();
+        let mut debugger = Debugger::new();
+        debugger.execute_command(&mut chip8, "step 4");
+        assert_eq!(chip8.pc(), 0x304);
+        debugger.execute_command(&mut chip8, "back 2");
+        assert_eq!(chip8.pc(), 0x300);
+        assert_eq!(chip8.get8(Register::V1 as usize), 0);
+        assert!(debugger
+            .execute_command(&mut chip8, "back 10")
+            .starts_with("Can't go back"));
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut debugger = Debugger::new();
+        assert!(debugger
+            .execute_command(&mut chip8, "frobnicate")
+            .starts_with("Unknown command"));
+        assert!(debugger
+            .execute_command(&mut chip8, "watch x V0")
+            .starts_with("Invalid watch kind"));
+        assert!(debugger
+            .execute_command(&mut chip8, "finish")
+            .starts_with("Not inside"));
+    }
+}
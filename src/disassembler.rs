@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instruction::Instruction;
+use crate::memory::Memory;
+
+// programs are always loaded at 0x200, which is where the PC starts
+const PROGRAM_START: usize = 0x200;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Label {
+    Jump,       // target of a JP
+    Subroutine, // target of a CALL
+    Sprite,     // target of a LD I that is never executed as code
+}
+
+impl Label {
+    fn name(&self, address: usize) -> String {
+        match self {
+            Label::Jump => format!("L{:03X}", address),
+            Label::Subroutine => format!("SUB{:03X}", address),
+            Label::Sprite => format!("SPR{:03X}", address),
+        }
+    }
+}
+
+struct Disassembler<'a> {
+    memory: &'a Memory,
+    end: usize,
+    code: BTreeSet<usize>, // addresses of reachable instructions
+    labels: BTreeMap<usize, Label>,
+}
+
+// Produces a listing of everything from 0x200 up to the last non-zero byte in memory.
+// Each line is in the same "address: word // comment" format read by Chip8::load_from_text,
+// so the output can be loaded straight back in.
+pub fn disassemble(memory: &Memory) -> String {
+    let mut disassembler = Disassembler::new(memory);
+    disassembler.trace(PROGRAM_START);
+    disassembler.listing()
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(memory: &'a Memory) -> Self {
+        let mut end = PROGRAM_START;
+        for address in PROGRAM_START..4096 {
+            if memory.get8(address) != 0 {
+                end = address + 1;
+            }
+        }
+
+        Self {
+            memory,
+            end,
+            code: BTreeSet::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    fn in_bounds(&self, address: usize) -> bool {
+        (PROGRAM_START..4095).contains(&address)
+    }
+
+    // follow every path from the entry point through jumps, calls and skips,
+    // stopping at anything that doesn't decode (including the 0x0000 halt)
+    fn trace(&mut self, entry: usize) {
+        let mut sprites = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop() {
+            if !self.in_bounds(address) || self.code.contains(&address) {
+                continue;
+            }
+            let instruction = match Instruction::try_decode(self.memory.get16(address)) {
+                Ok(instruction) => instruction,
+                Err(_) => continue,
+            };
+            self.code.insert(address);
+            self.end = self.end.max(address + 2);
+
+            let next = address + 2;
+            match instruction {
+                Instruction::JP(nnn) => {
+                    self.add_label(nnn as usize, Label::Jump);
+                    pending.push(nnn as usize);
+                }
+                Instruction::CALL(nnn) => {
+                    self.add_label(nnn as usize, Label::Subroutine);
+                    pending.push(nnn as usize);
+                    pending.push(next);
+                }
+                Instruction::SEImm(..)
+                | Instruction::SNE(..)
+                | Instruction::SEDir(..)
+                | Instruction::SNEDir(..)
+                | Instruction::SKP(..)
+                | Instruction::SKNP(..) => {
+                    pending.push(next);
+                    pending.push(next + 2);
+                }
+                // the target of these can't be known without running the program
+                Instruction::RET | Instruction::JPOff(_) => {}
+                Instruction::LDI(nnn) => {
+                    sprites.insert(nnn as usize);
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        // anything pointed at by I that turned out to be code keeps its code label
+        for address in sprites {
+            if self.in_bounds(address) && !self.code.contains(&address) {
+                self.labels.entry(address).or_insert(Label::Sprite);
+            }
+        }
+    }
+
+    fn add_label(&mut self, address: usize, label: Label) {
+        if !self.in_bounds(address) {
+            return;
+        }
+        // a subroutine label is more descriptive than a jump label
+        let existing = self.labels.entry(address).or_insert(label);
+        if label == Label::Subroutine {
+            *existing = label;
+        }
+    }
+
+    fn label_name(&self, address: u16) -> Option<String> {
+        self.labels
+            .get(&(address as usize))
+            .map(|label| label.name(address as usize))
+    }
+
+    fn mnemonic(&self, instruction: &Instruction) -> String {
+        let labelled = match instruction {
+            Instruction::JP(nnn) => self.label_name(*nnn).map(|name| format!("JP {name}")),
+            Instruction::CALL(nnn) => self.label_name(*nnn).map(|name| format!("CALL {name}")),
+            Instruction::LDI(nnn) => self.label_name(*nnn).map(|name| format!("LD I, {name}")),
+            _ => None,
+        };
+        labelled.unwrap_or_else(|| instruction.to_string())
+    }
+
+    fn listing(&self) -> String {
+        let mut lines = vec![format!(
+            "// disassembly of {:03X} to {:03X}",
+            PROGRAM_START,
+            self.end.max(PROGRAM_START + 1) - 1
+        )];
+        let mut in_sprite = false;
+        let mut address = PROGRAM_START;
+
+        while address < self.end {
+            if let Some(label) = self.labels.get(&address) {
+                lines.push(String::new());
+                lines.push(format!("// {}:", label.name(address)));
+                in_sprite = *label == Label::Sprite;
+            }
+
+            if self.code.contains(&address) {
+                let word = self.memory.get16(address);
+                let instruction = Instruction::decode(word);
+                lines.push(format!(
+                    "{:03X}: {:04X} // {}",
+                    address,
+                    word,
+                    self.mnemonic(&instruction)
+                ));
+                in_sprite = false;
+                address += 2;
+                continue;
+            }
+
+            let byte = self.memory.get8(address);
+            if in_sprite {
+                lines.push(format!(
+                    "{:03X}: {:02X} // {}",
+                    address,
+                    byte,
+                    sprite_row(byte)
+                ));
+                address += 1;
+                continue;
+            }
+
+            // linear sweep over anything that was never reached, in case it is code
+            // that is only jumped to indirectly (e.g. through JP V0)
+            let next = address + 1;
+            if next < self.end && !self.code.contains(&next) && !self.labels.contains_key(&next) {
+                let word = self.memory.get16(address);
+                if let Ok(instruction) = Instruction::try_decode(word) {
+                    lines.push(format!(
+                        "{:03X}: {:04X} // {} (unreachable)",
+                        address,
+                        word,
+                        self.mnemonic(&instruction)
+                    ));
+                    address += 2;
+                    continue;
+                }
+            }
+
+            lines.push(format!("{:03X}: {:02X} // data", address, byte));
+            address += 1;
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
+
+// draws a sprite byte the way it appears on screen
+fn sprite_row(byte: u8) -> String {
+    (0..8)
+        .map(|bit| {
+            if (byte >> (7 - bit)) & 1 == 1 {
+                '#'
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::Chip8;
+
+    const PROGRAM: &str = r#"
+        200: 600A // LD V0, 10
+        202: 6105 // LD V1, 5
+        204: A300 // LD I, 300
+        206: 2210 // CALL 210
+        208: 4000 // SNE V0, 0
+        20A: 1206 // JP 206
+        20C: 120C // JP 20C
+        20E: 6003
+        210: D015 // DRW V0, V1, 5
+        212: 00EE // RET
+
+        300: FF
+        301: 81
+        302: 81
+        303: 81
+        304: FF
+        "#;
+
+    #[test]
+    fn test_disassemble_labels() {
+        let listing = Chip8::load_from_text(PROGRAM).disassemble();
+        assert!(listing.contains("204: A300 // LD I, SPR300"));
+        assert!(listing.contains("206: 2210 // CALL SUB210"));
+        assert!(listing.contains("20A: 1206 // JP L206"));
+        assert!(listing.contains("// SUB210:\n210: D015 // DRW V0, V1, 5"));
+        assert!(listing.contains("// SPR300:\n300: FF // ########\n301: 81 // #......#"));
+    }
+
+    #[test]
+    fn test_disassemble_unreachable() {
+        let listing = Chip8::load_from_text(PROGRAM).disassemble();
+        assert!(listing.contains("20E: 6003 // LD V0, 0x03 (unreachable)"));
+        // the gap between the code and the sprite is swept as zero words
+        assert!(listing.contains("214: 00 // data"));
+    }
+
+    #[test]
+    fn test_disassemble_round_trip() {
+        let original = Chip8::load_from_text(PROGRAM);
+        let reloaded = Chip8::load_from_text(&original.disassemble());
+        for address in 0x200..0x400 {
+            assert_eq!(original.get8(address), reloaded.get8(address));
+        }
+    }
+}
@@ -1,14 +1,138 @@
-use std::time::Duration;
+#[cfg(feature = "sdl")]
+use sdl2::keyboard::Keycode;
+use std::time::{Duration, Instant};
 
+use crate::capture::Capture;
+use crate::cartridge::Cartridge;
+#[cfg(feature = "sdl")]
+use crate::controller::ButtonMap;
+use crate::debugger::Debugger;
+use crate::disassembler;
+use crate::frontend::{Frontend, InputEvent, Stats};
+use crate::gdb::GdbStub;
+#[cfg(feature = "sdl")]
+use crate::graphics::DisplayOptions;
 use crate::instruction::Instruction;
+use crate::key::Key;
+#[cfg(feature = "sdl")]
+use crate::keymap::Keymap;
 use crate::memory::Memory;
+use crate::movie::{Movie, Recorder};
+use crate::octo;
+use crate::palette::Palette;
+use crate::panels::DebugView;
+use crate::quirks::Quirks;
 use crate::register::Register;
+use crate::rewind::RewindBuffer;
+use crate::savestate;
+use crate::screenshot::{self, Screen};
+#[cfg(feature = "script")]
+use crate::script::Script;
+#[cfg(feature = "sdl")]
 use crate::sdl_context::SdlContext;
+use crate::sha1::sha1_hex;
+use crate::tracer::{TraceEntry, Tracer};
+
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// the sprites for FX29, 5 rows each, where the original interpreter kept them
+const FONT_ADDRESS: usize = 0x50;
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// how fast frames run compared to the real 60 Hz
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Speed {
+    Times(f64),
+    // as fast as the host can go
+    Unthrottled,
+}
+
+impl std::fmt::Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Speed::Times(speed) => write!(f, "{speed}x"),
+            Speed::Unthrottled => write!(f, "max"),
+        }
+    }
+}
+
+impl Speed {
+    pub const NORMAL: Speed = Speed::Times(1.0);
+
+    // None when there's no need to wait between frames
+    fn frame_duration(&self) -> Option<Duration> {
+        match self {
+            Speed::Times(speed) if *speed > 0.0 => Some(FRAME_DURATION.div_f64(*speed)),
+            _ => None,
+        }
+    }
+}
 
 pub struct Chip8 {
     memory: Memory,
-    sdl_context: Option<SdlContext>,
+    frontend: Option<Box<dyn Frontend>>,
     delay_timer: u32,
+    // the beeper sounds while this is above zero
+    sound_timer: u32,
+    stack: Vec<u16>,
+    debugger: Option<Debugger>,
+    gdb: Option<GdbStub>,
+    #[cfg(feature = "script")]
+    script: Option<Script>,
+    tracer: Option<Tracer>,
+    cycles: u64,
+    keys: [bool; 16],
+    rng_state: u32,
+    quirks: Quirks,
+    rewind: Option<RewindBuffer>,
+    rewinding: bool,
+    cycles_per_frame: u32,
+    frames: u64,
+    recorder: Option<Recorder>,
+    capture: Option<Capture>,
+    palette: Palette,
+    // how much bigger than the framebuffer the F12 screenshots and F10 captures are
+    screenshot_scale: usize,
+    // memory as it was loaded, for resetting to
+    power_on: Vec<u8>,
+    // SHA-1 of the program as it was loaded
+    rom_hash: String,
+    paused: bool,
+    speed: Speed,
+    // the speeds the fast forward and slow motion hotkeys switch to
+    fast_forward: Speed,
+    slow_motion: Speed,
+}
+
+// xorshift never leaves zero, but seed_rng makes sure the seed isn't
+#[cfg(not(target_arch = "wasm32"))]
+fn clock_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos())
+}
+
+// there's no clock on wasm32-unknown-unknown, so the page seeds it instead
+#[cfg(target_arch = "wasm32")]
+fn clock_seed() -> u32 {
+    1
 }
 
 impl Chip8 {
@@ -20,22 +144,209 @@ impl Chip8 {
         }
     }
 
+    // load a binary rom (e.g. a .ch8 file), rather than the text format
+    pub fn load_from_rom(filepath: &str) -> Result<Self, &str> {
+        let contents = std::fs::read(filepath);
+        match contents {
+            Ok(data) => Chip8::load_from_bytes(&data),
+            Err(_) => Err("Unable to read file contents"),
+        }
+    }
+
+    // assemble Octo source (e.g. a .8o file) and load the result
+    pub fn load_from_octo(source: &str) -> Result<Self, String> {
+        let image = octo::assemble(source)?;
+        Chip8::load_from_bytes(&image).map_err(str::to_owned)
+    }
+
+    // an Octo cartridge GIF, with the settings it was saved with
+    pub fn load_from_cartridge(cartridge: &Cartridge) -> Result<Self, String> {
+        let mut chip8 = Chip8::load_from_bytes(&cartridge.program)?.setup_quirks(cartridge.quirks);
+        if let Some(tick_rate) = cartridge.tick_rate {
+            chip8 = chip8.setup_tick_rate(tick_rate);
+        }
+        if let Some(palette) = cartridge.palette {
+            chip8 = chip8.setup_palette(palette);
+        }
+        Ok(chip8)
+    }
+
+    // picks the loader from the extension, so anything the command line takes
+    pub fn load_from_path(filepath: &str) -> Result<Self, String> {
+        if filepath.ends_with(".txt") {
+            Chip8::load_from_file(filepath).map_err(str::to_owned)
+        } else if filepath.ends_with(".8o") {
+            std::fs::read_to_string(filepath)
+                .map_err(|_| "Unable to read file contents".to_owned())
+                .and_then(|source| Chip8::load_from_octo(&source))
+        } else if filepath.ends_with(".gif") {
+            Chip8::load_from_cartridge(&Cartridge::load(filepath)?)
+        } else {
+            Chip8::load_from_rom(filepath).map_err(str::to_owned)
+        }
+    }
+
+    pub fn load_from_bytes(data: &[u8]) -> Result<Self, &'static str> {
+        if data.len() > 4096 - 0x200 {
+            return Err("ROM is too large to fit in memory");
+        }
+
+        let mut chip8 = Chip8::load_from_text("");
+        for (offset, byte) in data.iter().enumerate() {
+            chip8.memory.set8(0x200 + offset, *byte);
+        }
+        chip8.power_on = chip8.memory.data().to_vec();
+        chip8.rom_hash = sha1_hex(data);
+
+        Ok(chip8)
+    }
+
     pub fn load_from_text(data: &str) -> Self {
         let mut memory = Memory::new();
         memory.set16(Register::PC as usize, 0x200);
+        for (offset, byte) in FONT.iter().enumerate() {
+            memory.set8(FONT_ADDRESS + offset, *byte);
+        }
 
         let mut chip8 = Chip8 {
             memory,
-            sdl_context: None,
+            frontend: None,
             delay_timer: 0,
+            sound_timer: 0,
+            stack: Vec::new(),
+            debugger: None,
+            gdb: None,
+            #[cfg(feature = "script")]
+            script: None,
+            tracer: None,
+            cycles: 0,
+            keys: [false; 16],
+            rng_state: 1,
+            quirks: Quirks::default(),
+            rewind: None,
+            rewinding: false,
+            cycles_per_frame: 1,
+            frames: 0,
+            recorder: None,
+            capture: None,
+            palette: Palette::default(),
+            screenshot_scale: screenshot::DEFAULT_SCALE,
+            power_on: Vec::new(),
+            rom_hash: String::new(),
+            paused: false,
+            speed: Speed::NORMAL,
+            fast_forward: Speed::Unthrottled,
+            slow_motion: Speed::Times(0.25),
         };
+        chip8.seed_rng(clock_seed());
         chip8.read_data(&data);
+        chip8.power_on = chip8.memory.data().to_vec();
+        // the program is everything from 0x200 up to the last byte the text sets
+        let program = &chip8.power_on[0x200..];
+        let end = program
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |last| last + 1);
+        chip8.rom_hash = sha1_hex(&program[..end]);
 
         chip8
     }
 
-    pub fn setup_sdl(mut self) -> Self {
-        self.sdl_context = Some(SdlContext::new());
+    #[cfg(feature = "sdl")]
+    pub fn setup_sdl(
+        self,
+        display: DisplayOptions,
+        keymap: Keymap<Keycode>,
+        buttons: ButtonMap,
+    ) -> Self {
+        self.setup_frontend(Box::new(SdlContext::new(display, keymap, buttons)))
+    }
+
+    pub fn setup_frontend(mut self, frontend: Box<dyn Frontend>) -> Self {
+        self.frontend = Some(frontend);
+        self
+    }
+
+    pub fn setup_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn setup_rewind(mut self, buffer: RewindBuffer) -> Self {
+        self.rewind = Some(buffer);
+        self
+    }
+
+    pub fn setup_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn setup_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    pub fn setup_screenshot_scale(mut self, scale: usize) -> Self {
+        self.screenshot_scale = scale;
+        self
+    }
+
+    pub fn setup_speeds(mut self, fast_forward: Speed, slow_motion: Speed) -> Self {
+        self.fast_forward = fast_forward;
+        self.slow_motion = slow_motion;
+        self
+    }
+
+    // instructions run each frame
+    pub fn setup_tick_rate(mut self, cycles_per_frame: u32) -> Self {
+        self.cycles_per_frame = cycles_per_frame;
+        self
+    }
+
+    pub fn setup_capture(mut self, capture: Capture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    // for reproducible runs, since the generator is seeded from the clock by default
+    pub fn seed_rng(&mut self, seed: u32) {
+        self.rng_state = seed.max(1);
+    }
+
+    fn next_random(&mut self) -> u8 {
+        // xorshift32
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+        (x >> 24) as u8
+    }
+
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        self.keys[key as usize] = pressed;
+    }
+
+    pub fn setup_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    // starts paused, with a command prompt in the terminal
+    pub fn setup_debugger(mut self) -> Self {
+        self.debugger = Some(Debugger::with_terminal_prompt());
+        self
+    }
+
+    pub fn setup_gdb(mut self, stub: GdbStub) -> Self {
+        self.gdb = Some(stub);
+        self
+    }
+
+    #[cfg(feature = "script")]
+    pub fn setup_script(mut self, script: Script) -> Self {
+        self.script = Some(script);
         self
     }
 
@@ -101,57 +412,433 @@ impl Chip8 {
     }
 
     pub fn cycle(&mut self) -> i8 {
-        let next_instruction = self.fetch();
+        self.try_cycle().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    // same as cycle, but an instruction that can't run is an error instead of a panic,
+    // and is left unfetched so that PC still points at it
+    pub fn try_cycle(&mut self) -> Result<i8, String> {
+        // a script's hook runs before the instruction is fetched, so whatever it changes
+        // (PC included) counts for this step, and sees what the instruction wrote afterwards
+        #[cfg(feature = "script")]
+        let mut script = self.script.take();
+        #[cfg(feature = "script")]
+        let tracked = match script.as_mut() {
+            Some(script) => {
+                script.at_pc(self, self.pc());
+                script.watches_writes().then(|| {
+                    let tracked = self.memory.tracked_count();
+                    if tracked.is_none() {
+                        self.memory.start_tracking();
+                    }
+                    tracked
+                })
+            }
+            None => None,
+        };
+
+        let result = self.fetch_and_execute();
+
+        #[cfg(feature = "script")]
+        if let Some(mut script) = script {
+            // leaving tracking going if the debugger started it
+            let accesses = match tracked {
+                Some(Some(start)) => self.memory.accesses_since(start),
+                Some(None) => self.memory.stop_tracking(),
+                None => Vec::new(),
+            };
+            script.after_accesses(self, &accesses);
+            self.script = Some(script);
+        }
+
+        result
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<i8, String> {
+        let pc = self.pc();
+        if pc as usize > 4096 - 2 {
+            self.dump_trace();
+            return Err(format!("{pc:03X}: PC is past the end of memory"));
+        }
+        let next_instruction = self.memory.peek16(pc as usize);
         if next_instruction == 0 {
-            return -1;
+            self.fetch();
+            self.dump_trace();
+            return Ok(-1);
         }
-        let instruction = Instruction::decode(next_instruction);
-        self.execute(instruction);
+        let instruction = Instruction::try_decode(next_instruction)
+            .and_then(|instruction| self.check_stack(instruction))
+            .map_err(|error| {
+                self.dump_trace();
+                format!("{pc:03X}: {error}")
+            })?;
+        self.fetch();
+        self.cycles += 1;
 
-        0
+        match self.tracer.take() {
+            Some(mut tracer) => {
+                let kind = instruction.kind();
+                if tracer.wants(pc, &kind) {
+                    let before = self.v_registers();
+                    let description = instruction.to_string();
+                    self.execute(instruction);
+                    tracer.record(&self.trace_entry(
+                        pc,
+                        next_instruction,
+                        kind,
+                        description,
+                        before,
+                    ));
+                } else {
+                    self.execute(instruction);
+                }
+                self.tracer = Some(tracer);
+            }
+            None => self.execute(instruction),
+        }
+
+        Ok(0)
+    }
+
+    // the instruction, unless running it would take the stack past either end
+    fn check_stack(&self, instruction: Instruction) -> Result<Instruction, String> {
+        match instruction {
+            Instruction::RET if self.stack.is_empty() => Err("Stack underflow".to_owned()),
+            Instruction::CALL(_) if self.stack.len() >= 16 => Err("Stack overflow".to_owned()),
+            _ => Ok(instruction),
+        }
+    }
+
+    fn v_registers(&self) -> [u8; 16] {
+        let mut values = [0; 16];
+        for (number, value) in values.iter_mut().enumerate() {
+            *value = self
+                .memory
+                .peek8(Register::v_register_from(number as u8) as usize);
+        }
+        values
+    }
+
+    fn trace_entry(
+        &self,
+        pc: u16,
+        opcode: u16,
+        kind: String,
+        instruction: String,
+        before: [u8; 16],
+    ) -> TraceEntry {
+        let after = self.v_registers();
+        let changed_registers = (0..16)
+            .filter(|number| before[*number] != after[*number])
+            .map(|number| (number as u8, after[number]))
+            .collect();
+        TraceEntry {
+            cycle: self.cycles,
+            pc,
+            opcode,
+            kind,
+            instruction,
+            changed_registers,
+            index_register: self.memory.peek16(Register::IR as usize),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            stack_depth: self.stack.len(),
+        }
+    }
+
+    // the instructions that led up to the program stopping, when only the last few are kept
+    fn dump_trace(&mut self) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.dump_last();
+        }
     }
 
     pub fn run(&mut self) {
-        let timer_frequency: f32 = 1.0 / 60.0;
-        let mut elapsed_time: f32 = 0.0;
+        let mut next_frame = Instant::now();
+        let mut last_render: Option<Instant> = None;
+        // when the stats were last sent, and the frame and cycle counts then
+        let mut last_stats = (Instant::now(), self.frames, self.cycles);
+        loop {
+            let view = self
+                .frontend
+                .as_ref()
+                .is_some_and(|frontend| frontend.shows_debug_view())
+                .then(|| self.debug_view());
+            let frontend = self.frontend.as_mut().expect("Frontend not initialised");
+            if let Some(view) = &view {
+                frontend.show_debug(view);
+            }
+            // fast forward doesn't need drawing any more often than normal
+            if last_render.is_none_or(|time| time.elapsed() >= FRAME_DURATION) {
+                frontend.render_graphics(&self.memory, &self.palette);
+                last_render = Some(Instant::now());
+            }
+            match frontend.handle_input() {
+                Err("QUIT") => return,
+                Ok(events) => {
+                    for event in events {
+                        self.handle_event(event);
+                    }
+                }
+                _ => {}
+            }
 
-        'fde: loop {
-            let delta_time = self.sdl_context.as_mut().unwrap().get_delta_time();
-            /* println!("Delta time: {delta_time}s"); */
+            if !self.paused && self.run_frame() == -1 {
+                return;
+            }
 
-            elapsed_time += delta_time;
+            let (time, frames, cycles) = last_stats;
+            let elapsed = time.elapsed().as_secs_f64();
+            if elapsed >= 1.0 {
+                let stats = Stats {
+                    frames_per_second: self.frames.saturating_sub(frames) as f64 / elapsed,
+                    instructions_per_second: (self.cycles.saturating_sub(cycles) as f64 / elapsed)
+                        as u64,
+                    quirks: self.quirks,
+                };
+                if let Some(frontend) = self.frontend.as_mut() {
+                    frontend.show_stats(&stats);
+                }
+                last_stats = (Instant::now(), self.frames, self.cycles);
+            }
 
-            if elapsed_time >= timer_frequency {
-                if self.delay_timer > 0 {
-                    println!("Delay timer: {}", self.delay_timer);
-                    self.delay_timer -= 1;
+            // keep to the speed on average, without trying to catch up after falling behind
+            let speed = if self.paused {
+                Speed::NORMAL
+            } else {
+                self.speed
+            };
+            match speed.frame_duration() {
+                Some(duration) => {
+                    next_frame += duration;
+                    let now = Instant::now();
+                    if next_frame > now {
+                        std::thread::sleep(next_frame - now);
+                    } else {
+                        next_frame = now;
+                    }
                 }
+                None => next_frame = Instant::now(),
+            }
+        }
+    }
+
+    // One 60 Hz frame: the timers tick, then cycles_per_frame instructions run.
+    // Counting time in frames rather than with the wall clock is what lets a movie
+    // play back the same way every time.
+    pub fn run_frame(&mut self) -> i8 {
+        self.try_run_frame()
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    // same as run_frame, but an instruction that can't run ends the frame early with an
+    // error instead of panicking
+    pub fn try_run_frame(&mut self) -> Result<i8, String> {
+        // a debugger that has stopped the program still handles its commands, but time
+        // stands still: no timers, no frame and none of the per-frame hooks
+        let stopped = self.debugger.as_ref().is_some_and(Debugger::is_paused)
+            || self.gdb.as_ref().is_some_and(GdbStub::is_stopped);
+        if stopped {
+            return self.step();
+        }
+
+        // one snapshot per frame, unless we're playing them back
+        if self.rewinding {
+            self.rewind_frame();
+            return Ok(0);
+        }
+        self.tick_timers();
+        self.record_snapshot();
+
+        let mut result = 0;
+        for _ in 0..self.cycles_per_frame {
+            result = self.step()?;
+            if result == -1 {
+                break;
+            }
+        }
+
+        self.frames += 1;
+        #[cfg(feature = "script")]
+        if let Some(mut script) = self.script.take() {
+            script.end_frame(self, self.frames);
+            self.script = Some(script);
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.set_frames(self.frames);
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(error) = capture.add_frame(self.memory.framebuffer()) {
+                self.notify(&format!("{error}, stopping the capture"));
+                self.capture = None;
+            }
+        }
+        Ok(result)
+    }
+
+    // the start of a frame, for running one instruction at a time without run_frame
+    pub fn tick_timers(&mut self) {
+        if let Some(buffer) = self.rewind.as_mut() {
+            buffer.tick(self.cycles);
+        }
+        self.count_down_timers();
+    }
+
+    fn count_down_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    pub fn cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
 
-                elapsed_time -= timer_frequency;
-            }
-
-            self.sdl_context
-                .as_mut()
-                .expect("SDL context not initialised")
-                .render_graphics(&self.memory);
-            match self
-                .sdl_context
-                .as_mut()
-                .expect("SDL context not initialised")
-                .handle_input()
-            {
-                Err("QUIT") => {
-                    panic!("Quitting");
+    fn handle_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Key(key, pressed) => {
+                // key repeats aren't changes, so they're left out of movies
+                if let Some(recorder) = self.recorder.as_mut() {
+                    if self.keys[key as usize] != pressed {
+                        recorder.key(self.frames, key, pressed);
+                    }
                 }
-                _ => {}
+                self.set_key(key, pressed);
+            }
+            // going back in time would make the movie impossible to play back
+            InputEvent::Rewind(held) => {
+                self.rewinding = held && self.rewind.is_some() && self.recorder.is_none()
+            }
+            InputEvent::LoadState(_) if self.recorder.is_some() => {
+                self.notify("Save states can't be loaded while recording a movie");
+            }
+            InputEvent::Reset if self.recorder.is_some() => {
+                self.notify("Can't reset while recording a movie");
+            }
+            // the window's controls work the debugger when it's stopped
+            InputEvent::Pause if self.debugger.as_ref().is_some_and(Debugger::is_paused) => {
+                self.debugger_command("continue")
+            }
+            InputEvent::FrameAdvance if self.debugger.as_ref().is_some_and(Debugger::is_paused) => {
+                self.debugger_command("step")
+            }
+            InputEvent::ToggleBreakpoint(address) => {
+                let debugger = self.debugger.get_or_insert_with(Debugger::running);
+                let message = if debugger.toggle_breakpoint(address) {
+                    format!("Breakpoint at {address:03X}")
+                } else {
+                    format!("Deleted breakpoint {address:03X}")
+                };
+                self.notify(&message);
+            }
+            InputEvent::Pause => {
+                self.set_paused(!self.paused);
+                self.notify(if self.paused { "Paused" } else { "Resumed" });
+            }
+            // pausing first, if it's running
+            InputEvent::FrameAdvance => {
+                if self.paused {
+                    self.run_frame();
+                } else {
+                    self.set_paused(true);
+                }
+            }
+            InputEvent::FastForward => {
+                self.toggle_speed(self.fast_forward);
+                self.notify(&format!("Speed {}", self.speed));
+            }
+            InputEvent::SlowMotion => {
+                self.toggle_speed(self.slow_motion);
+                self.notify(&format!("Speed {}", self.speed));
             }
+            InputEvent::Reset => self.reset(),
+            InputEvent::NextPalette => self.palette = self.palette.next(),
+            InputEvent::Screenshot => {
+                let filepath = format!("screenshot{}.png", self.frames);
+                match self.save_screenshot(&filepath, self.screenshot_scale, &self.palette) {
+                    Ok(()) => self.notify(&format!("Screenshot saved to {filepath}")),
+                    Err(error) => self.notify(&error),
+                }
+            }
+            // dropping the capture finishes the file
+            InputEvent::ToggleCapture => match self.capture.take() {
+                Some(_) => self.notify("Capture stopped"),
+                None => {
+                    let filepath = format!("capture{}.gif", self.frames);
+                    match Capture::to_gif(&filepath, self.screenshot_scale, self.palette) {
+                        Ok(capture) => {
+                            self.notify(&format!("Capturing to {filepath}"));
+                            self.capture = Some(capture);
+                        }
+                        Err(error) => self.notify(&error),
+                    }
+                }
+            },
+            InputEvent::SaveState(slot) => {
+                let filepath = savestate::slot_path(slot);
+                match std::fs::write(&filepath, self.save_state()) {
+                    Ok(()) => self.notify(&format!("State saved to slot {slot}")),
+                    Err(error) => self.notify(&format!("Unable to save {filepath}: {error}")),
+                }
+            }
+            InputEvent::LoadState(slot) => {
+                let filepath = savestate::slot_path(slot);
+                let result = std::fs::read(&filepath)
+                    .map_err(|error| error.to_string())
+                    .and_then(|data| self.load_state(&data));
+                match result {
+                    Ok(()) => self.notify(&format!("State loaded from slot {slot}")),
+                    Err(error) => self.notify(&format!("Unable to load {filepath}: {error}")),
+                }
+            }
+        }
+    }
 
-            if self.cycle() == -1 {
-                break 'fde;
+    // a command for the debugger from the window rather than its prompt
+    fn debugger_command(&mut self, line: &str) {
+        if let Some(mut debugger) = self.debugger.take() {
+            let output = debugger.execute_command(self, line);
+            self.debugger = Some(debugger);
+            if !output.is_empty() {
+                self.notify(&output);
             }
+        }
+    }
 
-            // sleep for 1/60th of a second
-            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    // shown by the frontend, or printed if there isn't one
+    pub fn notify(&mut self, message: &str) {
+        match self.frontend.as_mut() {
+            Some(frontend) => frontend.show_message(message),
+            None => println!("{message}"),
+        }
+    }
+
+    // text drawn over the screen until the next call, for scripts
+    pub fn show_overlay(&mut self, lines: &[String]) {
+        if let Some(frontend) = self.frontend.as_mut() {
+            frontend.show_overlay(lines);
+        }
+    }
+
+    // with a debugger or GDB attached, it decides whether to execute the next instruction
+    fn step(&mut self) -> Result<i8, String> {
+        match self.debugger.take() {
+            Some(mut debugger) => {
+                let result = debugger.tick(self);
+                self.debugger = Some(debugger);
+                Ok(result)
+            }
+            None => match self.gdb.take() {
+                Some(mut gdb) => {
+                    let result = gdb.tick(self);
+                    self.gdb = Some(gdb);
+                    Ok(result)
+                }
+                None => self.try_cycle(),
+            },
         }
     }
 
@@ -182,14 +869,28 @@ impl Chip8 {
             Instruction::CLS => {
                 self.memory.clear_framebuffer();
             }
+            Instruction::RET => {
+                let return_address = self.stack.pop().expect("Stack underflow");
+                self.memory.set16(Register::PC as usize, return_address);
+            }
             Instruction::JP(nnn) => {
                 self.memory.set16(Register::PC as usize, nnn);
             }
+            Instruction::CALL(nnn) => {
+                // the original interpreter only had room for 16 return addresses
+                if self.stack.len() >= 16 {
+                    panic!("Stack overflow");
+                }
+                // PC has already been moved past the CALL by fetch
+                self.stack.push(self.memory.get16(Register::PC as usize));
+                self.memory.set16(Register::PC as usize, nnn);
+            }
             Instruction::LDImm(vx, value) => {
                 self.memory.set8(vx as usize, value);
             }
             Instruction::ADDImm(vx, value) => {
-                let new_value = self.memory.get8(vx.clone() as usize) + value;
+                // wraps around, and unlike 8XY4 leaves VF alone
+                let new_value = self.memory.get8(vx as usize).wrapping_add(value);
                 self.memory.set8(vx as usize, new_value);
             }
             Instruction::LDDir(vx, vy) => {
@@ -199,21 +900,28 @@ impl Chip8 {
                 let vx_value = self.memory.get8(vx.clone() as usize);
                 let vy_value = self.memory.get8(vy as usize);
                 self.memory.set8(vx as usize, vx_value | vy_value);
+                self.logic_vf_reset();
             }
             Instruction::AND(vx, vy) => {
                 let vx_value = self.memory.get8(vx.clone() as usize);
                 let vy_value = self.memory.get8(vy as usize);
                 self.memory.set8(vx as usize, vx_value & vy_value);
+                self.logic_vf_reset();
             }
             Instruction::XOR(vx, vy) => {
                 let vx_value = self.memory.get8(vx.clone() as usize);
                 let vy_value = self.memory.get8(vy as usize);
                 self.memory.set8(vx as usize, vx_value ^ vy_value);
+                self.logic_vf_reset();
             }
             Instruction::ADDDir(vx, vy) => {
                 let vx_value = self.memory.get8(vx as usize);
                 let vy_value = self.memory.get8(vy as usize);
-                self.memory.set8(vx as usize, vx_value + vy_value);
+                let (sum, carry) = vx_value.overflowing_add(vy_value);
+                self.memory.set8(vx as usize, sum);
+                // VF last, so it holds the carry even when it's Vx
+                self.memory
+                    .set8(Register::v_register_from(0xF) as usize, carry as u8);
             }
             Instruction::SUB(vx, vy) => {
                 let vx_value = self.memory.get8(vx as usize);
@@ -259,16 +967,42 @@ impl Chip8 {
                 self.memory.set16(Register::IR as usize, location);
             }
             Instruction::JPOff(offset) => {
-                let v0_value = self.memory.get8(Register::v_register_from(0) as usize) as u16;
-                self.memory.set16(Register::PC as usize, v0_value + offset);
+                // with the quirk, BXNN adds VX where X is the top nibble of the address
+                let register = if self.quirks.jump_uses_vx {
+                    Register::v_register_from((offset >> 8) as u8)
+                } else {
+                    Register::v_register_from(0)
+                };
+                let v_value = self.memory.get8(register as usize) as u16;
+                self.memory.set16(Register::PC as usize, v_value + offset);
+            }
+            Instruction::RND(vx, nn) => {
+                let random = self.next_random();
+                self.memory.set8(vx as usize, random & nn);
             }
             Instruction::DRW(vx, vy, height) => {
                 self.draw_update(Instruction::DRW(vx, vy, height));
             }
-            Instruction::LDK(vx) => {
-                println!("Waiting for keypress...");
-                self.sdl_context.as_mut().unwrap().wait_for_keypress();
-                /* self.memory.set8(vx as usize, key as u8); */
+            Instruction::LDK(vx) => match self.keys.iter().position(|pressed| *pressed) {
+                Some(key) => self.memory.set8(vx as usize, key as u8),
+                None => {
+                    // wait by running this instruction again until a key is down
+                    let pc = self.memory.get16(Register::PC as usize);
+                    self.memory.set16(Register::PC as usize, pc - 2);
+                    if let Some(frontend) = self.frontend.as_mut() {
+                        frontend.wait_for_keypress();
+                    }
+                }
+            },
+            Instruction::SKP(vx) => {
+                if self.keys[self.memory.get8(vx as usize) as usize & 0xF] {
+                    self.fetch();
+                }
+            }
+            Instruction::SKNP(vx) => {
+                if !self.keys[self.memory.get8(vx as usize) as usize & 0xF] {
+                    self.fetch();
+                }
             }
             Instruction::SEImm(vx, nn) => {
                 if self.memory.get8(vx as usize) == nn {
@@ -281,6 +1015,11 @@ impl Chip8 {
                 }
             }
             Instruction::SEDir(vx, vy) => {
+                if self.memory.get8(vx as usize) == self.memory.get8(vy as usize) {
+                    self.fetch();
+                }
+            }
+            Instruction::SNEDir(vx, vy) => {
                 if self.memory.get8(vx as usize) != self.memory.get8(vy as usize) {
                     self.fetch();
                 }
@@ -294,6 +1033,56 @@ impl Chip8 {
                 // set the value of vx to the delay timer
                 self.memory.set8(vx as usize, self.delay_timer as u8);
             }
+            Instruction::LDST(vx) => {
+                self.sound_timer = self.memory.get8(vx as usize) as u32;
+            }
+            Instruction::ADDI(vx) => {
+                let index = self.memory.get16(Register::IR as usize);
+                let vx_value = self.memory.get8(vx as usize) as u16;
+                self.memory
+                    .set16(Register::IR as usize, index.wrapping_add(vx_value) & 0xFFF);
+            }
+            Instruction::LDF(vx) => {
+                let digit = (self.memory.get8(vx as usize) & 0xF) as usize;
+                self.memory
+                    .set16(Register::IR as usize, (FONT_ADDRESS + digit * 5) as u16);
+            }
+            Instruction::LDB(vx) => {
+                let vx_value = self.memory.get8(vx as usize);
+                let index = self.memory.get16(Register::IR as usize) as usize;
+                for (offset, digit) in [vx_value / 100, vx_value / 10 % 10, vx_value % 10]
+                    .into_iter()
+                    .enumerate()
+                {
+                    self.memory.set8((index + offset) % 4096, digit);
+                }
+            }
+            // like the original interpreter (and Octo by default), I ends up past the
+            // last register stored or loaded
+            Instruction::LDMem(vx) | Instruction::LDReg(vx) => {
+                let store = matches!(instruction, Instruction::LDMem(_));
+                let index = self.memory.get16(Register::IR as usize) as usize;
+                let count = vx as usize - Register::V0 as usize + 1;
+                for number in 0..count {
+                    let register = Register::v_register_from(number as u8) as usize;
+                    let address = (index + number) % 4096;
+                    if store {
+                        let value = self.memory.get8(register);
+                        self.memory.set8(address, value);
+                    } else {
+                        let value = self.memory.get8(address);
+                        self.memory.set8(register, value);
+                    }
+                }
+                self.memory
+                    .set16(Register::IR as usize, ((index + count) % 4096) as u16);
+            }
+        }
+    }
+
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.memory.set8(Register::v_register_from(0xF) as usize, 0);
         }
     }
 
@@ -306,6 +1095,12 @@ impl Chip8 {
                 for i in 0..height {
                     let new_byte_data = self.memory.get8((index_location as usize) + (i as usize));
                     for j in 0..8 {
+                        // pixels past the edge are either dropped or wrapped around
+                        let clipped = (x_position % 64) as u16 + j as u16 >= 64
+                            || (y_position % 32) as u16 + i as u16 >= 32;
+                        if self.quirks.clip_sprites && clipped {
+                            continue;
+                        }
                         let x_position_wrapped = x_position.wrapping_add(j) % 64;
                         let y_position_wrapped = y_position.wrapping_add(i) % 32;
                         let old_bit_data = self
@@ -332,7 +1127,238 @@ impl Chip8 {
         }
     }
 
+    pub fn pc(&self) -> u16 {
+        self.memory.peek16(Register::PC as usize)
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u32 {
+        self.delay_timer
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn set_delay_timer(&mut self, value: u32) {
+        self.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u32 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u32) {
+        self.sound_timer = value;
+    }
+
+    // return addresses can be dropped, but there's nothing to make up new ones from
+    pub fn set_stack_depth(&mut self, depth: usize) -> Result<(), String> {
+        if depth > self.stack.len() {
+            return Err(format!("The stack is only {} deep", self.stack.len()));
+        }
+        self.stack.truncate(depth);
+        Ok(())
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        let mut v = [0; 16];
+        for (number, value) in v.iter_mut().enumerate() {
+            *value = self
+                .memory
+                .peek8(Register::v_register_from(number as u8) as usize);
+        }
+        DebugView {
+            pc: self.pc(),
+            i: self.memory.peek16(Register::IR as usize),
+            v,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            memory: self.memory.data().to_vec(),
+            breakpoints: self
+                .debugger
+                .as_ref()
+                .map_or(Vec::new(), Debugger::breakpoints),
+        }
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(&savestate::MachineState {
+            memory: self.memory.data().to_vec(),
+            framebuffer: self.memory.framebuffer().to_vec(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            stack: self.stack.clone(),
+            keys: self.keys,
+            rng_state: self.rng_state,
+            quirks: self.quirks,
+            cycles: self.cycles,
+        })
+    }
+
+    pub fn record_snapshot(&mut self) {
+        let cycles = self.cycles;
+        let needs_snapshot = match &self.rewind {
+            Some(buffer) => buffer.last_cycle() != Some(cycles),
+            None => false,
+        };
+        if needs_snapshot {
+            let state = self.save_state();
+            if let Some(buffer) = self.rewind.as_mut() {
+                buffer.push(cycles, state);
+            }
+        }
+    }
+
+    // steps back to the previous snapshot, returning false once there are none left
+    pub fn rewind_frame(&mut self) -> bool {
+        let snapshot = self.rewind.as_mut().and_then(|buffer| buffer.pop());
+        match snapshot {
+            Some((_, state)) => self.load_state(&state).is_ok(),
+            None => false,
+        }
+    }
+
+    // Goes back to an earlier cycle by restoring the nearest snapshot before it and
+    // executing forward from there, ticking the timers where they ticked the first time.
+    pub fn rewind_to(&mut self, cycle: u64) -> Result<(), String> {
+        let buffer = self.rewind.as_mut().ok_or("Rewind is not enabled")?;
+        let (_, state, ticks) = buffer.nearest(cycle).ok_or("No snapshot that far back")?;
+        buffer.truncate_after(cycle);
+        self.load_state(&state)?;
+        let mut ticks = ticks.into_iter().peekable();
+        loop {
+            // the buffer still has these ticks, so they aren't recorded again
+            while ticks.next_if(|tick| *tick <= self.cycles).is_some() {
+                self.count_down_timers();
+            }
+            if self.cycles >= cycle || self.cycle() == -1 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    // to the given speed, or back to normal if it's already at it
+    fn toggle_speed(&mut self, speed: Speed) {
+        self.speed = if self.speed == speed {
+            Speed::NORMAL
+        } else {
+            speed
+        };
+    }
+
+    // back to how things were just after loading the ROM. The cycle count keeps going
+    // up, so rewinding can still go back to before the reset.
+    pub fn reset(&mut self) {
+        let framebuffer = vec![0; self.memory.framebuffer().len()];
+        self.memory.restore(&self.power_on, &framebuffer);
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.stack.clear();
+        self.keys = [false; 16];
+    }
+
+    pub fn screen(&self) -> Screen<'_> {
+        Screen {
+            framebuffer: self.memory.framebuffer(),
+            width: 64,
+        }
+    }
+
+    // PNG, or PPM if the file name ends in .ppm
+    pub fn save_screenshot(
+        &self,
+        filepath: &str,
+        scale: usize,
+        palette: &Palette,
+    ) -> Result<(), String> {
+        screenshot::save(filepath, &self.screen(), scale, palette)
+    }
+
+    // identifies the program for movies, the ROM database and "rom.<sha1>." options: for a
+    // binary ROM the SHA-1 of the file, as sha1sum prints it, and for Octo source or a
+    // cartridge that of the program it holds
+    pub fn rom_hash(&self) -> String {
+        self.rom_hash.clone()
+    }
+
+    // the header of a movie that starts from the current state
+    pub fn new_movie(&self) -> Movie {
+        Movie {
+            rom_hash: self.rom_hash(),
+            quirks: self.quirks,
+            seed: self.rng_state,
+            cycles_per_frame: self.cycles_per_frame,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    // runs a movie from power-on, headless
+    pub fn play_movie(&mut self, movie: &Movie) -> Result<(), String> {
+        if self.rom_hash() != movie.rom_hash {
+            return Err("Movie was recorded with a different ROM".to_owned());
+        }
+        self.quirks = movie.quirks;
+        self.seed_rng(movie.seed);
+        self.cycles_per_frame = movie.cycles_per_frame;
+
+        let mut events = movie.events.iter().peekable();
+        for frame in 0..movie.frames {
+            while let Some(event) = events.next_if(|event| event.frame == frame) {
+                self.set_key(event.key, event.pressed);
+            }
+            if self.run_frame() == -1 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // on error the machine is left untouched
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let state = savestate::load(data)?;
+        self.memory.restore(&state.memory, &state.framebuffer);
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.stack = state.stack;
+        self.keys = state.keys;
+        self.rng_state = state.rng_state;
+        self.quirks = state.quirks;
+        self.cycles = state.cycles;
+        Ok(())
+    }
+
     // helper functions for testing
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
     pub fn get16(&self, index: usize) -> u16 {
         self.memory.get16(index)
     }
@@ -340,6 +1366,18 @@ impl Chip8 {
     pub fn get8(&self, index: usize) -> u8 {
         self.memory.get8(index)
     }
+
+    pub fn set16(&mut self, index: usize, value: u16) {
+        self.memory.set16(index, value)
+    }
+
+    pub fn set8(&mut self, index: usize, value: u8) {
+        self.memory.set8(index, value)
+    }
+
+    pub fn disassemble(&self) -> String {
+        disassembler::disassemble(&self.memory)
+    }
 }
 
 #[cfg(test)]
@@ -544,4 +1582,492 @@ mod tests {
         chip8.test_run();
         assert_eq!(chip8.get16(Register::IR as usize), 0x300)
     }
+
+    #[test]
+    fn test_load_from_bytes() {
+        let chip8 = Chip8::load_from_bytes(&[0xA2, 0x02, 0x60, 0x00]).unwrap();
+        assert_eq!(chip8.get16(0x200), 0xA202);
+        assert_eq!(chip8.get16(0x202), 0x6000);
+        assert_eq!(chip8.get16(Register::PC as usize), 0x200);
+        assert!(Chip8::load_from_bytes(&[0; 4096]).is_err());
+    }
+
+    #[test]
+    fn test_execute_call_ret() {
+        let code = r#"
+    200: 2300 // CALL 300
+    202: 6102 // LD V1, 2
+    300: 6001 // LD V0, 1
+    302: 00EE // RET
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.test_run();
+        assert_eq!(chip8.get8(Register::v_register_from(0) as usize), 1);
+        assert_eq!(chip8.get8(Register::v_register_from(1) as usize), 2);
+        assert!(chip8.stack.is_empty());
+    }
+
+    #[test]
+    fn test_load_from_octo() {
+        let source = r#"
+    : main
+        v0 := 1
+        v0 += 2
+    "#;
+        let mut chip8 = Chip8::load_from_octo(source).unwrap();
+        chip8.test_run();
+        assert_eq!(chip8.get8(Register::v_register_from(0) as usize), 3)
+    }
+
+    #[test]
+    fn test_load_from_cartridge() {
+        let cartridge = Cartridge {
+            program: vec![0x60, 0x07],
+            tick_rate: Some(10),
+            quirks: Quirks::parse("clip").unwrap(),
+            palette: Palette::named("lcd"),
+        };
+        let cartridge = Cartridge::decode(&cartridge.encode("test")).unwrap();
+        let mut chip8 = Chip8::load_from_cartridge(&cartridge).unwrap();
+        assert!(chip8.quirks.clip_sprites && !chip8.quirks.jump_uses_vx);
+        assert_eq!(chip8.cycles_per_frame, 10);
+        assert_eq!(chip8.palette.background, [0x9B, 0xBC, 0x0F]);
+        chip8.test_run();
+        assert_eq!(chip8.get8(Register::v_register_from(0) as usize), 7);
+    }
+
+    #[test]
+    fn test_octo_constructs_run() {
+        // everything the assembler emits has to run, including what has no
+        // instruction of its own in the text format
+        let source = r#"
+    : main
+        v0 := 7
+        v1 := 7
+        v2 := 0
+        if v0 == v1 then v2 += 1
+        if v0 != v1 then v2 += 16
+        buzzer := v0
+        i := 0x300
+        v3 := 4
+        i += v3
+        v4 := 234
+        bcd v4
+        i := 0x310
+        save v2
+        i := 0x310
+        load v1
+        i := hex v0
+    "#;
+        let mut chip8 = Chip8::load_from_octo(source).unwrap();
+        while chip8.cycle() != -1 {}
+        let v = |number| chip8.get8(Register::v_register_from(number) as usize);
+        assert_eq!(v(2), 1);
+        assert_eq!(chip8.sound_timer(), 7);
+        assert_eq!(
+            [chip8.get8(0x304), chip8.get8(0x305), chip8.get8(0x306)],
+            [2, 3, 4]
+        );
+        // load v1 read back V0 and V1, leaving I just past them
+        assert_eq!(
+            [chip8.get8(0x310), chip8.get8(0x311), chip8.get8(0x312)],
+            [7, 7, 1]
+        );
+        assert_eq!((v(0), v(1)), (7, 7));
+        // i := hex v0 points at the 5 rows of the 7
+        assert_eq!(chip8.get16(Register::IR as usize), 0x50 + 7 * 5);
+        assert_eq!(chip8.get8(0x50 + 7 * 5), 0xF0);
+
+        chip8.tick_timers();
+        assert_eq!(chip8.sound_timer(), 6);
+    }
+
+    #[test]
+    fn test_paused_debugger_stops_time() {
+        let mut chip8 = Chip8::load_from_text(
+            r#"
+    200: 1200 // JP 200
+    "#,
+        );
+        chip8.set_delay_timer(10);
+        chip8.debugger = Some(Debugger::new());
+        for _ in 0..5 {
+            chip8.run_frame();
+        }
+        assert_eq!((chip8.delay_timer(), chip8.frames), (10, 0));
+
+        chip8.debugger = Some(Debugger::running());
+        chip8.run_frame();
+        assert_eq!((chip8.delay_timer(), chip8.frames), (9, 1));
+    }
+
+    #[test]
+    fn test_octo_arithmetic_wraps() {
+        // -= is 7XNN with the negated constant, which has to wrap without touching VF
+        let source = r#"
+    : main
+        vf := 9
+        v0 := 5
+        v0 -= 1
+        v1 := 0
+        v1 -= 1
+        v2 := 200
+        v3 := 100
+        v2 += v3
+        v4 := 1
+        v5 := 2
+        v4 += v5
+    "#;
+        let mut chip8 = Chip8::load_from_octo(source).unwrap();
+        let v = |chip8: &Chip8, number| chip8.get8(Register::v_register_from(number) as usize);
+        for _ in 0..5 {
+            chip8.cycle();
+        }
+        assert_eq!((v(&chip8, 0), v(&chip8, 1), v(&chip8, 0xF)), (4, 255, 9));
+        // 8XY4 wraps too, with the carry in VF
+        for _ in 0..3 {
+            chip8.cycle();
+        }
+        assert_eq!((v(&chip8, 2), v(&chip8, 0xF)), (44, 1));
+        while chip8.cycle() != -1 {}
+        assert_eq!((v(&chip8, 4), v(&chip8, 0xF)), (3, 0));
+    }
+
+    #[test]
+    fn test_execute_rnd() {
+        let code = r#"
+    200: C00F // RND V0, 0x0F
+    202: C100 // RND V1, 0
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.seed_rng(1234);
+        chip8.test_run();
+        assert!(chip8.get8(Register::v_register_from(0) as usize) <= 0x0F);
+        assert_eq!(chip8.get8(Register::v_register_from(1) as usize), 0);
+
+        // the same seed gives the same numbers
+        let mut first = Chip8::load_from_text("200: C0FF");
+        let mut second = Chip8::load_from_text("200: C0FF");
+        first.seed_rng(99);
+        second.seed_rng(99);
+        first.cycle();
+        second.cycle();
+        assert_eq!(
+            first.get8(Register::V0 as usize),
+            second.get8(Register::V0 as usize)
+        );
+    }
+
+    #[test]
+    fn test_quirk_logic_resets_vf() {
+        let code = r#"
+    200: 6F05
+    202: 8011 // OR V0, V1
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.test_run();
+        assert_eq!(chip8.get8(Register::VF as usize), 5);
+
+        let mut chip8 = Chip8::load_from_text(code).setup_quirks(Quirks {
+            logic_resets_vf: true,
+            ..Quirks::default()
+        });
+        chip8.test_run();
+        assert_eq!(chip8.get8(Register::VF as usize), 0);
+    }
+
+    #[test]
+    fn test_quirk_jump_uses_vx() {
+        let code = r#"
+    200: 6002 // LD V0, 2
+    202: 6304 // LD V3, 4
+    204: B300 // JP V0, 300
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.cycle();
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.pc(), 0x302);
+
+        let mut chip8 = Chip8::load_from_text(code).setup_quirks(Quirks {
+            jump_uses_vx: true,
+            ..Quirks::default()
+        });
+        chip8.cycle();
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.pc(), 0x304);
+    }
+
+    #[test]
+    fn test_quirk_clip_sprites() {
+        let code = r#"
+    200: 603F // LD V0, 63
+    202: 6100 // LD V1, 0
+    204: A300 // LD I, 300
+    206: D011 // DRW V0, V1, 1
+    300: FF
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.test_run();
+        assert_eq!(chip8.memory.get8_framebuffer(0, 0), 1);
+
+        let mut chip8 = Chip8::load_from_text(code).setup_quirks(Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        });
+        chip8.test_run();
+        assert_eq!(chip8.memory.get8_framebuffer(0, 0), 0);
+        assert_eq!(chip8.memory.get8_framebuffer(63, 0), 1);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let code = r#"
+    200: 6001 // LD V0, 1
+    202: 2300 // CALL 300
+    300: C0FF // RND V0, 0xFF
+    302: 6107 // LD V1, 7
+    "#;
+        let mut chip8 = Chip8::load_from_text(code).setup_quirks(Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        });
+        chip8.seed_rng(42);
+        chip8.set_key(Key::Code7, true);
+        chip8.cycle();
+        chip8.cycle();
+        let state = chip8.save_state();
+
+        chip8.cycle();
+        chip8.cycle();
+        let v0 = chip8.get8(Register::V0 as usize);
+
+        let mut restored = Chip8::load_from_text("");
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.pc(), 0x300);
+        assert_eq!(restored.stack(), &[0x204]);
+        assert!(restored.keys[Key::Code7 as usize]);
+        assert!(restored.quirks.clip_sprites);
+        // the random number generator carries on from where it was
+        restored.cycle();
+        restored.cycle();
+        assert_eq!(restored.get8(Register::V0 as usize), v0);
+        assert_eq!(restored.get8(Register::V1 as usize), 7);
+        assert_eq!(restored.save_state(), chip8.save_state());
+    }
+
+    #[test]
+    fn test_rewind_to_ticks_timers() {
+        let code = r#"
+    200: 6064 // LD V0, 100
+    202: F015 // LD DT, V0
+    204: 7101 // ADD V1, 1
+    206: F207 // LD V2, DT
+    208: 1204 // JP 204
+    "#;
+        let mut chip8 = Chip8::load_from_text(code).setup_rewind(RewindBuffer::new(100, 4));
+        let mut expected = None;
+        // frames of 4 cycles, with a snapshot at the start of every other one
+        for frame in 0..10 {
+            chip8.tick_timers();
+            if frame % 2 == 0 {
+                chip8.record_snapshot();
+            }
+            for _ in 0..4 {
+                chip8.cycle();
+                if chip8.cycles() == 31 {
+                    expected = Some(chip8.save_state());
+                }
+            }
+        }
+
+        // the frame starting at cycle 28 has no snapshot, so its tick is replayed
+        chip8.rewind_to(31).unwrap();
+        assert_eq!(Some(chip8.save_state()), expected);
+        assert_eq!(chip8.delay_timer(), 93);
+    }
+
+    #[test]
+    fn test_rewind_to() {
+        let code = r#"
+    200: 7001 // ADD V0, 1
+    202: 1200 // JP 200
+    "#;
+        let mut chip8 = Chip8::load_from_text(code).setup_rewind(RewindBuffer::new(10, 4));
+        for _ in 0..5 {
+            chip8.record_snapshot();
+            for _ in 0..10 {
+                chip8.cycle();
+            }
+        }
+        assert_eq!(chip8.get8(Register::V0 as usize), 25);
+
+        // 37 cycles in is between snapshots, so some of it is executed again
+        chip8.rewind_to(37).unwrap();
+        assert_eq!(chip8.cycles(), 37);
+        assert_eq!(chip8.get8(Register::V0 as usize), 19);
+        assert_eq!(chip8.pc(), 0x202);
+
+        assert!(Chip8::load_from_text(code).rewind_to(0).is_err());
+    }
+
+    #[test]
+    fn test_rewind_frame() {
+        let mut chip8 =
+            Chip8::load_from_text("200: 7001\n202: 1200").setup_rewind(RewindBuffer::new(10, 4));
+        for _ in 0..3 {
+            chip8.record_snapshot();
+            chip8.cycle();
+            chip8.cycle();
+        }
+        assert_eq!(chip8.get8(Register::V0 as usize), 3);
+        assert!(chip8.rewind_frame());
+        assert_eq!(chip8.get8(Register::V0 as usize), 2);
+        assert!(chip8.rewind_frame());
+        assert!(chip8.rewind_frame());
+        assert_eq!(chip8.get8(Register::V0 as usize), 0);
+        assert!(!chip8.rewind_frame());
+    }
+
+    #[test]
+    fn test_reset() {
+        let code = r#"
+    200: 7001 // ADD V0, 1
+    202: A300 // LD I, 300
+    204: D011 // DRW V0, V1, 1
+    206: 1200 // JP 200
+    300: 80
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        for _ in 0..10 {
+            chip8.cycle();
+        }
+        assert_eq!(chip8.get8(Register::V0 as usize), 3);
+        assert!(chip8.memory().framebuffer().contains(&1));
+
+        chip8.handle_event(InputEvent::Reset);
+        assert_eq!(chip8.pc(), 0x200);
+        assert_eq!(chip8.get8(Register::V0 as usize), 0);
+        assert_eq!(chip8.get16(0x206), 0x1200);
+        assert!(!chip8.memory().framebuffer().contains(&1));
+        assert_eq!(chip8.cycles(), 10);
+    }
+
+    #[test]
+    fn test_pause_and_speed() {
+        let mut chip8 = Chip8::load_from_text("200: 7001\n202: 1200");
+        chip8.handle_event(InputEvent::FrameAdvance);
+        assert!(chip8.is_paused());
+        assert_eq!(chip8.cycles(), 0);
+        chip8.handle_event(InputEvent::FrameAdvance);
+        assert_eq!(chip8.cycles(), 1);
+        chip8.handle_event(InputEvent::Pause);
+        assert!(!chip8.is_paused());
+
+        chip8.handle_event(InputEvent::FastForward);
+        assert_eq!(chip8.speed(), Speed::Unthrottled);
+        chip8.handle_event(InputEvent::SlowMotion);
+        assert_eq!(chip8.speed(), Speed::Times(0.25));
+        chip8.handle_event(InputEvent::SlowMotion);
+        assert_eq!(chip8.speed(), Speed::NORMAL);
+        assert_eq!(Speed::Times(2.0).frame_duration(), Some(FRAME_DURATION / 2));
+        assert_eq!(Speed::Unthrottled.frame_duration(), None);
+    }
+
+    #[test]
+    fn test_skip_if_key() {
+        let code = r#"
+    200: 6205 // LD V2, 5
+    202: E29E // SKP V2
+    204: 6001 // LD V0, 1
+    206: E2A1 // SKNP V2
+    208: 6101 // LD V1, 1
+    "#;
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.set_key(Key::Code5, true);
+        chip8.test_run();
+        assert_eq!(chip8.get8(Register::V0 as usize), 0);
+        assert_eq!(chip8.get8(Register::V1 as usize), 1);
+    }
+
+    #[test]
+    fn test_wait_for_key() {
+        let mut chip8 = Chip8::load_from_text("200: F30A // LD V3, K");
+        chip8.cycle();
+        chip8.cycle();
+        assert_eq!(chip8.pc(), 0x200);
+        chip8.set_key(Key::CodeB, true);
+        chip8.cycle();
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.get8(Register::V3 as usize), 0xB);
+    }
+
+    #[test]
+    fn test_rom_hash() {
+        // the same program as a binary ROM, as Octo source and as text
+        let rom = [0x60, 0x05, 0x12, 0x02];
+        let hash = sha1_hex(&rom);
+        assert_eq!(Chip8::load_from_bytes(&rom).unwrap().rom_hash(), hash);
+        assert_eq!(
+            Chip8::load_from_octo(": main v0 := 5 jump 0x202")
+                .unwrap()
+                .rom_hash(),
+            hash
+        );
+        let mut chip8 = Chip8::load_from_text("200: 6005\n202: 1202");
+        assert_eq!(chip8.rom_hash(), hash);
+        // and running it doesn't change what it's identified as
+        chip8.memory.set8(0x300, 1);
+        assert_eq!(chip8.rom_hash(), hash);
+    }
+
+    #[test]
+    fn test_movie_replay() {
+        // draws a random sprite row each frame that key 5 is held down
+        let code = r#"
+    200: A300 // LD I, 300
+    202: 6205 // LD V2, 5
+    204: E2A1 // SKNP V2
+    206: 120A // JP 20A
+    208: 1204 // JP 204
+    20A: C13F // RND V1, 0x3F
+    20C: C03F // RND V0, 0x3F
+    20E: D011 // DRW V0, V1, 1
+    210: 1204 // JP 204
+    300: F0
+    "#;
+        let filepath = std::env::temp_dir().join("chip8_movie_test.txt");
+        let filepath = filepath.to_str().unwrap();
+        let mut chip8 = Chip8::load_from_text(code);
+        chip8.cycles_per_frame = 4;
+        let recorder = Recorder::to_file(chip8.new_movie(), filepath);
+        let mut chip8 = chip8.setup_recorder(recorder);
+        for frame in 0..40 {
+            match frame {
+                5 | 25 => chip8.handle_event(InputEvent::Key(Key::Code5, true)),
+                15 | 32 => chip8.handle_event(InputEvent::Key(Key::Code5, false)),
+                // a repeat, which shouldn't be recorded
+                26 => chip8.handle_event(InputEvent::Key(Key::Code5, true)),
+                _ => {}
+            }
+            chip8.run_frame();
+        }
+        // the movie is written out when the recorder is dropped
+        chip8.recorder = None;
+        let movie = Movie::load(filepath).unwrap();
+        std::fs::remove_file(filepath).unwrap();
+        assert_eq!(movie.frames, 40);
+        assert_eq!(movie.events.len(), 4);
+        assert!(chip8.memory.framebuffer().contains(&1));
+
+        // a fresh machine gets a different seed from the clock, which the movie replaces
+        let mut replay = Chip8::load_from_text(code);
+        replay.play_movie(&movie).unwrap();
+        assert_eq!(replay.memory.framebuffer(), chip8.memory.framebuffer());
+        assert_eq!(replay.cycles(), chip8.cycles());
+
+        let mut other_rom = Chip8::load_from_text("200: 1200");
+        assert!(other_rom.play_movie(&movie).is_err());
+    }
 }
@@ -0,0 +1,60 @@
+use crate::key::Key;
+use crate::memory::Memory;
+use crate::palette::Palette;
+use crate::panels::DebugView;
+use crate::quirks::Quirks;
+
+// things the emulator needs to act on, from the keyboard or the window
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InputEvent {
+    Key(Key, bool),        // pressed or released
+    SaveState(u8),         // Shift+F1 to Shift+F9
+    LoadState(u8),         // F1 to F9
+    Rewind(bool),          // Backspace, held down to play backwards
+    Screenshot,            // F12
+    ToggleCapture,         // F10, to start or stop recording a GIF
+    NextPalette,           // Tab
+    ToggleBreakpoint(u16), // clicking a line of disassembly
+    // the rest are on hotkeys from the keymap
+    Pause,
+    FrameAdvance,
+    FastForward, // toggles
+    SlowMotion,  // toggles
+    Reset,
+}
+
+// how the emulator has been running over the last second or so
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub frames_per_second: f64,
+    pub instructions_per_second: u64,
+    pub quirks: Quirks,
+}
+
+// what the emulator draws to and reads the keypad from, e.g. an SDL window or a terminal
+pub trait Frontend {
+    fn render_graphics(&mut self, memory: &Memory, palette: &Palette);
+
+    // the events since the last call, or Err("QUIT") when the user wants to stop
+    fn handle_input(&mut self) -> Result<Vec<InputEvent>, &str>;
+
+    // LD Vx, K is waiting for a key to go down
+    fn wait_for_keypress(&mut self) {}
+
+    // feedback such as "State saved to slot 2"
+    fn show_message(&mut self, message: &str) {
+        println!("{message}");
+    }
+
+    fn show_stats(&mut self, _stats: &Stats) {}
+
+    // lines a script wants drawn, replacing the last ones
+    fn show_overlay(&mut self, _lines: &[String]) {}
+
+    // whether to call show_debug before every frame is drawn
+    fn shows_debug_view(&self) -> bool {
+        false
+    }
+
+    fn show_debug(&mut self, _view: &DebugView) {}
+}
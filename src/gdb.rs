@@ -0,0 +1,607 @@
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use crate::emulator::Chip8;
+use crate::hex::{from_hex, to_hex};
+use crate::register::Register;
+
+// A GDB remote serial protocol server, so gdb (or anything else that speaks the protocol)
+// can attach to the emulator over TCP with "target remote localhost:<port>". The program
+// waits at 200 until a client connects and tells it to continue.
+//
+// The registers are numbered V0-VF (0-15), I, PC, SP, DT and ST, and are sent big-endian
+// like everything else on a CHIP-8. SP is the stack depth, which can only be made smaller.
+
+const REGISTER_COUNT: usize = 21;
+
+const PACKET_SIZE: usize = 0x1000;
+
+// signals for stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+// what a client sent, as far as the emulator cares
+#[derive(Debug, PartialEq, Eq)]
+enum Incoming {
+    Packet(String),
+    BadChecksum,
+    Interrupt,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    Stopped,
+    Running,
+    Stepping,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn frame(data: &str) -> String {
+    format!("${data}#{:02x}", checksum(data.as_bytes()))
+}
+
+// takes the next packet or interrupt off the front of input, skipping acks and noise,
+// or returns None if there isn't a whole one yet
+fn next_packet(input: &mut Vec<u8>) -> Option<Incoming> {
+    loop {
+        match input.first()? {
+            b'$' => {
+                let end = input.iter().position(|byte| *byte == b'#')?;
+                if input.len() < end + 3 {
+                    return None;
+                }
+                let packet: Vec<u8> = input.drain(..end + 3).collect();
+                let data = &packet[1..end];
+                let sent = std::str::from_utf8(&packet[end + 1..])
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                if sent != Some(checksum(data)) {
+                    return Some(Incoming::BadChecksum);
+                }
+                return Some(Incoming::Packet(String::from_utf8_lossy(data).into_owned()));
+            }
+            0x03 => {
+                input.remove(0);
+                return Some(Incoming::Interrupt);
+            }
+            _ => {
+                input.remove(0);
+            }
+        }
+    }
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// "addr,length" as in m and M packets, checked against the size of memory
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+    // checked, since a huge length from the client would overflow
+    (address.checked_add(length)? <= 4096).then_some((address, length))
+}
+
+fn register_size(number: usize) -> usize {
+    match number {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+fn target_xml() -> String {
+    let mut registers: Vec<String> = (0..16)
+        .map(|number| format!("    <reg name=\"v{number:x}\" bitsize=\"8\" type=\"uint8\"/>"))
+        .collect();
+    registers.extend([
+        "    <reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>".to_owned(),
+        "    <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>".to_owned(),
+        "    <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>".to_owned(),
+        "    <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>".to_owned(),
+        "    <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>".to_owned(),
+    ]);
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  \
+         <feature name=\"org.chip8.cpu\">\n{}\n  </feature>\n</target>\n",
+        registers.join("\n")
+    )
+}
+
+fn read_register(chip8: &Chip8, number: usize) -> u16 {
+    let memory = chip8.memory();
+    match number {
+        0..=15 => memory.peek8(Register::v_register_from(number as u8) as usize) as u16,
+        16 => memory.peek16(Register::IR as usize),
+        17 => chip8.pc(),
+        18 => chip8.stack().len() as u16,
+        19 => chip8.delay_timer().min(0xFF) as u16,
+        20 => chip8.sound_timer().min(0xFF) as u16,
+        _ => 0,
+    }
+}
+
+fn write_register(chip8: &mut Chip8, number: usize, value: u16) -> Result<(), String> {
+    match number {
+        0..=15 => chip8.set8(
+            Register::v_register_from(number as u8) as usize,
+            value as u8,
+        ),
+        16 => chip8.set16(Register::IR as usize, value),
+        17 => chip8.set16(Register::PC as usize, value),
+        18 => chip8.set_stack_depth(value as usize)?,
+        19 => chip8.set_delay_timer(value as u32),
+        20 => chip8.set_sound_timer(value as u32),
+        _ => return Err(format!("No register {number}")),
+    }
+    Ok(())
+}
+
+fn encode_register(chip8: &Chip8, number: usize) -> String {
+    let bytes = read_register(chip8, number).to_be_bytes();
+    to_hex(&bytes[2 - register_size(number)..])
+}
+
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    input: Vec<u8>,
+    breakpoints: BTreeSet<u16>,
+    state: State,
+    // lets the instruction we stopped on run when continuing, instead of breaking again
+    resuming: bool,
+    acks: bool,
+    quit: bool,
+}
+
+impl GdbStub {
+    // only on localhost, since anyone connected can read and write all of memory
+    pub fn listen(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|error| format!("Unable to listen for GDB on port {port}: {error}"))?;
+        Ok(Self {
+            listener,
+            client: None,
+            input: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            state: State::Stopped,
+            resuming: false,
+            acks: true,
+            quit: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    // Called once per iteration of the emulator loop instead of Chip8::cycle, like
+    // Debugger::tick. Handles whatever the client has sent, then executes an instruction
+    // unless stopped. Returns -1 once the program should stop.
+    // waiting for the client to continue or step
+    pub fn is_stopped(&self) -> bool {
+        self.state == State::Stopped
+    }
+
+    pub fn tick(&mut self, chip8: &mut Chip8) -> i8 {
+        self.accept();
+        self.receive(chip8);
+        if self.quit {
+            return -1;
+        }
+        if self.state == State::Stopped {
+            return 0;
+        }
+
+        let resuming = std::mem::take(&mut self.resuming);
+        if !resuming && self.breakpoints.contains(&chip8.pc()) {
+            self.state = State::Stopped;
+            self.send(&format!("T{SIGTRAP:02x}swbreak:;"));
+            return 0;
+        }
+        if chip8.cycle() == -1 {
+            self.send("W00");
+            return -1;
+        }
+        if self.state == State::Stepping {
+            self.state = State::Stopped;
+            self.send(&format!("S{SIGTRAP:02x}"));
+        }
+        0
+    }
+
+    // a new client finds the program stopped, wherever it had got to
+    fn accept(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        if let Ok((stream, address)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                println!("GDB connected from {address}");
+                stream.set_nodelay(true).unwrap_or(());
+                self.client = Some(stream);
+                self.input.clear();
+                self.state = State::Stopped;
+                self.acks = true;
+            }
+        }
+    }
+
+    fn receive(&mut self, chip8: &mut Chip8) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        let mut buffer = [0; 1024];
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => {
+                    self.detach();
+                    return;
+                }
+                Ok(length) => self.input.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.detach();
+                    return;
+                }
+            }
+        }
+
+        while let Some(incoming) = next_packet(&mut self.input) {
+            match incoming {
+                Incoming::Packet(packet) => {
+                    if self.acks {
+                        self.write(b"+");
+                    }
+                    if let Some(reply) = self.handle_packet(chip8, &packet) {
+                        self.send(&reply);
+                    }
+                }
+                Incoming::BadChecksum => self.write(b"-"),
+                Incoming::Interrupt => {
+                    if self.state != State::Stopped {
+                        self.state = State::Stopped;
+                        self.send(&format!("S{SIGINT:02x}"));
+                    }
+                }
+            }
+        }
+    }
+
+    // the program carries on without the client's breakpoints
+    fn detach(&mut self) {
+        println!("GDB disconnected");
+        self.client = None;
+        self.breakpoints.clear();
+        self.state = State::Running;
+        self.resuming = true;
+    }
+
+    fn send(&mut self, data: &str) {
+        self.write(frame(data).as_bytes());
+    }
+
+    // blocking, since replies are small and the client is waiting for them
+    fn write(&mut self, bytes: &[u8]) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        let result = client
+            .set_nonblocking(false)
+            .and_then(|_| client.write_all(bytes))
+            .and_then(|_| client.set_nonblocking(true));
+        if result.is_err() {
+            self.detach();
+        }
+    }
+
+    fn resume(&mut self, chip8: &mut Chip8, state: State, address: &str) -> Option<String> {
+        if let Some(address) = parse_hex(address) {
+            chip8.set16(Register::PC as usize, address as u16);
+        }
+        self.state = state;
+        self.resuming = true;
+        None
+    }
+
+    // the reply to a packet, or None if it comes later (when the program stops)
+    fn handle_packet(&mut self, chip8: &mut Chip8, packet: &str) -> Option<String> {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "c" => return self.resume(chip8, State::Running, arguments),
+            "s" => return self.resume(chip8, State::Stepping, arguments),
+            "g" => (0..REGISTER_COUNT)
+                .map(|number| encode_register(chip8, number))
+                .collect(),
+            "G" => self.write_registers(chip8, arguments),
+            "p" => match parse_hex(arguments) {
+                Some(number) if number < REGISTER_COUNT => encode_register(chip8, number),
+                _ => "E01".to_owned(),
+            },
+            "P" => Self::write_one_register(chip8, arguments),
+            "m" => match parse_range(arguments) {
+                Some((address, length)) => {
+                    to_hex(&chip8.memory().data()[address..address + length])
+                }
+                None => "E01".to_owned(),
+            },
+            "M" => Self::write_memory(chip8, arguments),
+            "Z" | "z" => self.set_breakpoint(command == "Z", arguments),
+            "k" => {
+                self.quit = true;
+                return None;
+            }
+            "D" => {
+                self.send("OK");
+                self.detach();
+                return None;
+            }
+            "H" => "OK".to_owned(),
+            "q" | "Q" => self.query(packet),
+            // anything else is unsupported, which gdb knows from an empty reply
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        let (name, arguments) = packet.split_once(':').unwrap_or((packet, ""));
+        match name {
+            "qSupported" => {
+                format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;swbreak+;QStartNoAckMode+")
+            }
+            "QStartNoAckMode" => {
+                self.acks = false;
+                "OK".to_owned()
+            }
+            "qAttached" => "1".to_owned(),
+            "qC" => "QC1".to_owned(),
+            "qfThreadInfo" => "m1".to_owned(),
+            "qsThreadInfo" => "l".to_owned(),
+            "qXfer" => match arguments.strip_prefix("features:read:target.xml:") {
+                Some(range) => {
+                    let xml = target_xml();
+                    let range = range.split_once(',').and_then(|(offset, length)| {
+                        Some((parse_hex(offset)?, parse_hex(length)?))
+                    });
+                    match range {
+                        Some((offset, length)) => {
+                            let start = offset.min(xml.len());
+                            let end = (start + length).min(xml.len());
+                            let more = if end < xml.len() { 'm' } else { 'l' };
+                            format!("{more}{}", &xml[start..end])
+                        }
+                        None => "E01".to_owned(),
+                    }
+                }
+                None => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+
+    fn write_registers(&mut self, chip8: &mut Chip8, hex: &str) -> String {
+        let Some(bytes) = from_hex(hex) else {
+            return "E01".to_owned();
+        };
+        let mut offset = 0;
+        for number in 0..REGISTER_COUNT {
+            let size = register_size(number);
+            let Some(value) = bytes.get(offset..offset + size) else {
+                break;
+            };
+            let value = value
+                .iter()
+                .fold(0, |value, byte| (value << 8) | *byte as u16);
+            if write_register(chip8, number, value).is_err() {
+                return "E01".to_owned();
+            }
+            offset += size;
+        }
+        "OK".to_owned()
+    }
+
+    fn write_one_register(chip8: &mut Chip8, arguments: &str) -> String {
+        let Some((number, value)) = arguments.split_once('=') else {
+            return "E01".to_owned();
+        };
+        let value = from_hex(value).map(|bytes| {
+            bytes
+                .iter()
+                .fold(0, |value, byte| (value << 8) | *byte as u16)
+        });
+        match (parse_hex(number), value) {
+            (Some(number), Some(value)) if write_register(chip8, number, value).is_ok() => {
+                "OK".to_owned()
+            }
+            _ => "E01".to_owned(),
+        }
+    }
+
+    fn write_memory(chip8: &mut Chip8, arguments: &str) -> String {
+        let write = arguments.split_once(':').and_then(|(range, data)| {
+            let (address, length) = parse_range(range)?;
+            let bytes = from_hex(data)?;
+            (bytes.len() == length).then_some((address, bytes))
+        });
+        match write {
+            Some((address, bytes)) => {
+                for (offset, byte) in bytes.iter().enumerate() {
+                    chip8.set8(address + offset, *byte);
+                }
+                "OK".to_owned()
+            }
+            None => "E01".to_owned(),
+        }
+    }
+
+    // only software breakpoints (type 0), "0,addr,kind"
+    fn set_breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.split(',');
+        if fields.next() != Some("0") {
+            return String::new();
+        }
+        match fields.next().and_then(parse_hex) {
+            Some(address) if address < 4096 => {
+                if insert {
+                    self.breakpoints.insert(address as u16);
+                } else {
+                    self.breakpoints.remove(&(address as u16));
+                }
+                "OK".to_owned()
+            }
+            _ => "E01".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const PROGRAM: &str = r#"
+    200: 6001 // LD V0, 1
+    202: 7001 // ADD V0, 1
+    204: A300 // LD I, 300
+    206: 1202 // JP 202
+    300: AB
+    301: CD
+    "#;
+
+    // a client that sends one packet at a time and runs the emulator until the reply
+    struct Client {
+        stream: TcpStream,
+        input: Vec<u8>,
+    }
+
+    impl Client {
+        fn connect(stub: &GdbStub) -> Self {
+            let stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(1)))
+                .unwrap();
+            Self {
+                stream,
+                input: Vec::new(),
+            }
+        }
+
+        fn reply(&mut self, stub: &mut GdbStub, chip8: &mut Chip8) -> String {
+            for _ in 0..10000 {
+                stub.tick(chip8);
+                let mut buffer = [0; 4096];
+                if let Ok(length) = self.stream.read(&mut buffer) {
+                    self.input.extend_from_slice(&buffer[..length]);
+                }
+                if let Some(Incoming::Packet(reply)) = next_packet(&mut self.input) {
+                    return reply;
+                }
+            }
+            panic!("No reply from the stub");
+        }
+
+        fn send(&mut self, stub: &mut GdbStub, chip8: &mut Chip8, packet: &str) -> String {
+            self.stream.write_all(frame(packet).as_bytes()).unwrap();
+            self.reply(stub, chip8)
+        }
+    }
+
+    #[test]
+    fn test_next_packet() {
+        let mut input = b"+$g#67$m200,2#".to_vec();
+        assert_eq!(
+            next_packet(&mut input),
+            Some(Incoming::Packet("g".to_owned()))
+        );
+        // incomplete until the checksum arrives
+        assert_eq!(next_packet(&mut input), None);
+        input.extend_from_slice(b"00\x03$?#00");
+        assert_eq!(next_packet(&mut input), Some(Incoming::BadChecksum));
+        assert_eq!(next_packet(&mut input), Some(Incoming::Interrupt));
+        assert_eq!(next_packet(&mut input), Some(Incoming::BadChecksum));
+        assert!(input.is_empty());
+
+        assert_eq!(frame("OK"), "$OK#9a");
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut stub = GdbStub::listen(0).unwrap();
+        let mut client = Client::connect(&stub);
+
+        assert!(client
+            .send(&mut stub, &mut chip8, "qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        let xml = client.send(
+            &mut stub,
+            &mut chip8,
+            "qXfer:features:read:target.xml:0,ffb",
+        );
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+        assert_eq!(client.send(&mut stub, &mut chip8, "?"), "S05");
+
+        // V0-VF, I, PC, SP, DT and ST
+        let registers = client.send(&mut stub, &mut chip8, "g");
+        assert_eq!(registers, format!("{}00000200000000", "00".repeat(16)));
+        assert_eq!(client.send(&mut stub, &mut chip8, "P3=2a"), "OK");
+        assert_eq!(client.send(&mut stub, &mut chip8, "p3"), "2a");
+        assert_eq!(client.send(&mut stub, &mut chip8, "P12=01"), "E01");
+        assert_eq!(client.send(&mut stub, &mut chip8, "p11"), "0200");
+
+        assert_eq!(client.send(&mut stub, &mut chip8, "m300,2"), "abcd");
+        assert_eq!(client.send(&mut stub, &mut chip8, "M301,2:eeff"), "OK");
+        assert_eq!(client.send(&mut stub, &mut chip8, "m300,3"), "abeeff");
+        assert_eq!(client.send(&mut stub, &mut chip8, "mfff,2"), "E01");
+        assert_eq!(
+            client.send(&mut stub, &mut chip8, "m1,ffffffffffffffff"),
+            "E01"
+        );
+        assert_eq!(client.send(&mut stub, &mut chip8, "vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn test_run_control() {
+        let mut chip8 = Chip8::load_from_text(PROGRAM);
+        let mut stub = GdbStub::listen(0).unwrap();
+        let mut client = Client::connect(&stub);
+
+        assert_eq!(client.send(&mut stub, &mut chip8, "QStartNoAckMode"), "OK");
+        assert_eq!(client.send(&mut stub, &mut chip8, "s"), "S05");
+        assert_eq!(chip8.pc(), 0x202);
+
+        assert_eq!(client.send(&mut stub, &mut chip8, "Z0,206,2"), "OK");
+        assert_eq!(client.send(&mut stub, &mut chip8, "c"), "T05swbreak:;");
+        assert_eq!(chip8.pc(), 0x206);
+        // continuing doesn't stop at the same breakpoint straight away
+        assert_eq!(client.send(&mut stub, &mut chip8, "c"), "T05swbreak:;");
+        assert_eq!(chip8.get8(Register::V0 as usize), 3);
+
+        assert_eq!(client.send(&mut stub, &mut chip8, "z0,206,2"), "OK");
+        client.stream.write_all(frame("c").as_bytes()).unwrap();
+        for _ in 0..10 {
+            stub.tick(&mut chip8);
+        }
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.reply(&mut stub, &mut chip8), "S02");
+
+        client.stream.write_all(frame("k").as_bytes()).unwrap();
+        for _ in 0..1000 {
+            if stub.tick(&mut chip8) == -1 {
+                return;
+            }
+        }
+        panic!("The stub didn't stop after a kill");
+    }
+}
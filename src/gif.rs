@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+
+// The parts of GIF shared by screen captures and Octo cartridges: LZW compression of
+// the colour indices, and reading the first image back out of a file.
+
+const MAXIMUM_CODE_SIZE: u32 = 12;
+
+// a single image, as indices into its colour table
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub palette: Vec<[u8; 3]>,
+    pub pixels: Vec<u8>,
+}
+
+// variable length codes, packed from the least significant bit
+struct CodeWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl CodeWriter {
+    fn write(&mut self, code: u32, size: u32) {
+        self.buffer |= code << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// every pixel has to be below 1 << minimum_code_size
+pub fn lzw(pixels: &[u8], minimum_code_size: u32) -> Vec<u8> {
+    let clear = 1 << minimum_code_size;
+    let end = clear + 1;
+    let mut writer = CodeWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut codes: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut size = minimum_code_size + 1;
+    writer.write(clear, size);
+
+    let Some((first, rest)) = pixels.split_first() else {
+        writer.write(end, size);
+        return writer.finish();
+    };
+    let mut prefix = *first as u32;
+    for pixel in rest {
+        if let Some(code) = codes.get(&(prefix, *pixel)) {
+            prefix = *code;
+            continue;
+        }
+        writer.write(prefix, size);
+        // the decoder adds its entry for a code one code later, so grow after writing
+        if next_code > (1 << size) - 1 && size < MAXIMUM_CODE_SIZE {
+            size += 1;
+        }
+        if next_code < 1 << MAXIMUM_CODE_SIZE {
+            codes.insert((prefix, *pixel), next_code);
+            next_code += 1;
+        } else {
+            writer.write(clear, size);
+            codes.clear();
+            next_code = end + 1;
+            size = minimum_code_size + 1;
+        }
+        prefix = *pixel as u32;
+    }
+    writer.write(prefix, size);
+    if next_code > (1 << size) - 1 && size < MAXIMUM_CODE_SIZE {
+        size += 1;
+    }
+    writer.write(end, size);
+    writer.finish()
+}
+
+// Stops at the end code, or wherever the data runs out, since not every encoder
+// writes one.
+pub fn unlzw(data: &[u8], minimum_code_size: u32) -> Result<Vec<u8>, String> {
+    if !(2..=8).contains(&minimum_code_size) {
+        return Err(format!("Invalid LZW code size {minimum_code_size}"));
+    }
+    let clear = 1u32 << minimum_code_size;
+    let end = clear + 1;
+    // each code is the string of an earlier code with one more byte on the end
+    let mut table: Vec<(Option<u32>, u8)> = (0..=end).map(|value| (None, value as u8)).collect();
+    let mut size = minimum_code_size + 1;
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    let mut bytes = data.iter();
+    let mut previous: Option<u32> = None;
+    let mut output = Vec::new();
+    let mut string = Vec::new();
+    loop {
+        while bits < size {
+            let Some(byte) = bytes.next() else {
+                return Ok(output);
+            };
+            buffer |= (*byte as u32) << bits;
+            bits += 8;
+        }
+        let code = buffer & ((1 << size) - 1);
+        buffer >>= size;
+        bits -= size;
+
+        if code == clear {
+            table.truncate(end as usize + 1);
+            size = minimum_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return Ok(output);
+        }
+        // a code can be the one about to be added: the previous string and its first byte
+        let known = (code as usize) < table.len();
+        let start = match previous {
+            _ if known => code,
+            Some(previous) if code as usize == table.len() => previous,
+            _ => return Err(format!("Invalid LZW code {code}")),
+        };
+        string.clear();
+        let mut next = Some(start);
+        while let Some(code) = next {
+            let (prefix, byte) = table[code as usize];
+            string.push(byte);
+            next = prefix;
+        }
+        string.reverse();
+        if !known {
+            string.push(string[0]);
+        }
+
+        if let Some(previous) = previous {
+            if table.len() < 1 << MAXIMUM_CODE_SIZE {
+                table.push((Some(previous), string[0]));
+                if table.len() == 1 << size && size < MAXIMUM_CODE_SIZE {
+                    size += 1;
+                }
+            }
+        }
+        output.extend_from_slice(&string);
+        previous = Some(code);
+    }
+}
+
+// the minimum code size byte, then the compressed pixels in sub-blocks of up to 255 bytes
+pub fn write_image_data(data: &mut Vec<u8>, pixels: &[u8], minimum_code_size: u32) {
+    data.push(minimum_code_size as u8);
+    for block in lzw(pixels, minimum_code_size).chunks(255) {
+        data.push(block.len() as u8);
+        data.extend_from_slice(block);
+    }
+    data.push(0x00);
+}
+
+// a still GIF, with a global colour table of up to 256 colours
+pub fn encode(image: &Image) -> Vec<u8> {
+    // the table has a power of two entries, at least 4 so the code size is at least 2
+    let mut bits = 2;
+    while 1 << bits < image.palette.len() {
+        bits += 1;
+    }
+    let mut data = b"GIF89a".to_vec();
+    data.extend_from_slice(&(image.width as u16).to_le_bytes());
+    data.extend_from_slice(&(image.height as u16).to_le_bytes());
+    data.extend_from_slice(&[0x80 | (bits - 1) as u8, 0, 0]);
+    for index in 0..1 << bits {
+        data.extend_from_slice(&image.palette.get(index).copied().unwrap_or([0; 3]));
+    }
+    // image descriptor covering the whole screen, using the global colour table
+    data.push(0x2C);
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(&(image.width as u16).to_le_bytes());
+    data.extend_from_slice(&(image.height as u16).to_le_bytes());
+    data.push(0x00);
+    write_image_data(&mut data, &image.pixels, bits);
+    data.push(0x3B);
+    data
+}
+
+// reads through a GIF, failing if it ends too soon
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or("GIF is truncated")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<usize, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn colour_table(&mut self, flags: u8) -> Result<Vec<[u8; 3]>, String> {
+        let count = 2 << (flags & 0x07);
+        let bytes = self.take(count * 3)?;
+        Ok(bytes
+            .chunks(3)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect())
+    }
+
+    // sub-blocks of up to 255 bytes, until an empty one
+    fn blocks(&mut self) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        loop {
+            let length = self.u8()? as usize;
+            if length == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(self.take(length)?);
+        }
+    }
+}
+
+// interlaced images store every 8th row from 0, every 8th from 4, every 4th from 2,
+// then every other row from 1
+fn deinterlace(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let rows = [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .iter()
+        .flat_map(|(start, step)| (*start..height).step_by(*step));
+    let mut output = vec![0; pixels.len()];
+    for (stored, row) in rows.enumerate() {
+        output[row * width..(row + 1) * width]
+            .copy_from_slice(&pixels[stored * width..(stored + 1) * width]);
+    }
+    output
+}
+
+// the first image, which is all a cartridge has
+pub fn decode(data: &[u8]) -> Result<Image, String> {
+    if !data.starts_with(b"GIF87a") && !data.starts_with(b"GIF89a") {
+        return Err("Not a GIF".to_owned());
+    }
+    let mut reader = Reader { data, position: 6 };
+    reader.take(4)?;
+    let flags = reader.u8()?;
+    reader.take(2)?;
+    let mut palette = if flags & 0x80 != 0 {
+        reader.colour_table(flags)?
+    } else {
+        Vec::new()
+    };
+
+    loop {
+        match reader.u8()? {
+            // an extension, which only matters for animation and comments
+            0x21 => {
+                reader.u8()?;
+                reader.blocks()?;
+            }
+            0x2C => {
+                reader.take(4)?;
+                let width = reader.u16()?;
+                let height = reader.u16()?;
+                let flags = reader.u8()?;
+                if flags & 0x80 != 0 {
+                    palette = reader.colour_table(flags)?;
+                }
+                let minimum_code_size = reader.u8()? as u32;
+                let mut pixels = unlzw(&reader.blocks()?, minimum_code_size)?;
+                if pixels.len() < width * height {
+                    return Err("GIF image data is truncated".to_owned());
+                }
+                pixels.truncate(width * height);
+                if flags & 0x40 != 0 {
+                    pixels = deinterlace(&pixels, width, height);
+                }
+                return Ok(Image {
+                    width,
+                    height,
+                    palette,
+                    pixels,
+                });
+            }
+            0x3B => return Err("GIF has no image".to_owned()),
+            block => return Err(format!("Invalid GIF block {block:#04X}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzw_round_trip() {
+        // long enough to go through every code size and a clear
+        let mut seed = 1u32;
+        let mut random = |bits: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed >> (32 - bits)) as u8
+        };
+        let pixels: Vec<u8> = (0..40_000).map(|_| random(1)).collect();
+        assert_eq!(unlzw(&lzw(&pixels, 2), 2).unwrap(), pixels);
+        let pixels: Vec<u8> = (0..40_000).map(|_| random(8)).collect();
+        assert_eq!(unlzw(&lzw(&pixels, 8), 8).unwrap(), pixels);
+        assert_eq!(unlzw(&lzw(&[1; 5000], 2), 2).unwrap(), vec![1; 5000]);
+        assert_eq!(unlzw(&lzw(&[0], 2), 2).unwrap(), vec![0]);
+        assert!(unlzw(&[0xFF, 0xFF], 2).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let image = Image {
+            width: 7,
+            height: 3,
+            palette: vec![
+                [0, 0, 0],
+                [255, 255, 255],
+                [255, 0, 0],
+                [0, 0, 255],
+                [1, 2, 3],
+            ],
+            pixels: (0..21).map(|index| index % 5).collect(),
+        };
+        let decoded = decode(&encode(&image)).unwrap();
+        assert_eq!((decoded.width, decoded.height), (7, 3));
+        assert_eq!(decoded.palette.len(), 8);
+        assert_eq!(decoded.palette[..5], image.palette[..]);
+        assert_eq!(decoded.pixels, image.pixels);
+
+        assert_eq!(decode(b"GIF89a").err().unwrap(), "GIF is truncated");
+        assert!(decode(b"PNG").is_err());
+    }
+
+    #[test]
+    fn test_deinterlace() {
+        // rows 0 and 8, then 4, then 2 and 6, then the odd ones
+        let stored: Vec<u8> = [0, 8, 4, 2, 6, 1, 3, 5, 7].to_vec();
+        assert_eq!(deinterlace(&stored, 1, 9), (0..9).collect::<Vec<u8>>());
+    }
+}
@@ -1,76 +1,409 @@
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::{Point, Rect};
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 use sdl2::Sdl;
-use std::time::Duration;
 
+use crate::font;
 use crate::memory::Memory;
+use crate::palette::Palette;
+use crate::panels::{self, DebugView, PanelLine};
+use crate::phosphor::{Phosphor, PhosphorFilter};
+
+const SCREEN_WIDTH: u32 = 64;
+
+// the window starts at 15 window pixels per CHIP-8 pixel
+const WINDOW_SCALE: u32 = 15;
+
+// and bigger with the debug panels, to leave room for them
+const PANELS_WINDOW_SIZE: (u32, u32) = (1280, 720);
+
+// rows of the memory panel under the screen
+const MEMORY_ROWS: u32 = 8;
+
+fn sdl_color([r, g, b]: [u8; 3]) -> Color {
+    Color::RGB(r, g, b)
+}
+
+// how the screen fits into the window, from the command line
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayOptions {
+    // only whole window pixels per CHIP-8 pixel, so every pixel is the same size
+    pub integer_scaling: bool,
+    pub grid: bool,
+    pub fullscreen: bool,
+    // smooths out flicker from the frames already drawn
+    pub phosphor: Option<Phosphor>,
+    // FPS, instructions per second and quirks in the corner
+    pub stats: bool,
+    // registers, disassembly and memory around the screen
+    pub panels: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            integer_scaling: false,
+            grid: true,
+            fullscreen: false,
+            phosphor: None,
+            stats: false,
+            panels: false,
+        }
+    }
+}
+
+// the largest area with the screen's aspect ratio that fits in the window, centred,
+// leaving black bars along the other sides
+fn viewport(window: (u32, u32), screen: (u32, u32), integer_scaling: bool) -> Rect {
+    let (window_width, window_height) = window;
+    let (screen_width, screen_height) = screen;
+    let mut scale = f64::min(
+        window_width as f64 / screen_width as f64,
+        window_height as f64 / screen_height as f64,
+    );
+    // a window smaller than the screen still gets scaled down
+    if integer_scaling && scale >= 1.0 {
+        scale = scale.floor();
+    }
+    let width = ((screen_width as f64 * scale).round() as u32).max(1);
+    let height = ((screen_height as f64 * scale).round() as u32).max(1);
+    Rect::new(
+        (window_width.saturating_sub(width) / 2) as i32,
+        (window_height.saturating_sub(height) / 2) as i32,
+        width,
+        height,
+    )
+}
+
+// text is drawn with font pixels that grow with the window
+fn text_scale((_, window_height): (u32, u32)) -> u32 {
+    (window_height / 240).max(1)
+}
+
+fn line_height(scale: u32) -> u32 {
+    (font::GLYPH_HEIGHT + 2) * scale
+}
+
+// the debug panels take a column on the right and rows along the bottom, and the
+// screen gets the rest: (screen, side panel, memory panel)
+fn panel_layout(window: (u32, u32)) -> (Rect, Rect, Rect) {
+    let (window_width, window_height) = window;
+    let scale = text_scale(window);
+    let margin = 2 * scale;
+    let side_width = (panels::COLUMNS as u32 * font::text_size(" ").0 * scale + 2 * margin)
+        .min(window_width.saturating_sub(1));
+    let memory_height =
+        (MEMORY_ROWS * line_height(scale) + 2 * margin).min(window_height.saturating_sub(1));
+    let screen_width = window_width - side_width;
+    let screen_height = window_height - memory_height;
+    (
+        Rect::new(0, 0, screen_width, screen_height),
+        Rect::new(screen_width as i32, 0, side_width, window_height),
+        Rect::new(0, screen_height as i32, screen_width, memory_height),
+    )
+}
 
 pub struct Graphics {
     canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    // the framebuffer is uploaded here every frame, then stretched over the viewport
+    texture: Texture,
+    texture_size: (u32, u32),
+    display: DisplayOptions,
+    filter: Option<PhosphorFilter>,
+    // where each line of disassembly was last drawn, for clicking on
+    clickable: Vec<(Rect, u16)>,
 }
 
 impl Graphics {
-    pub fn new(sdl_context: &Sdl) -> Self {
+    pub fn new(sdl_context: &Sdl, display: DisplayOptions) -> Self {
         let video_subsystem = sdl_context
             .video()
             .expect("Unable to initialise video subsystem");
 
-        let window = video_subsystem
-            // screen must be in the ratio 64x32
-            .window("Chip8 Emulator", 960, 480)
-            .position_centered()
-            .build()
-            .expect("Unable to create window");
+        let (width, height) = if display.panels {
+            PANELS_WINDOW_SIZE
+        } else {
+            (SCREEN_WIDTH * WINDOW_SCALE, SCREEN_WIDTH / 2 * WINDOW_SCALE)
+        };
+        let mut window = video_subsystem.window("Chip8 Emulator", width, height);
+        window.position_centered().resizable();
+        if display.fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().expect("Unable to create window");
 
         let canvas = window
             .into_canvas()
             .build()
             .expect("Unable to create canvas");
-        Self { canvas }
+        let texture_creator = canvas.texture_creator();
+        let texture_size = (SCREEN_WIDTH, SCREEN_WIDTH / 2);
+        let texture = Self::create_texture(&texture_creator, texture_size);
+        Self {
+            canvas,
+            texture_creator,
+            texture,
+            texture_size,
+            display,
+            filter: display.phosphor.map(PhosphorFilter::new),
+            clickable: Vec::new(),
+        }
     }
 
-    pub fn render(&mut self, memory: &Memory) {
-        self.canvas.set_draw_color(Color::WHITE);
-        self.canvas.clear();
+    fn create_texture(
+        texture_creator: &TextureCreator<WindowContext>,
+        (width, height): (u32, u32),
+    ) -> Texture {
+        texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+            .expect("Unable to create texture")
+    }
+
+    pub fn toggle_grid(&mut self) {
+        self.display.grid = !self.display.grid;
+    }
+
+    // fullscreen at the desktop's resolution, so switching doesn't change the video mode
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(error) = window.set_fullscreen(fullscreen) {
+            eprintln!("Unable to change fullscreen: {error}");
+        }
+    }
+
+    pub fn shows_panels(&self) -> bool {
+        self.display.panels
+    }
+
+    // the address of the line of disassembly at a point in the window, if there is one
+    pub fn address_at(&self, x: i32, y: i32) -> Option<u16> {
+        // the renderer can have more pixels than the window on high DPI screens
         let (window_width, window_height) = self.canvas.window().size();
-        let square_size = window_width / 64;
-
-        self.canvas.set_draw_color(Color::BLACK);
-
-        // drawing the grid squares
-        for y in 0..32 {
-            for x in 0..64 {
-                if memory.get8_framebuffer(x, y) == 1 {
-                    self.canvas
-                        .fill_rect(Rect::new(
-                            (x as i32) * (square_size as i32),
-                            (y as i32) * (square_size as i32),
-                            square_size,
-                            square_size,
-                        ))
-                        .unwrap();
-                }
+        let (output_width, output_height) = self.canvas.output_size().ok()?;
+        let point = Point::new(
+            (x as i64 * output_width as i64 / window_width.max(1) as i64) as i32,
+            (y as i64 * output_height as i64 / window_height.max(1) as i64) as i32,
+        );
+        self.clickable
+            .iter()
+            .find(|(rect, _)| rect.contains_point(point))
+            .map(|(_, address)| *address)
+    }
+
+    // lines of text are drawn over the top of the screen, and the panels around it
+    pub fn render(
+        &mut self,
+        memory: &Memory,
+        palette: &Palette,
+        text: &[String],
+        debug: Option<&DebugView>,
+    ) {
+        let framebuffer = memory.framebuffer();
+        let screen_size = (SCREEN_WIDTH, framebuffer.len() as u32 / SCREEN_WIDTH);
+        if screen_size != self.texture_size {
+            self.texture = Self::create_texture(&self.texture_creator, screen_size);
+            self.texture_size = screen_size;
+        }
+
+        let pixels: Vec<u8> = match &mut self.filter {
+            Some(filter) => filter.apply(framebuffer, palette).concat(),
+            None => framebuffer
+                .iter()
+                .flat_map(|pixel| palette.color(*pixel))
+                .collect(),
+        };
+        self.texture
+            .update(None, &pixels, SCREEN_WIDTH as usize * 3)
+            .expect("Unable to update texture");
+
+        // the letterbox bars
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        let output_size = self.canvas.output_size().unwrap_or((0, 0));
+        let screen_area = match debug {
+            Some(view) => {
+                let (screen_area, side, memory) = panel_layout(output_size);
+                self.draw_panels(view, side, memory);
+                screen_area
             }
+            None => Rect::new(0, 0, output_size.0.max(1), output_size.1.max(1)),
+        };
+        let mut area = viewport(
+            screen_area.size(),
+            screen_size,
+            self.display.integer_scaling,
+        );
+        area.offset(screen_area.x(), screen_area.y());
+        self.canvas.copy(&self.texture, None, area).unwrap();
+
+        if self.display.grid {
+            self.draw_grid(area, screen_size, palette);
         }
+        self.draw_text(text, output_size);
+        self.canvas.present();
+    }
+
+    // white on a dark box in the top left
+    fn draw_text(&mut self, lines: &[String], window: (u32, u32)) {
+        if lines.is_empty() {
+            return;
+        }
+        let scale = text_scale(window);
+        let margin = 2 * scale;
+        let width = lines
+            .iter()
+            .map(|line| font::text_size(line).0 * scale)
+            .max()
+            .unwrap_or(0);
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.canvas
+            .fill_rect(Rect::new(
+                0,
+                0,
+                width + 2 * margin,
+                line_height(scale) * lines.len() as u32 + 2 * margin,
+            ))
+            .unwrap();
+        self.canvas.set_blend_mode(BlendMode::None);
 
-        // drawing the vertical grid lines
-        for i in 1..64 {
-            let x = (i as i32) * (window_width as i32 / 64);
+        for (index, line) in lines.iter().enumerate() {
+            let top = margin + index as u32 * line_height(scale);
+            self.draw_string(line, margin as i32, top as i32, scale);
+        }
+    }
+
+    // one line of white text, with its top left at x, y
+    fn draw_string(&mut self, text: &str, x: i32, y: i32, scale: u32) {
+        // centred in the line's height
+        let y = y + scale as i32;
+        let rects: Vec<Rect> = font::text_pixels(text)
+            .into_iter()
+            .map(|(dx, dy)| {
+                Rect::new(
+                    x + (dx * scale) as i32,
+                    y + (dy * scale) as i32,
+                    scale,
+                    scale,
+                )
+            })
+            .collect();
+        self.canvas.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+        self.canvas.fill_rects(&rects).unwrap();
+    }
+
+    fn draw_panels(&mut self, view: &DebugView, side: Rect, memory: Rect) {
+        let scale = text_scale(side.size());
+        let margin = 2 * scale;
+        let header_lines = view.side_panel(0).len() as u32;
+        let disassembly_lines = (side.height().saturating_sub(2 * margin) / line_height(scale))
+            .saturating_sub(header_lines);
+
+        self.clickable.clear();
+        self.canvas.set_draw_color(Color::RGB(0x20, 0x20, 0x20));
+        self.canvas.fill_rects(&[side, memory]).unwrap();
+        let side_lines = view.side_panel(disassembly_lines as usize);
+        self.draw_panel(&side_lines, side, scale);
+        self.draw_panel(&view.memory_panel(MEMORY_ROWS as usize), memory, scale);
+    }
+
+    fn draw_panel(&mut self, lines: &[PanelLine], area: Rect, scale: u32) {
+        let margin = 2 * scale;
+        let char_width = font::text_size(" ").0 * scale;
+        for (index, line) in lines.iter().enumerate() {
+            let x = area.x() + margin as i32;
+            let y = area.y() + (margin + index as u32 * line_height(scale)) as i32;
+            let line_rect = Rect::new(x, y, area.width() - 2 * margin, line_height(scale));
+            if let Some((start, length)) = line.highlight {
+                self.canvas.set_draw_color(Color::RGB(0x30, 0x40, 0x90));
+                self.canvas
+                    .fill_rect(Rect::new(
+                        x + (start as u32 * char_width) as i32,
+                        y,
+                        length as u32 * char_width,
+                        line_height(scale),
+                    ))
+                    .unwrap();
+            }
+            if let Some(address) = line.address {
+                self.clickable.push((line_rect, address));
+            }
+            self.draw_string(&line.text, x, y, scale);
+        }
+    }
+
+    // lines between the pixels, placed the same way the texture is stretched so they
+    // still line up when the scale isn't a whole number
+    fn draw_grid(&mut self, area: Rect, (width, height): (u32, u32), palette: &Palette) {
+        self.canvas.set_draw_color(sdl_color(palette.grid));
+
+        for i in 1..width {
+            let x = area.x() + (i * area.width() / width) as i32;
             self.canvas
-                .draw_line(Point::new(x, 0), Point::new(x, window_height as i32))
+                .draw_line(Point::new(x, area.top()), Point::new(x, area.bottom() - 1))
                 .unwrap();
         }
 
-        // drawing the horizontal grid lines
-        for i in 1..32 {
-            let y = (i as i32) * (window_height as i32 / 32);
+        for i in 1..height {
+            let y = area.y() + (i * area.height() / height) as i32;
             self.canvas
-                .draw_line(Point::new(0, y), Point::new(window_width as i32, y))
+                .draw_line(Point::new(area.left(), y), Point::new(area.right() - 1, y))
                 .unwrap();
         }
+    }
+}
 
-        self.canvas.present();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewport() {
+        // an exact fit
+        assert_eq!(
+            viewport((960, 480), (64, 32), false),
+            Rect::new(0, 0, 960, 480)
+        );
+        // too tall, so bars above and below
+        assert_eq!(
+            viewport((640, 480), (64, 32), false),
+            Rect::new(0, 80, 640, 320)
+        );
+        // too wide, so bars on each side
+        assert_eq!(
+            viewport((1920, 800), (64, 32), false),
+            Rect::new(160, 0, 1600, 800)
+        );
+        // 1000 / 64 is 15.6, rounded down to 15
+        assert_eq!(
+            viewport((1000, 600), (64, 32), true),
+            Rect::new(20, 60, 960, 480)
+        );
+        assert_eq!(
+            viewport((1000, 600), (128, 64), true),
+            Rect::new(52, 76, 896, 448)
+        );
+        // smaller than the screen
+        assert_eq!(viewport((32, 32), (64, 32), true), Rect::new(0, 8, 32, 16));
+    }
+
+    #[test]
+    fn test_panel_layout() {
+        // text is 3 window pixels per font pixel at 720 high, so the column is
+        // 28 characters of 18 pixels, plus a margin of 6 on each side
+        let (screen, side, memory) = panel_layout((1280, 720));
+        assert_eq!(side, Rect::new(764, 0, 516, 720));
+        assert_eq!(memory, Rect::new(0, 492, 764, 228));
+        assert_eq!(screen, Rect::new(0, 0, 764, 492));
+        // too small for anything to fit, but still something to draw into
+        let (screen, _, _) = panel_layout((10, 10));
+        assert_eq!(screen.size(), (1, 1));
     }
 }
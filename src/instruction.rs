@@ -1,9 +1,13 @@
+use std::fmt;
+
 use crate::register::Register;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Instruction {
     CLS,
+    RET,                         // (0x00EE) RET (Return from a subroutine)
     JP(u16),                     // (0x1NNN) JP NNN
+    CALL(u16),                   // (0x2NNN) CALL NNN (Call subroutine at NNN)
     SEImm(Register, u8),         // (0x3XNN) SE Vx, NN (Skip next instruction if Vx == NN)
     SNE(Register, u8),           // (0x4XNN) SNE Vx, NN (Skip next instruction if Vx != NN)
     SEDir(Register, Register),   // (0x5XY0) SE Vx, Vy (Skip next instruction if Vx == Vy)
@@ -18,22 +22,41 @@ pub enum Instruction {
     SHR(Register),               // (0x8XY6) SHR Vx (Set Vx = Vx >> 1, set VF = Vx & 0b1)
     SUBN(Register, Register),    // (0x8XY7) SUBN Vx, Vy (Set Vx = Vy - Vx, set VF = NOT borrow)
     SHL(Register),               // (0x8XYE) SHL Vx (Set Vx = Vx << 1, set VF = Vx & 0b1000_0000)
+    SNEDir(Register, Register),  // (0x9XY0) SNE Vx, Vy (Skip next instruction if Vx != Vy)
     LDI(u16),                    // (0xANNN) LD I, NNN (Set I = NNN)
     JPOff(u16),                  // (0xBNNN) JP V0, NNN (Jump to address V0 + NNN)
+    RND(Register, u8),           // (0xCXNN) RND Vx, NN (Set Vx = random byte AND NN)
     DRW(Register, Register, u8), // (0xDXYN) DRW Vx, Vy, N
+    SKP(Register),               // (0xEX9E) SKP Vx (Skip next instruction if key Vx is down)
+    SKNP(Register),              // (0xEXA1) SKNP Vx (Skip next instruction if key Vx is up)
     LDVDT(Register),             // (0xFX07) LD Vx, DT
     LDK(Register),               // (0xFX0A) LD Vx, K
     LDDT(Register),              // (0xFX15) LD DT, Vx
+    LDST(Register),              // (0xFX18) LD ST, Vx
+    ADDI(Register),              // (0xFX1E) ADD I, Vx (Set I = I + Vx)
+    LDF(Register),               // (0xFX29) LD F, Vx (Set I = sprite address for digit Vx)
+    LDB(Register),               // (0xFX33) LD B, Vx (Store Vx in BCD at I, I+1 and I+2)
+    LDMem(Register),             // (0xFX55) LD [I], Vx (Store V0 to Vx from I onwards)
+    LDReg(Register),             // (0xFX65) LD Vx, [I] (Read V0 to Vx from I onwards)
 }
 
 impl Instruction {
     pub fn decode(instruction: u16) -> Instruction {
-        if instruction == 0x00E0 {
-            return Instruction::CLS;
+        match Instruction::try_decode(instruction) {
+            Ok(decoded) => decoded,
+            Err(message) => panic!("{}", message),
         }
+    }
 
-        // DEBUGGING PURPOSES
-        /* println!("instruction: {:#4x}", instruction); */
+    // same as decode, but returns an error instead of panicking
+    // used by the disassembler, where data is mixed in with code
+    pub fn try_decode(instruction: u16) -> Result<Instruction, String> {
+        if instruction == 0x00E0 {
+            return Ok(Instruction::CLS);
+        }
+        if instruction == 0x00EE {
+            return Ok(Instruction::RET);
+        }
 
         // seperate the instruction into two bytes, and then extract the nibbles
         let [b1, b2] = instruction.to_be_bytes();
@@ -46,8 +69,9 @@ impl Instruction {
         let vx = Register::v_register_from(n2);
         let vy = Register::v_register_from(n3);
 
-        match n1 {
+        let decoded = match n1 {
             1 => Instruction::JP(((n2 as u16) << 8) | (b2 as u16)),
+            2 => Instruction::CALL(((n2 as u16) << 8) | (b2 as u16)),
             3 => Instruction::SEImm(vx, b2),
             4 => Instruction::SNE(vx, b2),
             5 => Instruction::SEDir(vx, vy),
@@ -63,20 +87,92 @@ impl Instruction {
                 6 => Instruction::SHR(vx),
                 7 => Instruction::SUBN(vx, vy),
                 0xE => Instruction::SHL(vx),
-                _ => panic!("Could not decode instruction {:#04X}", instruction),
+                _ => return Err(format!("Could not decode instruction {:#04X}", instruction)),
             },
+            9 if n4 == 0 => Instruction::SNEDir(vx, vy),
             0xA => Instruction::LDI(((n2 as u16) << 8) | (b2 as u16)),
             0xB => Instruction::JPOff(((n2 as u16) << 8) | (b2 as u16)),
+            0xC => Instruction::RND(vx, b2),
             0xD => Instruction::DRW(vx, vy, n4),
+            0xE => match b2 {
+                0x9E => Instruction::SKP(vx),
+                0xA1 => Instruction::SKNP(vx),
+                _ => return Err(format!("Could not decode instruction {:#04X}", instruction)),
+            },
             0xF => match b2 {
                 0x07 => Instruction::LDVDT(Register::v_register_from(n2)),
                 0x0A => Instruction::LDK(Register::v_register_from(n2)),
                 0x15 => Instruction::LDDT(Register::v_register_from(n2)),
-                _ => panic!("Could not decode instruction {:#04X}", instruction),
+                0x18 => Instruction::LDST(vx),
+                0x1E => Instruction::ADDI(vx),
+                0x29 => Instruction::LDF(vx),
+                0x33 => Instruction::LDB(vx),
+                0x55 => Instruction::LDMem(vx),
+                0x65 => Instruction::LDReg(vx),
+                _ => return Err(format!("Could not decode instruction {:#04X}", instruction)),
             },
             _ => {
-                panic!("Could not decode instruction {:#04X} at all", instruction)
+                return Err(format!(
+                    "Could not decode instruction {:#04X} at all",
+                    instruction
+                ))
             }
+        };
+
+        Ok(decoded)
+    }
+}
+
+impl Instruction {
+    // the kind of instruction, e.g. "DRW" or "LD"
+    pub fn kind(&self) -> String {
+        let mnemonic = self.to_string();
+        mnemonic
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    }
+}
+
+// canonical mnemonics, in the same form as the comments next to each variant
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::CLS => write!(f, "CLS"),
+            Instruction::RET => write!(f, "RET"),
+            Instruction::JP(nnn) => write!(f, "JP {:#05X}", nnn),
+            Instruction::CALL(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SEImm(vx, nn) => write!(f, "SE {}, {:#04X}", vx, nn),
+            Instruction::SNE(vx, nn) => write!(f, "SNE {}, {:#04X}", vx, nn),
+            Instruction::SEDir(vx, vy) => write!(f, "SE {}, {}", vx, vy),
+            Instruction::LDImm(vx, nn) => write!(f, "LD {}, {:#04X}", vx, nn),
+            Instruction::ADDImm(vx, nn) => write!(f, "ADD {}, {:#04X}", vx, nn),
+            Instruction::LDDir(vx, vy) => write!(f, "LD {}, {}", vx, vy),
+            Instruction::OR(vx, vy) => write!(f, "OR {}, {}", vx, vy),
+            Instruction::AND(vx, vy) => write!(f, "AND {}, {}", vx, vy),
+            Instruction::XOR(vx, vy) => write!(f, "XOR {}, {}", vx, vy),
+            Instruction::ADDDir(vx, vy) => write!(f, "ADD {}, {}", vx, vy),
+            Instruction::SUB(vx, vy) => write!(f, "SUB {}, {}", vx, vy),
+            Instruction::SHR(vx) => write!(f, "SHR {}", vx),
+            Instruction::SUBN(vx, vy) => write!(f, "SUBN {}, {}", vx, vy),
+            Instruction::SHL(vx) => write!(f, "SHL {}", vx),
+            Instruction::SNEDir(vx, vy) => write!(f, "SNE {}, {}", vx, vy),
+            Instruction::LDI(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JPOff(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::RND(vx, nn) => write!(f, "RND {}, {:#04X}", vx, nn),
+            Instruction::DRW(vx, vy, n) => write!(f, "DRW {}, {}, {}", vx, vy, n),
+            Instruction::SKP(vx) => write!(f, "SKP {}", vx),
+            Instruction::SKNP(vx) => write!(f, "SKNP {}", vx),
+            Instruction::LDVDT(vx) => write!(f, "LD {}, DT", vx),
+            Instruction::LDK(vx) => write!(f, "LD {}, K", vx),
+            Instruction::LDDT(vx) => write!(f, "LD DT, {}", vx),
+            Instruction::LDST(vx) => write!(f, "LD ST, {}", vx),
+            Instruction::ADDI(vx) => write!(f, "ADD I, {}", vx),
+            Instruction::LDF(vx) => write!(f, "LD F, {}", vx),
+            Instruction::LDB(vx) => write!(f, "LD B, {}", vx),
+            Instruction::LDMem(vx) => write!(f, "LD [I], {}", vx),
+            Instruction::LDReg(vx) => write!(f, "LD {}, [I]", vx),
         }
     }
 }
@@ -193,4 +289,72 @@ mod tests {
             Instruction::SHL(Register::v_register_from(0))
         )
     }
+
+    #[test]
+    fn test_decode_rnd() {
+        assert_eq!(
+            Instruction::decode(0xC30F),
+            Instruction::RND(Register::v_register_from(3), 0x0F)
+        )
+    }
+
+    #[test]
+    fn test_decode_call() {
+        assert_eq!(Instruction::decode(0x2300), Instruction::CALL(0x300))
+    }
+
+    #[test]
+    fn test_decode_ret() {
+        assert_eq!(Instruction::decode(0x00EE), Instruction::RET)
+    }
+
+    #[test]
+    fn test_decode_skp() {
+        assert_eq!(
+            Instruction::decode(0xE29E),
+            Instruction::SKP(Register::v_register_from(2))
+        );
+        assert_eq!(
+            Instruction::decode(0xE2A1),
+            Instruction::SKNP(Register::v_register_from(2))
+        );
+    }
+
+    #[test]
+    fn test_decode_sne_dir() {
+        assert_eq!(
+            Instruction::decode(0x9120),
+            Instruction::SNEDir(Register::v_register_from(1), Register::v_register_from(2))
+        )
+    }
+
+    #[test]
+    fn test_decode_fx() {
+        let v3 = Register::v_register_from(3);
+        assert_eq!(Instruction::decode(0xF318), Instruction::LDST(v3));
+        assert_eq!(Instruction::decode(0xF31E), Instruction::ADDI(v3));
+        assert_eq!(Instruction::decode(0xF329), Instruction::LDF(v3));
+        assert_eq!(Instruction::decode(0xF333), Instruction::LDB(v3));
+        assert_eq!(Instruction::decode(0xF355), Instruction::LDMem(v3));
+        assert_eq!(Instruction::decode(0xF365), Instruction::LDReg(v3));
+    }
+
+    #[test]
+    fn test_try_decode_invalid() {
+        assert!(Instruction::try_decode(0xFFFF).is_err());
+        assert!(Instruction::try_decode(0x8008).is_err());
+        assert!(Instruction::try_decode(0xE09F).is_err());
+        assert!(Instruction::try_decode(0x9121).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Instruction::decode(0x600A).to_string(), "LD V0, 0x0A");
+        assert_eq!(Instruction::decode(0xA300).to_string(), "LD I, 0x300");
+        assert_eq!(Instruction::decode(0xD015).to_string(), "DRW V0, V1, 5");
+        assert_eq!(Instruction::decode(0x1206).to_string(), "JP 0x206");
+        assert_eq!(Instruction::decode(0xF40A).to_string(), "LD V4, K");
+        assert_eq!(Instruction::decode(0xF455).to_string(), "LD [I], V4");
+        assert_eq!(Instruction::decode(0xF465).to_string(), "LD V4, [I]");
+    }
 }
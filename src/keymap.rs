@@ -0,0 +1,247 @@
+#[cfg(feature = "sdl")]
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::frontend::InputEvent;
+use crate::key::Key;
+
+// Which keyboard keys press which keypad keys. The presets put the keypad's 4x4 grid on
+// the same physical keys whatever the layout, the ones that are 1234/QWER/ASDF/ZXCV on
+// QWERTY:
+//
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+//
+// except numpad, which uses the digits on the numpad and the keys around them for A-F.
+
+// the names of the keys for keypad 0 to F, separated by spaces
+const PRESETS: [(&str, &str); 5] = [
+    ("qwerty", "x 1 2 3 q w e a s d z c 4 r f v"),
+    ("azerty", "x & é \" a z e q s d w c ' r f v"),
+    ("qwertz", "x 1 2 3 q w e a s d y c 4 r f v"),
+    ("dvorak", "q 1 2 3 ' , . a o e ; j 4 p u k"),
+    (
+        "numpad",
+        "kp0 kp1 kp2 kp3 kp4 kp5 kp6 kp7 kp8 kp9 kpenter kp. kp/ kp* kp- kp+",
+    ),
+];
+
+// the emulator controls that can be put on keys, and where they are by default
+const HOTKEYS: [(&str, InputEvent, &str); 5] = [
+    ("pause", InputEvent::Pause, "space"),
+    ("advance", InputEvent::FrameAdvance, "]"),
+    ("fast-forward", InputEvent::FastForward, "="),
+    ("slow-motion", InputEvent::SlowMotion, "-"),
+    ("reset", InputEvent::Reset, "delete"),
+];
+
+// a key on whatever the frontend reads the keyboard with, found by the names keymaps use
+pub trait KeyName: Copy + Eq + Hash {
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+// a single character, one of a few common names, or anything SDL knows the name of
+#[cfg(feature = "sdl")]
+fn keycode_from_name(name: &str) -> Option<Keycode> {
+    let lowercase = name.to_lowercase();
+    let mut chars = lowercase.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Keycode::from_i32(c as i32);
+    }
+    let keycode = match lowercase.as_str() {
+        "space" => Keycode::Space,
+        "enter" | "return" => Keycode::Return,
+        "up" => Keycode::Up,
+        "down" => Keycode::Down,
+        "left" => Keycode::Left,
+        "right" => Keycode::Right,
+        "tab" => Keycode::Tab,
+        "insert" => Keycode::Insert,
+        "delete" => Keycode::Delete,
+        "home" => Keycode::Home,
+        "end" => Keycode::End,
+        "pageup" => Keycode::PageUp,
+        "pagedown" => Keycode::PageDown,
+        "kp0" => Keycode::Kp0,
+        "kp1" => Keycode::Kp1,
+        "kp2" => Keycode::Kp2,
+        "kp3" => Keycode::Kp3,
+        "kp4" => Keycode::Kp4,
+        "kp5" => Keycode::Kp5,
+        "kp6" => Keycode::Kp6,
+        "kp7" => Keycode::Kp7,
+        "kp8" => Keycode::Kp8,
+        "kp9" => Keycode::Kp9,
+        "kp." => Keycode::KpPeriod,
+        "kp/" => Keycode::KpDivide,
+        "kp*" => Keycode::KpMultiply,
+        "kp-" => Keycode::KpMinus,
+        "kp+" => Keycode::KpPlus,
+        "kpenter" => Keycode::KpEnter,
+        _ => return Keycode::from_name(name),
+    };
+    Some(keycode)
+}
+
+#[cfg(feature = "sdl")]
+impl KeyName for Keycode {
+    fn from_name(name: &str) -> Option<Self> {
+        keycode_from_name(name)
+    }
+}
+
+// A byte typed on a terminal: ASCII characters and the few named keys that send one byte.
+// Delete is the byte Backspace sends, since the Delete key sends an escape sequence.
+impl KeyName for u8 {
+    fn from_name(name: &str) -> Option<Self> {
+        let lowercase = name.to_lowercase();
+        match lowercase.as_bytes() {
+            [byte] if byte.is_ascii_graphic() => Some(*byte),
+            _ => match lowercase.as_str() {
+                "space" => Some(b' '),
+                "enter" | "return" => Some(b'\r'),
+                "tab" => Some(b'\t'),
+                "delete" => Some(0x7F),
+                _ => None,
+            },
+        }
+    }
+}
+
+// names separated by spaces
+fn keys_from_names<K: KeyName>(names: &str) -> Result<Vec<K>, String> {
+    names
+        .split_whitespace()
+        .map(|name| K::from_name(name).ok_or(format!("Unknown key name '{name}'")))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap<K: KeyName> {
+    keys: HashMap<K, Key>,
+    hotkeys: HashMap<K, InputEvent>,
+}
+
+impl<K: KeyName> Default for Keymap<K> {
+    fn default() -> Self {
+        Keymap::preset("qwerty").unwrap()
+    }
+}
+
+impl<K: KeyName> Keymap<K> {
+    // keys the frontend has no name for, like the numpad on a terminal, are left out
+    pub fn preset(name: &str) -> Option<Self> {
+        let (_, names) = PRESETS.iter().find(|(preset, _)| *preset == name)?;
+        let mut keys = HashMap::new();
+        for (value, name) in names.split(' ').enumerate() {
+            if let Some(keycode) = K::from_name(name) {
+                keys.insert(keycode, Key::try_from(value as u8).unwrap());
+            }
+        }
+        let hotkeys = HOTKEYS
+            .iter()
+            .filter_map(|(_, event, name)| Some((K::from_name(name)?, *event)))
+            .collect();
+        Some(Keymap { keys, hotkeys })
+    }
+
+    // keypad key is a hex digit and names are separated by spaces, e.g. "5" and "w up".
+    // They replace whatever the keypad key was on before.
+    pub fn bind(&mut self, key: &str, names: &str) -> Result<(), String> {
+        let key = u8::from_str_radix(key, 16)
+            .map_err(|_| format!("Invalid keypad key '{key}'"))
+            .and_then(Key::try_from)?;
+        let keycodes = keys_from_names(names)?;
+        self.keys.retain(|_, bound| *bound != key);
+        for keycode in keycodes {
+            self.hotkeys.remove(&keycode);
+            self.keys.insert(keycode, key);
+        }
+        Ok(())
+    }
+
+    // like bind, but for one of the emulator controls, e.g. "pause" and "p"
+    pub fn bind_hotkey(&mut self, action: &str, names: &str) -> Result<(), String> {
+        let (_, event, _) = HOTKEYS
+            .iter()
+            .find(|(name, _, _)| *name == action)
+            .ok_or(format!("Unknown hotkey '{action}'"))?;
+        let keycodes = keys_from_names(names)?;
+        self.hotkeys.retain(|_, bound| bound != event);
+        for keycode in keycodes {
+            self.keys.remove(&keycode);
+            self.hotkeys.insert(keycode, *event);
+        }
+        Ok(())
+    }
+
+    pub fn hotkey(&self, keycode: K) -> Option<InputEvent> {
+        self.hotkeys.get(&keycode).copied()
+    }
+
+    // None for keys that aren't on the keypad
+    pub fn get(&self, keycode: K) -> Option<Key> {
+        self.keys.get(&keycode).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sdl")]
+    #[test]
+    fn test_presets() {
+        for (name, _) in PRESETS {
+            assert_eq!(Keymap::<Keycode>::preset(name).unwrap().keys.len(), 16);
+        }
+        let keymap = Keymap::default();
+        assert_eq!(keymap.get(Keycode::Num4), Some(Key::CodeC));
+        assert_eq!(keymap.get(Keycode::X), Some(Key::Code0));
+        assert_eq!(keymap.get(Keycode::P), None);
+        let keymap = Keymap::preset("azerty").unwrap();
+        assert_eq!(keymap.get(Keycode::Ampersand), Some(Key::Code1));
+        assert_eq!(keymap.get(Keycode::Z), Some(Key::Code5));
+        let keymap = Keymap::preset("dvorak").unwrap();
+        assert_eq!(keymap.get(Keycode::Comma), Some(Key::Code5));
+        let keymap = Keymap::preset("numpad").unwrap();
+        assert_eq!(keymap.get(Keycode::Kp7), Some(Key::Code7));
+        assert_eq!(keymap.get(Keycode::KpEnter), Some(Key::CodeA));
+        assert!(Keymap::<Keycode>::preset("colemak").is_none());
+    }
+
+    #[cfg(feature = "sdl")]
+    #[test]
+    fn test_bind() {
+        let mut keymap = Keymap::default();
+        keymap.bind("5", "Space up").unwrap();
+        assert_eq!(keymap.get(Keycode::Space), Some(Key::Code5));
+        assert_eq!(keymap.get(Keycode::Up), Some(Key::Code5));
+        // W isn't 5 any more
+        assert_eq!(keymap.get(Keycode::W), None);
+        keymap.bind("a", "W").unwrap();
+        assert_eq!(keymap.get(Keycode::W), Some(Key::CodeA));
+        assert_eq!(keymap.bind("g", "w").unwrap_err(), "Invalid keypad key 'g'");
+    }
+
+    #[test]
+    fn test_terminal_bytes() {
+        let keymap = Keymap::<u8>::default();
+        assert_eq!(keymap.get(b'4'), Some(Key::CodeC));
+        assert_eq!(keymap.hotkey(b' '), Some(InputEvent::Pause));
+        // é is more than one byte, so azerty's 2 isn't on a terminal
+        let keymap = Keymap::<u8>::preset("azerty").unwrap();
+        assert_eq!(keymap.keys.len(), 15);
+        assert!(Keymap::<u8>::preset("numpad").unwrap().keys.is_empty());
+
+        let mut keymap = Keymap::<u8>::default();
+        keymap.bind("5", "Space I").unwrap();
+        assert_eq!(keymap.get(b' '), Some(Key::Code5));
+        assert_eq!(keymap.get(b'i'), Some(Key::Code5));
+        assert_eq!(keymap.hotkey(b' '), None);
+        assert_eq!(keymap.bind("5", "up").unwrap_err(), "Unknown key name 'up'");
+    }
+}
@@ -0,0 +1,43 @@
+pub mod capture;
+pub mod cartridge;
+pub mod config;
+pub mod control;
+#[cfg(feature = "sdl")]
+pub mod controller;
+pub mod debugger;
+pub mod disassembler;
+pub mod emulator;
+pub mod font;
+pub mod frontend;
+pub mod gdb;
+pub mod gif;
+#[cfg(feature = "sdl")]
+pub mod graphics;
+pub mod hex;
+pub mod instruction;
+pub mod json;
+pub mod key;
+pub mod keymap;
+pub mod libretro;
+pub mod memory;
+pub mod movie;
+pub mod octo;
+pub mod osd;
+pub mod palette;
+pub mod panels;
+pub mod phosphor;
+pub mod quirks;
+pub mod register;
+pub mod rewind;
+pub mod rom_database;
+pub mod savestate;
+pub mod screenshot;
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(feature = "sdl")]
+pub mod sdl_context;
+pub mod sha1;
+pub mod tracer;
+pub mod tty;
+#[cfg(feature = "wasm")]
+pub mod wasm;
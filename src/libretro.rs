@@ -0,0 +1,557 @@
+// the safety contract for every entry point is the one libretro.h spells out
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use crate::emulator::Chip8;
+use crate::key::Key;
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+use crate::savestate;
+
+// The libretro API, so RetroArch and other libretro frontends can load the emulator as a
+// core. Build it without SDL with `cargo build --release --lib --no-default-features`,
+// then install libchip8_emulator.so as chip8_libretro.so.
+//
+// Nothing that goes wrong in the core should take the frontend down with it: a program
+// that ends or reaches an instruction it can't run is halted, leaving its last frame on
+// screen, and every entry point that does more than return a constant catches panics
+// before they can unwind into the frontend's C code.
+
+const API_VERSION: c_uint = 1;
+
+const DEVICE_JOYPAD: c_uint = 1;
+const DEVICE_KEYBOARD: c_uint = 3;
+
+const JOYPAD_B: c_uint = 0;
+const JOYPAD_UP: c_uint = 4;
+const JOYPAD_DOWN: c_uint = 5;
+const JOYPAD_LEFT: c_uint = 6;
+const JOYPAD_RIGHT: c_uint = 7;
+const JOYPAD_A: c_uint = 8;
+
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const REGION_NTSC: c_uint = 0;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const FRAMES_PER_SECOND: f64 = 60.0;
+const SAMPLE_RATE: usize = 44100;
+// the beep while the sound timer is running, a square wave at about 440 Hz
+const TONE_PERIOD: usize = SAMPLE_RATE / 440;
+const TONE_VOLUME: i16 = 0x1000;
+
+// the same buttons as the SDL frontend's gamepad defaults
+const BUTTONS: [(c_uint, Key); 6] = [
+    (JOYPAD_UP, Key::Code5),
+    (JOYPAD_LEFT, Key::Code7),
+    (JOYPAD_DOWN, Key::Code8),
+    (JOYPAD_RIGHT, Key::Code9),
+    (JOYPAD_A, Key::Code6),
+    (JOYPAD_B, Key::Code4),
+];
+
+// keys 0 to F on a QWERTY keyboard, like the qwerty keymap. Letter and digit keys have
+// their ASCII codes in libretro.
+const KEYBOARD: &[u8; 16] = b"x123qweasdzc4rfv";
+
+type EnvironmentFn = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn =
+    extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = extern "C" fn();
+type InputStateFn = extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+// the core options, as "description; first value is the default|other values"
+const OPTIONS: [(&CStr, &CStr); 4] = [
+    (
+        c"chip8_vf_reset",
+        c"AND, OR and XOR reset VF; disabled|enabled",
+    ),
+    (
+        c"chip8_jump_vx",
+        c"BXNN jumps to XNN + VX; disabled|enabled",
+    ),
+    (
+        c"chip8_clip",
+        c"Clip sprites at the screen edge; disabled|enabled",
+    ),
+    (c"chip8_speed", c"Speed; 1x|2x|4x|8x|16x|32x|64x"),
+];
+
+#[derive(Default)]
+struct Core {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+    chip8: Option<Chip8>,
+    // the program has stopped, so no more frames are run until it's reset or a state is
+    // loaded
+    halted: bool,
+    quirks: Quirks,
+    // frames run for every frame the frontend shows
+    speed: u32,
+    video: Vec<u32>,
+    palette: Palette,
+    audio: Vec<i16>,
+    // how far into the square wave's period the next sample is, so the beep doesn't
+    // click between frames
+    tone_phase: usize,
+}
+
+impl Core {
+    fn variable(&self, key: &CStr) -> Option<String> {
+        let environment = self.environment?;
+        let mut variable = Variable {
+            key: key.as_ptr(),
+            value: ptr::null(),
+        };
+        let data = &mut variable as *mut Variable as *mut c_void;
+        if !environment(ENVIRONMENT_GET_VARIABLE, data) || variable.value.is_null() {
+            return None;
+        }
+        // only valid until the next call into the frontend, so copied straight away
+        let value = unsafe { CStr::from_ptr(variable.value) };
+        Some(value.to_string_lossy().into_owned())
+    }
+
+    fn read_options(&mut self) {
+        let enabled = |key| self.variable(key).as_deref() == Some("enabled");
+        self.quirks = Quirks {
+            logic_resets_vf: enabled(c"chip8_vf_reset"),
+            jump_uses_vx: enabled(c"chip8_jump_vx"),
+            clip_sprites: enabled(c"chip8_clip"),
+        };
+        self.speed = self
+            .variable(c"chip8_speed")
+            .and_then(|speed| speed.trim_end_matches('x').parse().ok())
+            .unwrap_or(1);
+        if let Some(chip8) = self.chip8.take() {
+            self.chip8 = Some(chip8.setup_quirks(self.quirks));
+        }
+    }
+
+    fn options_changed(&self) -> bool {
+        let mut changed = false;
+        self.environment.is_some_and(|environment| {
+            let data = &mut changed as *mut bool as *mut c_void;
+            environment(ENVIRONMENT_GET_VARIABLE_UPDATE, data)
+        }) && changed
+    }
+
+    fn poll_input(&mut self) {
+        let (Some(input_poll), Some(input_state)) = (self.input_poll, self.input_state) else {
+            return;
+        };
+        let Some(chip8) = self.chip8.as_mut() else {
+            return;
+        };
+        input_poll();
+        let mut pressed = [false; 16];
+        for (id, key) in BUTTONS {
+            pressed[key as usize] |= input_state(0, DEVICE_JOYPAD, 0, id) != 0;
+        }
+        for (value, code) in KEYBOARD.iter().enumerate() {
+            pressed[value] |= input_state(0, DEVICE_KEYBOARD, 0, *code as c_uint) != 0;
+        }
+        for (value, pressed) in pressed.into_iter().enumerate() {
+            chip8.set_key(Key::try_from(value as u8).unwrap(), pressed);
+        }
+    }
+
+    fn refresh_video(&mut self) {
+        let (Some(video_refresh), Some(chip8)) = (self.video_refresh, self.chip8.as_ref()) else {
+            return;
+        };
+        self.video.clear();
+        self.video
+            .extend(chip8.memory().framebuffer().iter().map(|pixel| {
+                let [red, green, blue] = self.palette.color(*pixel);
+                u32::from_be_bytes([0, red, green, blue])
+            }));
+        let pitch = WIDTH * std::mem::size_of::<u32>();
+        video_refresh(
+            self.video.as_ptr() as *const c_void,
+            WIDTH as c_uint,
+            HEIGHT as c_uint,
+            pitch,
+        );
+    }
+
+    // a frame's worth of stereo samples, beeping while the sound timer is running and
+    // silent otherwise, since frontends pace themselves by the audio
+    fn play_audio(&mut self) {
+        let Some(audio_sample_batch) = self.audio_sample_batch else {
+            return;
+        };
+        let frames = SAMPLE_RATE / FRAMES_PER_SECOND as usize;
+        let beeping = self
+            .chip8
+            .as_ref()
+            .is_some_and(|chip8| chip8.sound_timer() > 0);
+        self.audio.clear();
+        for _ in 0..frames {
+            let sample = match beeping {
+                false => 0,
+                true if self.tone_phase < TONE_PERIOD / 2 => TONE_VOLUME,
+                true => -TONE_VOLUME,
+            };
+            self.audio.extend([sample, sample]);
+            self.tone_phase = (self.tone_phase + 1) % TONE_PERIOD;
+        }
+        audio_sample_batch(self.audio.as_ptr(), frames);
+    }
+
+    // big enough for any state, whatever the depth of the stack, which CALL stops at 16
+    fn serialize_size(&self) -> usize {
+        self.chip8.as_ref().map_or(0, |chip8| {
+            chip8.save_state().len() + 2 * 16usize.saturating_sub(chip8.stack().len())
+        })
+    }
+}
+
+thread_local! {
+    static CORE: RefCell<Core> = RefCell::new(Core::default());
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with(|core| f(&mut core.borrow_mut()))
+}
+
+// runs an entry point, returning failed instead if it panics
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(failed)
+}
+
+// whether the program stopped during these frames
+fn run_frames(chip8: &mut Chip8, count: u32) -> bool {
+    for _ in 0..count {
+        match chip8.try_run_frame() {
+            Ok(-1) => return true,
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("{error}");
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: Option<EnvironmentFn>) {
+    guard((), || {
+        with_core(|core| core.environment = environment);
+        let Some(environment) = environment else {
+            return;
+        };
+        let mut variables: Vec<Variable> = OPTIONS
+            .iter()
+            .map(|(key, value)| Variable {
+                key: key.as_ptr(),
+                value: value.as_ptr(),
+            })
+            .collect();
+        variables.push(Variable {
+            key: ptr::null(),
+            value: ptr::null(),
+        });
+        environment(
+            ENVIRONMENT_SET_VARIABLES,
+            variables.as_mut_ptr() as *mut c_void,
+        );
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: Option<VideoRefreshFn>) {
+    guard((), || with_core(|core| core.video_refresh = video_refresh))
+}
+
+// everything goes through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: Option<AudioSampleFn>) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: Option<AudioSampleBatchFn>) {
+    guard((), || {
+        with_core(|core| core.audio_sample_batch = audio_sample_batch)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: Option<InputPollFn>) {
+    guard((), || with_core(|core| core.input_poll = input_poll))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: Option<InputStateFn>) {
+    guard((), || with_core(|core| core.input_state = input_state))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {
+    guard((), || {
+        with_core(|core| {
+            core.speed = 1;
+            core.palette = Palette::default();
+        });
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    guard((), || with_core(|core| core.chip8 = None))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    guard((), || {
+        if info.is_null() {
+            return;
+        }
+        *info = SystemInfo {
+            library_name: c"chip8-emulator".as_ptr(),
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+            valid_extensions: c"ch8".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        };
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    guard((), || {
+        if info.is_null() {
+            return;
+        }
+        *info = SystemAvInfo {
+            geometry: GameGeometry {
+                base_width: WIDTH as c_uint,
+                base_height: HEIGHT as c_uint,
+                max_width: WIDTH as c_uint,
+                max_height: HEIGHT as c_uint,
+                aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+            },
+            timing: SystemTiming {
+                fps: FRAMES_PER_SECOND,
+                sample_rate: SAMPLE_RATE as f64,
+            },
+        };
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    guard((), || {
+        with_core(|core| {
+            if let Some(chip8) = core.chip8.as_mut() {
+                chip8.reset();
+                core.halted = false;
+            }
+        });
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    guard((), || {
+        with_core(|core| {
+            if core.options_changed() {
+                core.read_options();
+            }
+            core.poll_input();
+            if let Some(chip8) = core.chip8.as_mut().filter(|_| !core.halted) {
+                // a panic stops the program too, rather than happening again every frame
+                let speed = core.speed;
+                core.halted = panic::catch_unwind(AssertUnwindSafe(|| run_frames(chip8, speed)))
+                    .unwrap_or(true);
+            }
+            core.refresh_video();
+            core.play_audio();
+        });
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    guard(0, || with_core(|core| core.serialize_size()))
+}
+
+// the state is padded out with zeros to retro_serialize_size
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    guard(false, || {
+        with_core(|core| {
+            let Some(chip8) = core.chip8.as_ref() else {
+                return false;
+            };
+            let state = chip8.save_state();
+            if data.is_null() || state.len() > size {
+                return false;
+            }
+            let buffer = slice::from_raw_parts_mut(data as *mut u8, size);
+            buffer[..state.len()].copy_from_slice(&state);
+            buffer[state.len()..].fill(0);
+            true
+        })
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    guard(false, || {
+        if data.is_null() {
+            return false;
+        }
+        let buffer = slice::from_raw_parts(data as *const u8, size);
+        let Some(length) = savestate::saved_length(buffer).filter(|length| *length <= size) else {
+            return false;
+        };
+        with_core(|core| {
+            let loaded = core
+                .chip8
+                .as_mut()
+                .is_some_and(|chip8| chip8.load_state(&buffer[..length]).is_ok());
+            core.halted &= !loaded;
+            loaded
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    guard(false, || {
+        let Some(game) = game.as_ref() else {
+            return false;
+        };
+        // the frontend usually has the ROM in memory already, but may only give a path
+        let rom = if !game.data.is_null() {
+            slice::from_raw_parts(game.data as *const u8, game.size).to_vec()
+        } else if !game.path.is_null() {
+            let path = CStr::from_ptr(game.path).to_string_lossy().into_owned();
+            match std::fs::read(path) {
+                Ok(rom) => rom,
+                Err(_) => return false,
+            }
+        } else {
+            return false;
+        };
+        let Ok(chip8) = Chip8::load_from_bytes(&rom) else {
+            return false;
+        };
+
+        with_core(|core| {
+            if let Some(environment) = core.environment {
+                let mut format = PIXEL_FORMAT_XRGB8888;
+                let data = &mut format as *mut c_uint as *mut c_void;
+                if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, data) {
+                    return false;
+                }
+            }
+            core.chip8 = Some(chip8);
+            core.halted = false;
+            core.read_options();
+            true
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    guard(false, || false)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    guard((), || with_core(|core| core.chip8 = None))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
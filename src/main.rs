@@ -1,16 +1,635 @@
-extern crate sdl2;
+use std::collections::HashMap;
+use std::path::Path;
 
-mod emulator;
-mod graphics;
-mod instruction;
-mod key;
-mod memory;
-mod register;
-mod sdl_context;
+#[cfg(feature = "script")]
+use chip8_emulator::script::Script;
+use chip8_emulator::{
+    capture, cartridge, config, control, controller, emulator, gdb, graphics, keymap, movie,
+    palette, phosphor, quirks, rewind, rom_database, screenshot, sha1, tracer, tty,
+};
 
-use emulator::Chip8;
+use capture::Capture;
+use cartridge::Cartridge;
+use control::ControlServer;
+use controller::ButtonMap;
+use emulator::{Chip8, Speed};
+use gdb::GdbStub;
+use graphics::DisplayOptions;
+use keymap::{KeyName, Keymap};
+use movie::{Movie, Recorder};
+use palette::Palette;
+use phosphor::Phosphor;
+use quirks::Quirks;
+use rewind::RewindBuffer;
+use rom_database::{Platform, RomDatabase};
+use screenshot::ImageFormat;
+use tracer::{TraceFormat, Tracer};
+use tty::{TtyContext, TtyStyle};
+
+const USAGE: &str = "\
+Usage: chip8-emulator [disasm|debug] [options] <rom>
+       chip8-emulator replay [--screenshot <file>] [--capture <file.gif|dir>] <rom> <movie>
+       chip8-emulator screenshot [--frames <n>] <rom> <file>
+       chip8-emulator control <port|socket> [rom]
+       chip8-emulator cartridge [options] <rom> <file.gif>
+
+A rom can be a binary, a .8o Octo source file, or an Octo cartridge .gif, whose settings
+are used unless options override them. cartridge saves one of those, with the quirks,
+tick rate and palette from the options.
+
+Options:
+  --trace <file|->           log every instruction to a file, or stderr with -
+  --trace-format <text|csv>  format of the trace (default text)
+  --trace-range <200-2FF>    only trace instructions in this address range
+  --trace-kind <DRW,JP,...>  only trace these kinds of instruction
+  --trace-last <n>           only keep the last n instructions, written out when the program stops or crashes
+  --quirks <list>            comma separated: vf-reset, jump-vx, clip
+  --tick-rate <n>            instructions run each frame (default 1)
+  --rom-database <file>      more ROMs to look up by SHA-1, in the format of the built-in database,
+                             which sets quirks, tick rate and palette for the ROMs it knows
+  --state <file>             start from a save state (F1-F9 load a slot, Shift+F1-F9 save one)
+  --record <file>            record the keypad to a movie, which replay plays back headless
+                             (keys a --script presses aren't recorded)
+  --scale <n>                size of screenshots and captures, in image pixels per CHIP-8 pixel (default 8)
+  --capture <file.gif|dir>   record every frame to a GIF, or to numbered PNGs in a directory (F10 toggles a GIF)
+  --dedupe                   leave out frames that are the same as the one before
+  --integer-scale            only scale the window by whole numbers, so every pixel is the same size
+  --no-grid                  don't draw lines between pixels (Ctrl+G toggles)
+  --fullscreen               start fullscreen (F11 toggles)
+  --stats                    show FPS, instructions per second and quirks in the window (Ctrl+I toggles)
+  --panels                   show registers, the stack, disassembly and memory next to the screen,
+                             where clicking a line of disassembly toggles a breakpoint
+  --phosphor <decay|or>      reduce flicker by fading pixels out slowly, or showing the last two frames
+  --phosphor-decay <0-1>     how much brightness a pixel keeps each frame after going off (default 0.5)
+  --keymap <name>            qwerty, azerty, qwertz, dvorak or numpad (default qwerty)
+  --key.<X> <keys>           put keypad key X (0-F) on these space separated keys, e.g. --key.5 \"w up\"
+  --pad.<X> <buttons>        put keypad key X on these gamepad buttons: a, b, x, y, back, guide, start,
+                             leftstick, rightstick, leftshoulder, rightshoulder, dpup, dpdown, dpleft
+                             or dpright (default dpup 5, dpleft 7, dpdown 8, dpright 9, a 6, b 4)
+  --hotkey.<name> <keys>     put a control on these keys: pause (default space), advance (]),
+                             fast-forward (=), slow-motion (-) or reset (delete)
+  --speed <n|max>            run at n times normal speed, or as fast as possible (default 1)
+  --fast-forward <n|max>     speed the fast forward hotkey switches to (default max)
+  --slow-motion <n>          speed the slow motion hotkey switches to (default 0.25)
+  --script <file.rhai>       run a Rhai script's hooks on frames, addresses and memory writes,
+                             loading it again whenever it changes
+  --gdb <port>               wait for GDB to connect on this local port (\"target remote :<port>\")
+  --tty                      draw in the terminal instead of a window (Escape or Ctrl+C quits)
+  --tty-chars <half|braille> characters to draw with in the terminal (default half)
+  --palette <name|colours>   default, green, amber, lcd, high-contrast, or RRGGBB,RRGGBB[,RRGGBB,RRGGBB]
+                             for the background, foreground, plane 2 and both planes (Tab cycles)
+  --config <file>            read defaults for these options from \"name = value\" lines
+                             (default ~/.config/chip8-emulator/config), where \"rom.<sha1>.\",
+                             with the SHA-1 of the ROM file as sha1sum prints it (or of the
+                             program an Octo source file or cartridge holds),
+                             in front of keymap, key.<X>, pad.<X> or hotkey.<name>
+                             only applies it to that ROM
+
+Hold Backspace to rewind up to 10 seconds.";
+
+// one snapshot per frame, with a full one every second
+const REWIND_FRAMES: usize = 600;
+const REWIND_KEYFRAME_INTERVAL: usize = 60;
+
+const DEFAULT_PHOSPHOR_DECAY: f32 = 0.5;
+
+// flags that don't take a value
+const SWITCHES: &[&str] = &[
+    "tty",
+    "dedupe",
+    "integer-scale",
+    "no-grid",
+    "fullscreen",
+    "stats",
+    "panels",
+];
+
+// "--name value" pairs, with everything else positional
+struct Options {
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Self {
+        let mut positional = Vec::new();
+        let mut flags = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if SWITCHES.contains(&name) => {
+                    flags.insert(name.to_owned(), String::new());
+                }
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .unwrap_or_else(|| panic!("Missing value for --{name}\n{USAGE}"));
+                    flags.insert(name.to_owned(), value.to_owned());
+                }
+                None => positional.push(arg.to_owned()),
+            }
+        }
+        Self { positional, flags }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).map(String::as_str)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    // the options starting with prefix, sorted, with the prefix taken off
+    fn with_prefix(&self, prefix: &str) -> Vec<(&str, &str)> {
+        let mut options: Vec<(&str, &str)> = self
+            .flags
+            .iter()
+            .filter_map(|(name, value)| Some((name.strip_prefix(prefix)?, value.as_str())))
+            .collect();
+        options.sort();
+        options
+    }
+
+    // options from the command line take priority over the config file
+    fn apply_config(&mut self, entries: Vec<(String, String)>) {
+        for (name, value) in entries {
+            if SWITCHES.contains(&name.as_str()) && value != "true" {
+                continue;
+            }
+            self.flags.entry(name).or_insert(value);
+        }
+    }
+}
+
+// an explicit --config has to exist, but the default one is optional
+fn load_config(options: &mut Options) {
+    let entries = match options.get("config") {
+        Some(filepath) => config::load(filepath).unwrap_or_else(|error| panic!("{error}")),
+        None => match config::default_path() {
+            Some(path) if path.exists() => {
+                config::load(&path.to_string_lossy()).unwrap_or_else(|error| panic!("{error}"))
+            }
+            _ => return,
+        },
+    };
+    options.apply_config(entries);
+}
+
+fn palette(options: &Options) -> Palette {
+    match options.get("palette") {
+        Some(text) => Palette::parse(text).unwrap_or_else(|error| panic!("{error}\n{USAGE}")),
+        None => Palette::default(),
+    }
+}
+
+// text files are in the "address: word" format, .8o files are Octo source, .gif files
+// are Octo cartridges, and anything else is treated as a binary rom
+fn load(filepath: &str) -> Chip8 {
+    Chip8::load_from_path(filepath).unwrap_or_else(|error| panic!("{filepath}: {error}"))
+}
+
+// settings saved in a cartridge, or for a known ROM, which anything from the command line
+// or config file overrides
+fn apply_rom_settings(options: &mut Options, filepath: &str, hash: &str) {
+    if filepath.ends_with(".gif") {
+        // load reports a cartridge that can't be read
+        if let Ok(cartridge) = Cartridge::load(filepath) {
+            options.apply_config(cartridge.options());
+        }
+        return;
+    }
+    let mut database = RomDatabase::embedded();
+    if let Some(database_filepath) = options.get("rom-database") {
+        database
+            .extend(RomDatabase::load(database_filepath).unwrap_or_else(|error| panic!("{error}")));
+    }
+    let Some(rom) = database.lookup(hash) else {
+        eprintln!("Warning: unknown ROM {hash}, using the default settings");
+        return;
+    };
+    println!("{rom}");
+    if let Some(keys) = rom.key_hints() {
+        println!("Keys: {keys}");
+    }
+    if rom.platform != Platform::Chip8 {
+        eprintln!(
+            "Warning: {} is a {} program, which may not run properly",
+            rom.title, rom.platform
+        );
+    }
+    options.apply_config(rom.options());
+}
+
+fn quirks(options: &Options) -> Quirks {
+    match options.get("quirks") {
+        Some(names) => Quirks::parse(names).unwrap_or_else(|error| panic!("{error}\n{USAGE}")),
+        None => Quirks::default(),
+    }
+}
+
+fn setup_quirks(chip8: Chip8, options: &Options) -> Chip8 {
+    chip8.setup_quirks(quirks(options))
+}
+
+fn tick_rate(options: &Options) -> Option<u32> {
+    options
+        .get("tick-rate")
+        .map(|tick_rate| match tick_rate.parse() {
+            Ok(tick_rate) if tick_rate > 0 => tick_rate,
+            _ => panic!("Invalid tick rate '{tick_rate}'\n{USAGE}"),
+        })
+}
+
+fn setup_tick_rate(chip8: Chip8, options: &Options) -> Chip8 {
+    match tick_rate(options) {
+        Some(tick_rate) => chip8.setup_tick_rate(tick_rate),
+        None => chip8,
+    }
+}
+
+// a cartridge GIF of the program, with the settings it would run with, labelled with its name
+fn export_cartridge(filepath: &str, gif_filepath: &str, options: &Options) {
+    let chip8 = load(filepath);
+    let mut program = chip8.memory().data()[0x200..].to_vec();
+    // memory after the program is zero anyway
+    while program.last() == Some(&0) {
+        program.pop();
+    }
+    let cartridge = Cartridge {
+        program,
+        tick_rate: tick_rate(options),
+        quirks: quirks(options),
+        palette: options.has("palette").then(|| palette(options)),
+    };
+    let title = Path::new(filepath)
+        .file_stem()
+        .map_or(filepath.into(), |stem| stem.to_string_lossy());
+    cartridge
+        .save(gif_filepath, &title)
+        .unwrap_or_else(|error| panic!("{error}"));
+    println!("Saved {gif_filepath}");
+}
+
+// a save state includes the quirks, so this has to come after setup_quirks
+fn load_state(mut chip8: Chip8, options: &Options) -> Chip8 {
+    if let Some(filepath) = options.get("state") {
+        let result = std::fs::read(filepath)
+            .map_err(|error| error.to_string())
+            .and_then(|data| chip8.load_state(&data));
+        if let Err(error) = result {
+            panic!("{filepath}: {error}");
+        }
+    }
+    chip8
+}
+
+fn phosphor(options: &Options) -> Option<Phosphor> {
+    let decay = match options.get("phosphor-decay") {
+        Some(decay) => match decay.parse() {
+            Ok(decay) if (0.0..=1.0).contains(&decay) => decay,
+            _ => panic!("Invalid phosphor decay '{decay}'\n{USAGE}"),
+        },
+        None => DEFAULT_PHOSPHOR_DECAY,
+    };
+    match options.get("phosphor") {
+        None => None,
+        Some("decay") => Some(Phosphor::Decay(decay)),
+        Some("or") => Some(Phosphor::LastTwo),
+        Some(mode) => panic!("Invalid phosphor mode '{mode}'\n{USAGE}"),
+    }
+}
+
+fn display_options(options: &Options) -> DisplayOptions {
+    DisplayOptions {
+        integer_scaling: options.has("integer-scale"),
+        grid: !options.has("no-grid"),
+        fullscreen: options.has("fullscreen"),
+        phosphor: phosphor(options),
+        stats: options.has("stats"),
+        panels: options.has("panels"),
+    }
+}
+
+// binds each "<name>.X" option, then the ones for this ROM so that they win
+fn bind_options(
+    options: &Options,
+    rom_hash: &str,
+    name: &str,
+    mut bind: impl FnMut(&str, &str) -> Result<(), String>,
+) {
+    let rom_prefix = format!("rom.{rom_hash}.");
+    for prefix in [format!("{name}."), format!("{rom_prefix}{name}.")] {
+        for (key, names) in options.with_prefix(&prefix) {
+            bind(key, names).unwrap_or_else(|error| panic!("--{prefix}{key}: {error}\n{USAGE}"));
+        }
+    }
+}
+
+// a preset, then any keys moved from it, for the window's keys or the terminal's bytes
+fn keymap<K: KeyName>(options: &Options, rom_hash: &str) -> Keymap<K> {
+    let name = options
+        .get(&format!("rom.{rom_hash}.keymap"))
+        .or(options.get("keymap"))
+        .unwrap_or("qwerty");
+    let mut keymap =
+        Keymap::preset(name).unwrap_or_else(|| panic!("Unknown keymap '{name}'\n{USAGE}"));
+    bind_options(options, rom_hash, "key", |key, names| {
+        keymap.bind(key, names)
+    });
+    bind_options(options, rom_hash, "hotkey", |action, names| {
+        keymap.bind_hotkey(action, names)
+    });
+    keymap
+}
+
+fn button_map(options: &Options, rom_hash: &str) -> ButtonMap {
+    let mut buttons = ButtonMap::default();
+    bind_options(options, rom_hash, "pad", |key, names| {
+        buttons.bind(key, names)
+    });
+    buttons
+}
+
+// an SDL window, unless --tty asks for the terminal
+fn setup_frontend(chip8: Chip8, options: &Options, rom_hash: &str) -> Chip8 {
+    if !options.has("tty") {
+        let keymap = keymap(options, rom_hash);
+        let buttons = button_map(options, rom_hash);
+        return chip8.setup_sdl(display_options(options), keymap, buttons);
+    }
+    let style = match options.get("tty-chars") {
+        None | Some("half") => TtyStyle::HalfBlocks,
+        Some("braille") => TtyStyle::Braille,
+        Some(chars) => panic!("Invalid terminal characters '{chars}'\n{USAGE}"),
+    };
+    let tty =
+        TtyContext::new(style, keymap(options, rom_hash)).unwrap_or_else(|error| panic!("{error}"));
+    chip8.setup_frontend(Box::new(tty))
+}
+
+// movies start from power-on, so can't be combined with --state
+fn setup_recorder(chip8: Chip8, options: &Options) -> Chip8 {
+    match options.get("record") {
+        Some(_) if options.get("state").is_some() => {
+            panic!("--record can't be used with --state\n{USAGE}")
+        }
+        Some(filepath) => {
+            let recorder = Recorder::to_file(chip8.new_movie(), filepath);
+            chip8.setup_recorder(recorder)
+        }
+        None => chip8,
+    }
+}
+
+// hooks from a Rhai script, which is loaded again whenever the file changes
+#[cfg(feature = "script")]
+fn setup_script(chip8: Chip8, options: &Options) -> Chip8 {
+    match options.get("script") {
+        Some(filepath) => {
+            let script = Script::load(filepath).unwrap_or_else(|error| panic!("{error}"));
+            chip8.setup_script(script)
+        }
+        None => chip8,
+    }
+}
+
+#[cfg(not(feature = "script"))]
+fn setup_script(chip8: Chip8, options: &Options) -> Chip8 {
+    if options.has("script") {
+        panic!("--script needs a build with the script feature\n{USAGE}");
+    }
+    chip8
+}
+
+// the program waits for GDB to attach before it starts
+fn setup_gdb(chip8: Chip8, options: &Options) -> Chip8 {
+    let Some(port) = options.get("gdb") else {
+        return chip8;
+    };
+    let port = port
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid port '{port}'\n{USAGE}"));
+    let stub = GdbStub::listen(port).unwrap_or_else(|error| panic!("{error}"));
+    if let Some(address) = stub.local_addr() {
+        println!("Waiting for GDB on {address}");
+    }
+    chip8.setup_gdb(stub)
+}
+
+// the screen as text, for bug reports and comparing runs
+fn print_screen(chip8: &Chip8) {
+    let framebuffer = chip8.memory().framebuffer();
+    for row in framebuffer.chunks(64) {
+        let line: String = row
+            .iter()
+            .map(|pixel| if *pixel == 0 { '.' } else { '#' })
+            .collect();
+        println!("{line}");
+    }
+    println!("framebuffer sha1 {}", sha1::sha1_hex(framebuffer));
+}
+
+fn scale(options: &Options) -> usize {
+    match options.get("scale") {
+        Some(scale) => scale
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid scale '{scale}'\n{USAGE}")),
+        None => screenshot::DEFAULT_SCALE,
+    }
+}
+
+// PNG, or PPM if the file name ends in .ppm
+fn save_screenshot(chip8: &Chip8, filepath: &str, options: &Options) {
+    chip8
+        .save_screenshot(filepath, scale(options), &palette(options))
+        .unwrap_or_else(|error| panic!("{error}"));
+}
+
+fn setup_capture(chip8: Chip8, options: &Options) -> Chip8 {
+    let capture = match options.get("capture") {
+        Some(filepath) if filepath.ends_with(".gif") => {
+            Capture::to_gif(filepath, scale(options), palette(options))
+        }
+        Some(directory) => Capture::to_frames(
+            directory,
+            ImageFormat::Png,
+            scale(options),
+            palette(options),
+        ),
+        None => return chip8,
+    };
+    let capture = capture.unwrap_or_else(|error| panic!("{error}"));
+    chip8.setup_capture(capture.dedupe(options.has("dedupe")))
+}
+
+// a multiple of normal speed, or max for as fast as possible
+fn speed(options: &Options, name: &str, default: Speed) -> Speed {
+    match options.get(name) {
+        Some("max") => Speed::Unthrottled,
+        Some(speed) => match speed.parse() {
+            Ok(speed) if speed > 0.0 => Speed::Times(speed),
+            _ => panic!("Invalid --{name} speed '{speed}'\n{USAGE}"),
+        },
+        None => default,
+    }
+}
+
+fn setup_speeds(chip8: Chip8, options: &Options) -> Chip8 {
+    let mut chip8 = chip8.setup_speeds(
+        speed(options, "fast-forward", Speed::Unthrottled),
+        speed(options, "slow-motion", Speed::Times(0.25)),
+    );
+    chip8.set_speed(speed(options, "speed", Speed::NORMAL));
+    chip8
+}
+
+fn setup_tracer(chip8: Chip8, options: &Options) -> Chip8 {
+    let mut tracer = match options.get("trace") {
+        Some("-") => Tracer::to_stderr(),
+        Some(filepath) => Tracer::to_file(filepath).unwrap_or_else(|error| panic!("{error}")),
+        None => return chip8,
+    };
+
+    if let Some(format) = options.get("trace-format") {
+        tracer = match format {
+            "text" => tracer.format(TraceFormat::Text),
+            "csv" => tracer.format(TraceFormat::Csv),
+            _ => panic!("Invalid trace format '{format}'\n{USAGE}"),
+        };
+    }
+    if let Some(range) = options.get("trace-range") {
+        let (start, end) = range
+            .split_once('-')
+            .and_then(|(start, end)| {
+                Some((
+                    u16::from_str_radix(start, 16).ok()?,
+                    u16::from_str_radix(end, 16).ok()?,
+                ))
+            })
+            .unwrap_or_else(|| panic!("Invalid trace range '{range}'\n{USAGE}"));
+        tracer = tracer.address_range(start, end);
+    }
+    if let Some(kinds) = options.get("trace-kind") {
+        let kinds: Vec<&str> = kinds.split(',').collect();
+        tracer = tracer.kinds(&kinds);
+    }
+    if let Some(count) = options.get("trace-last") {
+        let count = count
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid trace length '{count}'\n{USAGE}"));
+        tracer = tracer.ring_buffer(count);
+    }
+
+    chip8.setup_tracer(tracer)
+}
 
 pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options::parse(&args);
+    load_config(&mut options);
+    // copied, so that ROM settings can add options once the ROM is known
+    let positional = options.positional.clone();
+    let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+    match positional.as_slice() {
+        ["disasm", filepath] => {
+            print!("{}", load(filepath).disassemble());
+            return;
+        }
+        ["replay", filepath, movie_filepath] => {
+            let movie = Movie::load(movie_filepath).unwrap_or_else(|error| panic!("{error}"));
+            let mut chip8 = setup_capture(setup_tracer(load(filepath), &options), &options);
+            chip8
+                .play_movie(&movie)
+                .unwrap_or_else(|error| panic!("{movie_filepath}: {error}"));
+            println!("{} frames, {} instructions", movie.frames, chip8.cycles());
+            print_screen(&chip8);
+            if let Some(image_filepath) = options.get("screenshot") {
+                save_screenshot(&chip8, image_filepath, &options);
+            }
+            return;
+        }
+        // runs headless for a number of frames, then saves the screen
+        ["screenshot", filepath, image_filepath] => {
+            let frames: u64 = match options.get("frames") {
+                Some(frames) => frames
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid frame count '{frames}'\n{USAGE}")),
+                None => 60,
+            };
+            let chip8 = load(filepath);
+            let hash = chip8.rom_hash();
+            apply_rom_settings(&mut options, filepath, &hash);
+            let chip8 = setup_tick_rate(setup_quirks(chip8, &options), &options);
+            let mut chip8 = setup_tracer(chip8, &options);
+            for _ in 0..frames {
+                if chip8.run_frame() == -1 {
+                    break;
+                }
+            }
+            save_screenshot(&chip8, image_filepath, &options);
+            return;
+        }
+        ["cartridge", filepath, gif_filepath] => {
+            let hash = load(filepath).rom_hash();
+            apply_rom_settings(&mut options, filepath, &hash);
+            export_cartridge(filepath, gif_filepath, &options);
+            return;
+        }
+        // headless, for scripts to drive with JSON-RPC over loopback TCP or a Unix socket
+        ["control", address, rest @ ..] if rest.len() <= 1 => {
+            let chip8 = rest.first().map(|filepath| {
+                let chip8 = load(filepath);
+                let hash = chip8.rom_hash();
+                apply_rom_settings(&mut options, filepath, &hash);
+                setup_tick_rate(setup_quirks(chip8, &options), &options)
+            });
+            let mut server =
+                ControlServer::listen(address, chip8).unwrap_or_else(|error| panic!("{error}"));
+            match server.local_addr() {
+                Some(local) => println!("Listening for JSON-RPC on {local}"),
+                None => println!("Listening for JSON-RPC on {address}"),
+            }
+            server.run();
+            return;
+        }
+        ["debug", _] if options.has("tty") => {
+            panic!("The debugger reads commands from the terminal, so can't be used with --tty")
+        }
+        ["debug", filepath] => {
+            let chip8 = load(filepath);
+            let hash = chip8.rom_hash();
+            apply_rom_settings(&mut options, filepath, &hash);
+            let chip8 = setup_tick_rate(setup_quirks(chip8, &options), &options);
+            let chip8 = load_state(chip8, &options)
+                .setup_rewind(RewindBuffer::new(REWIND_FRAMES, REWIND_KEYFRAME_INTERVAL))
+                .setup_palette(palette(&options))
+                .setup_screenshot_scale(scale(&options));
+            let chip8 =
+                setup_speeds(setup_frontend(chip8, &options, &hash), &options).setup_debugger();
+            setup_script(setup_tracer(chip8, &options), &options).run();
+            return;
+        }
+        [filepath] => {
+            let chip8 = load(filepath);
+            let hash = chip8.rom_hash();
+            apply_rom_settings(&mut options, filepath, &hash);
+            let chip8 = setup_tick_rate(setup_quirks(chip8, &options), &options);
+            let chip8 = load_state(chip8, &options)
+                .setup_rewind(RewindBuffer::new(REWIND_FRAMES, REWIND_KEYFRAME_INTERVAL))
+                .setup_palette(palette(&options))
+                .setup_screenshot_scale(scale(&options));
+            let chip8 = setup_recorder(setup_frontend(chip8, &options, &hash), &options);
+            let chip8 = setup_capture(setup_speeds(chip8, &options), &options);
+            let chip8 = setup_script(setup_tracer(chip8, &options), &options);
+            setup_gdb(chip8, &options).run();
+            return;
+        }
+        [] => {}
+        _ => panic!("{USAGE}"),
+    }
+
+    // with no arguments, run a small demo program
     let code = r#"
         200: 600A // LD V0, 10
         202: 6105 // LD V1, 5
@@ -31,6 +650,8 @@ pub fn main() {
         304: FF
         "#;
 
-    let mut chip8 = Chip8::load_from_text(code).setup_sdl();
-    chip8.run();
+    let chip8 = Chip8::load_from_text(code).setup_palette(palette(&options));
+    let hash = chip8.rom_hash();
+    let chip8 = setup_frontend(chip8, &options, &hash);
+    setup_tracer(chip8, &options).run();
 }
@@ -1,7 +1,17 @@
+use std::cell::RefCell;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Access {
+    pub address: usize,
+    pub write: bool,
+}
+
 #[derive(Debug)]
 pub struct Memory {
     data: [u8; 4096],
     framebuffer: [u8; 64 * 32],
+    // only recorded while the debugger is watching, since reads happen through &self
+    accesses: RefCell<Option<Vec<Access>>>,
 }
 
 impl Memory {
@@ -9,30 +19,91 @@ impl Memory {
         Memory {
             data: [0; 4096],
             framebuffer: [0; 64 * 32],
+            accesses: RefCell::new(None),
         }
     }
 
     pub fn get8(&self, index: usize) -> u8 {
+        self.track(index, false);
         self.data[index]
     }
 
     pub fn get16(&self, index: usize) -> u16 {
+        self.track(index, false);
+        self.track(index + 1, false);
         let n1 = self.data[index] as u16;
         let n2 = self.data[index + 1] as u16;
         (n1 << 8) + n2
     }
 
     pub fn set8(&mut self, index: usize, value: u8) {
+        self.track(index, true);
         self.data[index] = value;
     }
 
     pub fn set16(&mut self, index: usize, value: u16) {
+        self.track(index, true);
+        self.track(index + 1, true);
         let n1 = (value >> 8) as u8;
         let n2 = (value & 0xFF) as u8;
         self.data[index] = n1;
         self.data[index + 1] = n2;
     }
 
+    // reads that don't count as an access, for tools looking at the machine from outside
+    pub fn peek8(&self, index: usize) -> u8 {
+        self.data[index]
+    }
+
+    pub fn peek16(&self, index: usize) -> u16 {
+        u16::from_be_bytes([self.data[index], self.data[index + 1]])
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // replaces the contents of memory and the framebuffer, e.g. when loading a save state
+    pub fn restore(&mut self, data: &[u8], framebuffer: &[u8]) {
+        self.data.copy_from_slice(data);
+        self.framebuffer.copy_from_slice(framebuffer);
+    }
+
+    fn track(&self, index: usize, write: bool) {
+        if let Some(accesses) = self.accesses.borrow_mut().as_mut() {
+            accesses.push(Access {
+                address: index,
+                write,
+            });
+        }
+    }
+
+    pub fn start_tracking(&self) {
+        *self.accesses.borrow_mut() = Some(Vec::new());
+    }
+
+    // how many accesses there have been since tracking started, or None if it hasn't,
+    // so code running inside something that's tracking can look at just its own
+    pub fn tracked_count(&self) -> Option<usize> {
+        self.accesses.borrow().as_ref().map(Vec::len)
+    }
+
+    pub fn accesses_since(&self, start: usize) -> Vec<Access> {
+        match self.accesses.borrow().as_ref() {
+            Some(accesses) => accesses.get(start..).unwrap_or_default().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    // stops tracking and returns every access since start_tracking was called
+    pub fn stop_tracking(&self) -> Vec<Access> {
+        self.accesses.borrow_mut().take().unwrap_or_default()
+    }
+
     pub fn set8_framebuffer(&mut self, x: u8, y: u8, value: u8) {
         self.framebuffer[self.get_framebuffer_location(x as usize, y as usize)] = value;
     }
@@ -84,4 +155,47 @@ mod tests {
         memory.set8_framebuffer(10, 10, 0b10011001);
         assert_eq!(memory.get8_framebuffer(10, 10), 0b10011001);
     }
+
+    #[test]
+    fn test_tracking() {
+        let mut memory = Memory::new();
+        memory.set8(0x300, 1);
+        memory.start_tracking();
+        memory.get8(0x300);
+        memory.set16(0x302, 0xABCD);
+        assert_eq!(
+            memory.stop_tracking(),
+            vec![
+                Access {
+                    address: 0x300,
+                    write: false
+                },
+                Access {
+                    address: 0x302,
+                    write: true
+                },
+                Access {
+                    address: 0x303,
+                    write: true
+                },
+            ]
+        );
+        // nothing is recorded once tracking has stopped
+        memory.get8(0x300);
+        assert!(memory.stop_tracking().is_empty());
+        assert_eq!(memory.tracked_count(), None);
+
+        memory.start_tracking();
+        memory.get8(0x300);
+        let start = memory.tracked_count().unwrap();
+        memory.set8(0x301, 2);
+        assert_eq!(
+            memory.accesses_since(start),
+            vec![Access {
+                address: 0x301,
+                write: true
+            }]
+        );
+        assert_eq!(memory.stop_tracking().len(), 2);
+    }
 }
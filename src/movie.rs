@@ -0,0 +1,218 @@
+use crate::key::Key;
+use crate::quirks::Quirks;
+
+// Input movies: every keypad change and the frame it happened on, plus everything
+// else needed to make a run from power-on play out the same way again.
+//
+//  chip8-movie 2
+//  rom 2fd4e1c67a2d28fced849ee1bb76e7391b93eb12   SHA-1 of the ROM, as in Chip8::rom_hash
+//  quirks 0                                       bits as in Quirks::to_bits
+//  seed 1A2B3C4D                                  random number generator state
+//  speed 1                                        instructions per frame
+//  frames 600                                     length of the movie
+//  12 5 down                                      frame, key, down or up
+//  20 5 up
+//
+// Frames are 1/60 of a second, so the delay timer ticks once per frame.
+//
+// Only the keypad is recorded. Keys a script presses aren't, since its hooks can press
+// them partway through a frame, so a scripted session plays back the same way only if
+// the script didn't press any.
+
+const HEADER: &str = "chip8-movie 2";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub quirks: Quirks,
+    pub seed: u32,
+    pub cycles_per_frame: u32,
+    pub frames: u64,
+    pub events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{HEADER}\nrom {}\nquirks {}\nseed {:08X}\nspeed {}\nframes {}\n",
+            self.rom_hash,
+            self.quirks.to_bits(),
+            self.seed,
+            self.cycles_per_frame,
+            self.frames
+        );
+        for event in &self.events {
+            let state = if event.pressed { "down" } else { "up" };
+            text.push_str(&format!("{} {:X} {state}\n", event.frame, event.key as u8));
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err("Not a chip8 movie, or an unsupported version".to_owned());
+        }
+
+        let mut movie = Movie {
+            rom_hash: String::new(),
+            quirks: Quirks::default(),
+            seed: 1,
+            cycles_per_frame: 1,
+            frames: 0,
+            events: Vec::new(),
+        };
+        for (line_number, line) in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let invalid = || format!("{line_number}: Invalid line '{line}'");
+            match words.as_slice() {
+                ["rom", hash] => movie.rom_hash = hash.to_string(),
+                ["quirks", bits] => {
+                    movie.quirks = Quirks::from_bits(bits.parse().map_err(|_| invalid())?)
+                }
+                ["seed", seed] => {
+                    movie.seed = u32::from_str_radix(seed, 16).map_err(|_| invalid())?
+                }
+                ["speed", speed] => {
+                    movie.cycles_per_frame = speed.parse().map_err(|_| invalid())?
+                }
+                ["frames", frames] => movie.frames = frames.parse().map_err(|_| invalid())?,
+                [frame, key, state] => {
+                    let pressed = match *state {
+                        "down" => true,
+                        "up" => false,
+                        _ => return Err(invalid()),
+                    };
+                    let key = u8::from_str_radix(key, 16)
+                        .map_err(|error| error.to_string())
+                        .and_then(Key::try_from)
+                        .map_err(|_| invalid())?;
+                    let frame = frame.parse().map_err(|_| invalid())?;
+                    movie.events.push(KeyEvent {
+                        frame,
+                        key,
+                        pressed,
+                    });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        if movie.rom_hash.is_empty() {
+            return Err("Movie has no rom hash".to_owned());
+        }
+        if movie
+            .events
+            .windows(2)
+            .any(|pair| pair[0].frame > pair[1].frame)
+        {
+            return Err("Movie events are out of order".to_owned());
+        }
+        Ok(movie)
+    }
+
+    pub fn load(filepath: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(filepath)
+            .map_err(|error| format!("Unable to read {filepath}: {error}"))?;
+        Movie::parse(&text).map_err(|error| format!("{filepath}: {error}"))
+    }
+}
+
+// Collects key events while the emulator runs, and writes the movie out when it's
+// dropped, however the emulator stops.
+pub struct Recorder {
+    movie: Movie,
+    filepath: String,
+}
+
+impl Recorder {
+    pub fn to_file(movie: Movie, filepath: &str) -> Self {
+        Self {
+            movie,
+            filepath: filepath.to_owned(),
+        }
+    }
+
+    pub fn key(&mut self, frame: u64, key: Key, pressed: bool) {
+        self.movie.events.push(KeyEvent {
+            frame,
+            key,
+            pressed,
+        });
+    }
+
+    pub fn set_frames(&mut self, frames: u64) {
+        self.movie.frames = frames;
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let filepath = &self.filepath;
+        match std::fs::write(filepath, self.movie.to_text()) {
+            Ok(()) => eprintln!("Movie saved to {filepath}"),
+            Err(error) => eprintln!("Unable to save {filepath}: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_movie() -> Movie {
+        Movie {
+            rom_hash: "a9993e364706816aba3e25717850c26c9cd0d89d".to_owned(),
+            quirks: Quirks {
+                clip_sprites: true,
+                ..Quirks::default()
+            },
+            seed: 0xDEADBEEF,
+            cycles_per_frame: 10,
+            frames: 120,
+            events: vec![
+                KeyEvent {
+                    frame: 3,
+                    key: Key::CodeA,
+                    pressed: true,
+                },
+                KeyEvent {
+                    frame: 9,
+                    key: Key::CodeA,
+                    pressed: false,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let movie = example_movie();
+        let text = movie.to_text();
+        assert!(text.contains("\n3 A down\n"));
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Movie::parse("hello").is_err());
+        let text = example_movie().to_text();
+        assert!(Movie::parse(&text.replace("3 A down", "3 G down"))
+            .unwrap_err()
+            .starts_with("7:"));
+        assert!(Movie::parse(&text.replace("3 A down", "30 A down"))
+            .unwrap_err()
+            .contains("out of order"));
+    }
+}
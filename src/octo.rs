@@ -0,0 +1,908 @@
+use std::collections::HashMap;
+
+// Assembler for a subset of Octo (https://johnearnest.github.io/Octo/) syntax.
+// Only plain CHIP-8 instructions are supported; SCHIP/XO-CHIP statements and the
+// rarer directives are rejected with an "unsupported construct" error.
+
+const PROGRAM_START: u16 = 0x200;
+
+// macros can call other macros, but not forever
+const MAX_MACRO_DEPTH: usize = 1000;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+// a 12 bit address that couldn't be resolved when the instruction was emitted
+struct Fixup {
+    position: usize,
+    label: String,
+    line: usize,
+}
+
+enum Control {
+    If { jump_at: usize },
+    Else { jump_at: usize },
+    Loop { start: u16, breaks: Vec<usize> },
+}
+
+// the two opcodes for a condition: one that skips when it holds, one that skips when it doesn't
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    position: usize,
+    image: Vec<u8>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    control: Vec<Control>,
+    // where the tokens of each macro expansion still being read end, innermost last
+    expansion_ends: Vec<usize>,
+}
+
+// Assembles Octo source into an image that is loaded at 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new(tokenize(source));
+    assembler.assemble()?;
+    Ok(assembler.image)
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        // everything after a # is a comment
+        let line = line.split('#').next().unwrap_or("");
+        for word in line.split_whitespace() {
+            tokens.push(Token {
+                text: word.to_owned(),
+                line: index + 1,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_unsupported(text: &str) -> bool {
+    matches!(
+        text,
+        "hires"
+            | "lores"
+            | "scroll-down"
+            | "scroll-up"
+            | "scroll-left"
+            | "scroll-right"
+            | "exit"
+            | "plane"
+            | "audio"
+            | "pitch"
+            | "saveflags"
+            | "loadflags"
+            | "long"
+    )
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            image: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            control: Vec::new(),
+            expansion_ends: Vec::new(),
+        }
+    }
+
+    fn assemble(&mut self) -> Result<(), String> {
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+
+        if let Some(control) = self.control.last() {
+            let unclosed = match control {
+                Control::If { .. } | Control::Else { .. } => "begin",
+                Control::Loop { .. } => "loop",
+            };
+            return Err(format!("Unclosed '{unclosed}' at end of program"));
+        }
+
+        for fixup in &self.fixups {
+            let address = *self.labels.get(&fixup.label).ok_or_else(|| {
+                if fixup.label == "main" && fixup.line == 0 {
+                    "Program has no ': main' label".to_owned()
+                } else {
+                    format!("{}: Undefined label '{}'", fixup.line, fixup.label)
+                }
+            })?;
+            self.image[fixup.position] |= (address >> 8) as u8;
+            self.image[fixup.position + 1] = (address & 0xFF) as u8;
+        }
+
+        Ok(())
+    }
+
+    fn here(&self) -> u16 {
+        PROGRAM_START + self.image.len() as u16
+    }
+
+    fn next_token(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.position).cloned().ok_or_else(|| {
+            let line = self.tokens.last().map_or(0, |token| token.line);
+            format!("{line}: Unexpected end of program")
+        })?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next_token()?;
+        if token.text != text {
+            return Err(format!(
+                "{}: Expected '{}' but found '{}'",
+                token.line, text, token.text
+            ));
+        }
+        Ok(())
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens
+            .get(self.position)
+            .is_some_and(|token| token.text == text)
+    }
+
+    // Octo programs start at main, so if anything comes before it the program
+    // has to begin with a jump there
+    fn start_program(&mut self) {
+        if self.image.is_empty() && !self.labels.contains_key("main") {
+            self.image.extend_from_slice(&[0x10, 0x00]);
+            self.fixups.push(Fixup {
+                position: 0,
+                label: "main".to_owned(),
+                line: 0,
+            });
+        }
+    }
+
+    fn emit8(&mut self, value: u8) {
+        self.image.push(value);
+    }
+
+    fn emit16(&mut self, value: u16) {
+        self.image.extend_from_slice(&value.to_be_bytes());
+    }
+
+    // emits an opcode whose lower 12 bits will be filled in once the label is known
+    fn emit_address(&mut self, opcode: u16, label: &str, line: usize) -> usize {
+        let position = self.image.len();
+        match self.labels.get(label) {
+            Some(address) => self.emit16(opcode | address),
+            None => {
+                self.emit16(opcode);
+                self.fixups.push(Fixup {
+                    position,
+                    label: label.to_owned(),
+                    line,
+                });
+            }
+        }
+        position
+    }
+
+    // a jump with no target yet, used by the control flow statements
+    fn emit_placeholder_jump(&mut self) -> usize {
+        let position = self.image.len();
+        self.emit16(0x1000);
+        position
+    }
+
+    fn patch_jump(&mut self, position: usize, target: u16) {
+        self.image[position] = 0x10 | (target >> 8) as u8;
+        self.image[position + 1] = (target & 0xFF) as u8;
+    }
+
+    fn register(&self, token: &Token) -> Option<u8> {
+        if let Some(number) = self.aliases.get(&token.text) {
+            return Some(*number);
+        }
+        let text = token.text.to_ascii_lowercase();
+        let digit = text.strip_prefix('v')?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u8, String> {
+        let token = self.next_token()?;
+        self.register(&token).ok_or_else(|| {
+            format!(
+                "{}: Expected a register but found '{}'",
+                token.line, token.text
+            )
+        })
+    }
+
+    fn constant(&self, token: &Token) -> Option<i64> {
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|address| *address as i64))
+    }
+
+    fn expect_byte(&mut self) -> Result<u8, String> {
+        let token = self.next_token()?;
+        let value = self.constant(&token).ok_or_else(|| {
+            format!(
+                "{}: Expected a number but found '{}'",
+                token.line, token.text
+            )
+        })?;
+        if !(-128..=255).contains(&value) {
+            return Err(format!(
+                "{}: Value {} does not fit in a byte",
+                token.line, value
+            ));
+        }
+        Ok(value as u8)
+    }
+
+    fn expect_nibble(&mut self) -> Result<u16, String> {
+        let token = self.next_token()?;
+        match self.constant(&token) {
+            Some(value) if (0..16).contains(&value) => Ok(value as u16),
+            _ => Err(format!(
+                "{}: Expected a value from 0 to 15 but found '{}'",
+                token.line, token.text
+            )),
+        }
+    }
+
+    // an address operand, which may be a label that is defined later on
+    fn emit_address_operand(&mut self, opcode: u16) -> Result<(), String> {
+        let token = self.next_token()?;
+        if let Some(value) =
+            parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+        {
+            if !(0..0x1000).contains(&value) {
+                return Err(format!("{}: Address {} is out of range", token.line, value));
+            }
+            self.emit16(opcode | value as u16);
+            return Ok(());
+        }
+        self.emit_address(opcode, &token.text, token.line);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next_token()?;
+        let line = token.line;
+        let emits_nothing = matches!(
+            token.text.as_str(),
+            ":" | ":alias" | ":const" | ":calc" | ":macro"
+        ) || self.macros.contains_key(&token.text);
+        if !emits_nothing {
+            self.start_program();
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next_token()?;
+                if name.text != "main" {
+                    self.start_program();
+                }
+                if self.labels.insert(name.text.clone(), self.here()).is_some() {
+                    return Err(format!(
+                        "{line}: Label '{}' is defined more than once",
+                        name.text
+                    ));
+                }
+            }
+            ":alias" => {
+                let name = self.next_token()?;
+                let register = self.expect_register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.next_token()?;
+                let value = self.next_token()?;
+                let value = self.constant(&value).ok_or_else(|| {
+                    format!("{line}: Expected a number but found '{}'", value.text)
+                })?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.next_token()?;
+                let body = self.braced_tokens()?;
+                let value = self.calculate(&body, line)?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let value = self.expect_byte()?;
+                self.emit8(value);
+            }
+            "clear" => self.emit16(0x00E0),
+            "return" | ";" => self.emit16(0x00EE),
+            "jump" => self.emit_address_operand(0x1000)?,
+            "jump0" => self.emit_address_operand(0xB000)?,
+            "i" => self.assign_index()?,
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.expect_register()? as u16;
+                let low = if token.text == "delay" { 0x15 } else { 0x18 };
+                self.emit16(0xF000 | (x << 8) | low);
+            }
+            "sprite" => {
+                let x = self.expect_register()? as u16;
+                let y = self.expect_register()? as u16;
+                let n = self.expect_nibble()?;
+                self.emit16(0xD000 | (x << 8) | (y << 4) | n);
+            }
+            "bcd" | "save" | "load" => {
+                let x = self.expect_register()? as u16;
+                let low = match token.text.as_str() {
+                    "bcd" => 0x33,
+                    "save" => 0x55,
+                    _ => 0x65,
+                };
+                self.emit16(0xF000 | (x << 8) | low);
+            }
+            "if" => self.if_statement(line)?,
+            "else" => match self.control.pop() {
+                Some(Control::If { jump_at }) => {
+                    let end_jump = self.emit_placeholder_jump();
+                    let here = self.here();
+                    self.patch_jump(jump_at, here);
+                    self.control.push(Control::Else { jump_at: end_jump });
+                }
+                _ => return Err(format!("{line}: 'else' without 'if ... begin'")),
+            },
+            "end" => match self.control.pop() {
+                Some(Control::If { jump_at }) | Some(Control::Else { jump_at }) => {
+                    let here = self.here();
+                    self.patch_jump(jump_at, here);
+                }
+                _ => return Err(format!("{line}: 'end' without 'begin'")),
+            },
+            "loop" => {
+                let start = self.here();
+                self.control.push(Control::Loop {
+                    start,
+                    breaks: Vec::new(),
+                });
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit16(condition.skip_if_true);
+                let jump_at = self.emit_placeholder_jump();
+                match self
+                    .control
+                    .iter_mut()
+                    .rev()
+                    .find(|control| matches!(control, Control::Loop { .. }))
+                {
+                    Some(Control::Loop { breaks, .. }) => breaks.push(jump_at),
+                    _ => return Err(format!("{line}: 'while' outside of a loop")),
+                }
+            }
+            "again" => match self.control.pop() {
+                Some(Control::Loop { start, breaks }) => {
+                    self.emit16(0x1000 | start);
+                    let here = self.here();
+                    for jump_at in breaks {
+                        self.patch_jump(jump_at, here);
+                    }
+                }
+                _ => return Err(format!("{line}: 'again' without 'loop'")),
+            },
+            text if is_unsupported(text) => {
+                return Err(format!(
+                    "{line}: Unsupported construct '{text}' (SCHIP/XO-CHIP)"
+                ));
+            }
+            text if text.starts_with(':') => {
+                return Err(format!("{line}: Unsupported construct '{text}'"));
+            }
+            text if self.macros.contains_key(text) => self.expand_macro(&token)?,
+            _ => {
+                if let Some(x) = self.register(&token) {
+                    self.assign_register(x as u16, line)?;
+                } else if let Some(value) = self
+                    .constant(&token)
+                    .filter(|_| !self.labels.contains_key(&token.text))
+                {
+                    // bare numbers are raw data
+                    if !(-128..=255).contains(&value) {
+                        return Err(format!("{line}: Value {value} does not fit in a byte"));
+                    }
+                    self.emit8(value as u8);
+                } else {
+                    // any other name is a call to a subroutine, which may be defined later
+                    self.emit_address(0x2000, &token.text, line);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn assign_index(&mut self) -> Result<(), String> {
+        let operator = self.next_token()?;
+        match operator.text.as_str() {
+            ":=" => {
+                if self.peek_is("hex") {
+                    self.position += 1;
+                    let x = self.expect_register()? as u16;
+                    self.emit16(0xF029 | (x << 8));
+                    Ok(())
+                } else if self.peek_is("bighex") {
+                    Err(format!(
+                        "{}: Unsupported construct 'bighex' (SCHIP)",
+                        operator.line
+                    ))
+                } else {
+                    self.emit_address_operand(0xA000)
+                }
+            }
+            "+=" => {
+                let x = self.expect_register()? as u16;
+                self.emit16(0xF01E | (x << 8));
+                Ok(())
+            }
+            _ => Err(format!(
+                "{}: Unknown operator 'i {}'",
+                operator.line, operator.text
+            )),
+        }
+    }
+
+    fn assign_register(&mut self, x: u16, line: usize) -> Result<(), String> {
+        let operator = self.next_token()?;
+        let operand = self.next_token()?;
+
+        if operator.text == ":=" {
+            let opcode = match operand.text.as_str() {
+                "delay" => Some(0xF007 | (x << 8)),
+                "key" => Some(0xF00A | (x << 8)),
+                "random" => Some(0xC000 | (x << 8) | self.expect_byte()? as u16),
+                _ => None,
+            };
+            if let Some(opcode) = opcode {
+                self.emit16(opcode);
+                return Ok(());
+            }
+        }
+
+        if let Some(y) = self.register(&operand) {
+            let y = y as u16;
+            let low = match operator.text.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return Err(format!("{line}: Unknown operator '{}'", operator.text)),
+            };
+            self.emit16(0x8000 | (x << 8) | (y << 4) | low);
+            return Ok(());
+        }
+
+        // otherwise the operand is a constant
+        self.position -= 1;
+        let value = self.expect_byte()? as u16;
+        match operator.text.as_str() {
+            ":=" => self.emit16(0x6000 | (x << 8) | value),
+            "+=" => self.emit16(0x7000 | (x << 8) | value),
+            "-=" => self.emit16(0x7000 | (x << 8) | (value.wrapping_neg() & 0xFF)),
+            _ => {
+                return Err(format!(
+                    "{line}: Operator '{}' needs a register on the right hand side",
+                    operator.text
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.expect_register()? as u16;
+        let operator = self.next_token()?;
+        let line = operator.line;
+
+        let (skip_if_true, skip_if_false) = match operator.text.as_str() {
+            "key" => (0xE09E | (x << 8), 0xE0A1 | (x << 8)),
+            "-key" => (0xE0A1 | (x << 8), 0xE09E | (x << 8)),
+            "==" | "!=" => {
+                let operand = self.next_token()?;
+                let (equal, not_equal) = match self.register(&operand) {
+                    Some(y) => {
+                        let y = y as u16;
+                        (0x5000 | (x << 8) | (y << 4), 0x9000 | (x << 8) | (y << 4))
+                    }
+                    None => {
+                        self.position -= 1;
+                        let value = self.expect_byte()? as u16;
+                        (0x3000 | (x << 8) | value, 0x4000 | (x << 8) | value)
+                    }
+                };
+                if operator.text == "==" {
+                    (equal, not_equal)
+                } else {
+                    (not_equal, equal)
+                }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                return Err(format!(
+                    "{line}: Unsupported construct '{}' (comparisons that clobber vf)",
+                    operator.text
+                ))
+            }
+            _ => return Err(format!("{line}: Unknown comparison '{}'", operator.text)),
+        };
+
+        Ok(Condition {
+            skip_if_true,
+            skip_if_false,
+        })
+    }
+
+    fn if_statement(&mut self, line: usize) -> Result<(), String> {
+        let condition = self.condition()?;
+        let keyword = self.next_token()?;
+        match keyword.text.as_str() {
+            // the next statement is skipped when the condition doesn't hold
+            "then" => self.emit16(condition.skip_if_false),
+            "begin" => {
+                self.emit16(condition.skip_if_true);
+                let jump_at = self.emit_placeholder_jump();
+                self.control.push(Control::If { jump_at });
+            }
+            _ => {
+                return Err(format!(
+                    "{line}: Expected 'then' or 'begin' but found '{}'",
+                    keyword.text
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // everything between a { and its matching }
+    fn braced_tokens(&mut self) -> Result<Vec<Token>, String> {
+        self.expect("{")?;
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return Ok(body);
+            }
+            body.push(token);
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next_token()?;
+        let mut parameters = Vec::new();
+        while !self.peek_is("{") {
+            parameters.push(self.next_token()?.text);
+        }
+        let body = self.braced_tokens()?;
+        self.macros.insert(name.text, Macro { parameters, body });
+        Ok(())
+    }
+
+    // replaces the macro's parameters in its body and splices the result into the token stream
+    fn expand_macro(&mut self, name: &Token) -> Result<(), String> {
+        // expansions read to the end are finished, unless this macro was their last token
+        let position = self.position;
+        self.expansion_ends.retain(|end| *end >= position);
+        if self.expansion_ends.len() >= MAX_MACRO_DEPTH {
+            return Err(format!(
+                "{}: Macros nested more than {MAX_MACRO_DEPTH} deep (recursive macro?)",
+                name.line
+            ));
+        }
+
+        let parameter_count = self.macros[&name.text].parameters.len();
+        let mut arguments = HashMap::new();
+        for index in 0..parameter_count {
+            let argument = self.next_token()?;
+            let parameter = self.macros[&name.text].parameters[index].clone();
+            arguments.insert(parameter, argument.text);
+        }
+
+        let expanded: Vec<Token> = self.macros[&name.text]
+            .body
+            .iter()
+            .map(|token| Token {
+                text: arguments.get(&token.text).unwrap_or(&token.text).clone(),
+                line: name.line,
+            })
+            .collect();
+        // the expansions this one is inside end later now
+        for end in &mut self.expansion_ends {
+            *end += expanded.len();
+        }
+        self.expansion_ends.push(self.position + expanded.len());
+        self.tokens.splice(self.position..self.position, expanded);
+        Ok(())
+    }
+
+    fn calculate(&self, tokens: &[Token], line: usize) -> Result<i64, String> {
+        let mut calculator = Calculator {
+            assembler: self,
+            tokens,
+            position: 0,
+            line,
+        };
+        let value = calculator.expression(0)?;
+        if calculator.position != tokens.len() {
+            return Err(format!(
+                "{line}: Unexpected '{}' in :calc",
+                tokens[calculator.position].text
+            ));
+        }
+        Ok(value)
+    }
+}
+
+// precedence climbing evaluator for the body of a :calc
+struct Calculator<'a> {
+    assembler: &'a Assembler,
+    tokens: &'a [Token],
+    position: usize,
+    line: usize,
+}
+
+impl Calculator<'_> {
+    fn precedence(operator: &str) -> Option<u8> {
+        match operator {
+            "|" => Some(1),
+            "^" => Some(2),
+            "&" => Some(3),
+            "<<" | ">>" => Some(4),
+            "+" | "-" => Some(5),
+            "*" | "/" | "%" => Some(6),
+            _ => None,
+        }
+    }
+
+    fn expression(&mut self, minimum: u8) -> Result<i64, String> {
+        let mut left = self.operand()?;
+        while let Some(token) = self.tokens.get(self.position) {
+            let precedence = match Calculator::precedence(&token.text) {
+                Some(precedence) if precedence > minimum => precedence,
+                _ => break,
+            };
+            let operator = token.text.clone();
+            self.position += 1;
+            let right = self.expression(precedence)?;
+            left = match operator.as_str() {
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left << right,
+                ">>" => left >> right,
+                "+" => left + right,
+                "-" => left - right,
+                "*" => left * right,
+                "/" | "%" if right == 0 => {
+                    return Err(format!("{}: Division by zero in :calc", self.line))
+                }
+                "/" => left / right,
+                _ => left % right,
+            };
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<i64, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| format!("{}: Unexpected end of :calc", self.line))?;
+        self.position += 1;
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression(0)?;
+                match self.tokens.get(self.position) {
+                    Some(token) if token.text == ")" => {
+                        self.position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(format!("{}: Missing ')' in :calc", self.line)),
+                }
+            }
+            "-" => Ok(-self.operand()?),
+            "HERE" => Ok(self.assembler.here() as i64),
+            _ => self
+                .assembler
+                .constant(token)
+                .ok_or_else(|| format!("{}: Unknown value '{}' in :calc", self.line, token.text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(image: &[u8]) -> Vec<u16> {
+        image
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+            .collect()
+    }
+
+    #[test]
+    fn test_assemble_basic() {
+        let source = r#"
+            : main
+                clear
+                v0 := 10     # LD V0, 10
+                v1 += 5
+                v0 := v1
+                v0 -= v1
+                v0 =- v1
+                i := sprite
+                sprite v0 v1 5
+                delay := v0
+                v2 := delay
+                v3 := key
+            : sprite
+                0xFF 0x81 0x81 0x81 0xFF
+        "#;
+        let image = assemble(source).unwrap();
+        assert_eq!(
+            words(&image[..22]),
+            vec![
+                0x00E0, 0x600A, 0x7105, 0x8010, 0x8015, 0x8017, 0xA216, 0xD015, 0xF015, 0xF207,
+                0xF30A
+            ]
+        );
+        assert_eq!(&image[22..], &[0xFF, 0x81, 0x81, 0x81, 0xFF]);
+    }
+
+    #[test]
+    fn test_assemble_main_jump() {
+        let source = r#"
+            : helper
+                return
+            : main
+                helper
+                jump main
+        "#;
+        let image = assemble(source).unwrap();
+        assert_eq!(words(&image), vec![0x1204, 0x00EE, 0x2202, 0x1204]);
+    }
+
+    #[test]
+    fn test_assemble_if_then() {
+        let image = assemble(": main if v0 == 3 then v1 := 1 if v0 != v2 then clear").unwrap();
+        assert_eq!(words(&image), vec![0x4003, 0x6101, 0x5020, 0x00E0]);
+        let image = assemble(": main if v0 == v1 then v2 := 1").unwrap();
+        assert_eq!(words(&image), vec![0x9010, 0x6201]);
+    }
+
+    #[test]
+    fn test_assemble_begin_else_end() {
+        let source = r#"
+            : main
+                if v0 key begin
+                    v1 := 1
+                else
+                    v1 := 2
+                end
+        "#;
+        let image = assemble(source).unwrap();
+        assert_eq!(words(&image), vec![0xE09E, 0x1208, 0x6101, 0x120A, 0x6102]);
+    }
+
+    #[test]
+    fn test_assemble_loop() {
+        let source = r#"
+            : main
+                loop
+                    v0 += 1
+                    while v0 != 10
+                again
+        "#;
+        let image = assemble(source).unwrap();
+        assert_eq!(words(&image), vec![0x7001, 0x400A, 0x1208, 0x1200]);
+    }
+
+    #[test]
+    fn test_assemble_alias_const_calc_macro() {
+        let source = r#"
+            :alias counter v4
+            :const SPEED 3
+            :calc DOUBLE { SPEED * 2 + ( 1 << 2 ) }
+            :macro bump reg amount { reg += amount }
+            : main
+                counter := SPEED
+                bump counter DOUBLE
+        "#;
+        let image = assemble(source).unwrap();
+        assert_eq!(words(&image), vec![0x6403, 0x740A]);
+    }
+
+    #[test]
+    fn test_macro_depth() {
+        // a small macro used many times over is fine
+        let source = format!(
+            ":macro bump {{ v0 += 1 }}\n: main\n{}",
+            "bump\n".repeat(20_000)
+        );
+        assert_eq!(assemble(&source).unwrap().len(), 40_000);
+        for source in [
+            ":macro forever { forever }\n: main forever",
+            ":macro forever { v0 += 1 forever v1 += 1 }\n: main forever",
+        ] {
+            assert!(assemble(source)
+                .unwrap_err()
+                .contains("nested more than 1000 deep"));
+        }
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert!(assemble(": main hires")
+            .unwrap_err()
+            .contains("Unsupported construct"));
+        assert!(assemble(": main :unpack 0xA data")
+            .unwrap_err()
+            .contains("Unsupported construct"));
+        assert!(assemble(": main if v0 < 3 then clear")
+            .unwrap_err()
+            .contains("Unsupported"));
+        assert!(assemble("clear").unwrap_err().contains("main"));
+        assert!(assemble(": main jump nowhere")
+            .unwrap_err()
+            .contains("Undefined label"));
+        assert!(assemble(": main loop clear")
+            .unwrap_err()
+            .contains("Unclosed"));
+        assert!(assemble(": main v0 := 300").unwrap_err().starts_with("1:"));
+    }
+}
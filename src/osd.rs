@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use crate::frontend::Stats;
+
+// Text drawn over the top left of the screen: messages that disappear after a couple
+// of seconds, optionally a line of stats that stays, and whatever a script draws.
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+// older messages are dropped if too many arrive at once
+const MAX_MESSAGES: usize = 4;
+
+pub struct Osd {
+    // each message and when it was shown
+    messages: Vec<(String, Instant)>,
+    stats: Option<String>,
+    show_stats: bool,
+    overlay: Vec<String>,
+}
+
+impl Osd {
+    pub fn new(show_stats: bool) -> Self {
+        Self {
+            messages: Vec::new(),
+            stats: None,
+            show_stats,
+            overlay: Vec::new(),
+        }
+    }
+
+    pub fn message(&mut self, text: &str, now: Instant) {
+        self.messages.push((text.to_owned(), now));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    pub fn set_stats(&mut self, stats: &Stats) {
+        self.stats = Some(format!(
+            "{:.0} FPS  {} IPS  QUIRKS {}",
+            stats.frames_per_second, stats.instructions_per_second, stats.quirks
+        ));
+    }
+
+    pub fn set_overlay(&mut self, lines: &[String]) {
+        self.overlay = lines.to_vec();
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+    }
+
+    // what to draw, top to bottom, with the stats first and messages last
+    pub fn lines(&mut self, now: Instant) -> Vec<String> {
+        self.messages
+            .retain(|(_, shown)| now.duration_since(*shown) < MESSAGE_DURATION);
+        let stats = self.stats.iter().filter(|_| self.show_stats);
+        stats
+            .chain(&self.overlay)
+            .chain(self.messages.iter().map(|(text, _)| text))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn test_messages_expire() {
+        let start = Instant::now();
+        let mut osd = Osd::new(false);
+        osd.message("State saved to slot 2", start);
+        osd.message("Paused", start + Duration::from_secs(1));
+        assert_eq!(osd.lines(start), vec!["State saved to slot 2", "Paused"]);
+        assert_eq!(osd.lines(start + Duration::from_secs(2)), vec!["Paused"]);
+        assert!(osd.lines(start + Duration::from_secs(3)).is_empty());
+
+        for i in 0..6 {
+            osd.message(&i.to_string(), start);
+        }
+        assert_eq!(osd.lines(start), vec!["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let now = Instant::now();
+        let mut osd = Osd::new(false);
+        osd.set_stats(&Stats {
+            frames_per_second: 59.9,
+            instructions_per_second: 600,
+            quirks: Quirks::default(),
+        });
+        assert!(osd.lines(now).is_empty());
+        osd.toggle_stats();
+        assert_eq!(osd.lines(now), vec!["60 FPS  600 IPS  QUIRKS none"]);
+
+        osd.message("Paused", now);
+        osd.set_overlay(&["LIVES 3".to_owned()]);
+        assert_eq!(
+            osd.lines(now),
+            vec!["60 FPS  600 IPS  QUIRKS none", "LIVES 3", "Paused"]
+        );
+    }
+}
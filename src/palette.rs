@@ -0,0 +1,161 @@
+// The colours the screen is drawn with, as RGB.
+// The default is what the SDL window has always drawn: black pixels on white.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Palette {
+    pub background: [u8; 3],
+    pub foreground: [u8; 3],
+    // XO-CHIP has a second plane, and pixels set in both planes get a colour of their own
+    pub plane2: [u8; 3],
+    pub both: [u8; 3],
+    pub grid: [u8; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::named("default").unwrap()
+    }
+}
+
+// the palettes that can be chosen by name, in the order Tab cycles through them
+pub const NAMES: [&str; 5] = ["default", "green", "amber", "lcd", "high-contrast"];
+
+const fn rgb(color: u32) -> [u8; 3] {
+    [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+}
+
+// a quarter of the way from one colour to another
+fn blend(from: [u8; 3], to: [u8; 3]) -> [u8; 3] {
+    let mut color = from;
+    for (channel, target) in color.iter_mut().zip(to) {
+        *channel = ((*channel as u16 * 3 + target as u16) / 4) as u8;
+    }
+    color
+}
+
+fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let hex = text.trim().trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(rgb(color)),
+        _ => Err(format!("Invalid colour '{text}', expected RRGGBB")),
+    }
+}
+
+impl Palette {
+    pub fn named(name: &str) -> Option<Self> {
+        // background, plane 1, plane 2, both planes, grid lines
+        let colors = match name {
+            "default" => [0xFFFFFF, 0x000000, 0xAAAAAA, 0x555555, 0x000000],
+            // green phosphor
+            "green" => [0x0A1A0A, 0x33FF66, 0x1F9940, 0x99FFB3, 0x122A12],
+            "amber" => [0x1A1000, 0xFFB000, 0x996A00, 0xFFD780, 0x2A1C00],
+            // an old handheld's LCD
+            "lcd" => [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F, 0x8BAC0F],
+            "high-contrast" => [0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF, 0x202020],
+            _ => return None,
+        };
+        Some(Palette {
+            background: rgb(colors[0]),
+            foreground: rgb(colors[1]),
+            plane2: rgb(colors[2]),
+            both: rgb(colors[3]),
+            grid: rgb(colors[4]),
+        })
+    }
+
+    // a name, or comma separated hex colours: background and foreground,
+    // optionally followed by the plane 2 and both planes colours
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if let Some(palette) = Palette::named(text) {
+            return Ok(palette);
+        }
+        let colors = text
+            .split(',')
+            .map(parse_color)
+            .collect::<Result<Vec<[u8; 3]>, String>>()
+            .map_err(|error| format!("Unknown palette '{text}': {error}"))?;
+        match colors.as_slice() {
+            [background, foreground] => Ok(Palette {
+                background: *background,
+                foreground: *foreground,
+                plane2: blend(*foreground, *background),
+                both: *foreground,
+                grid: blend(*background, *foreground),
+            }),
+            [background, foreground, plane2, both] => Ok(Palette {
+                background: *background,
+                foreground: *foreground,
+                plane2: *plane2,
+                both: *both,
+                grid: blend(*background, *foreground),
+            }),
+            _ => Err(format!(
+                "Unknown palette '{text}', expected a name or 2 or 4 colours"
+            )),
+        }
+    }
+
+    // the named palette after this one, wrapping around
+    pub fn next(&self) -> Self {
+        let index = NAMES
+            .iter()
+            .position(|name| Palette::named(name).as_ref() == Some(self))
+            .map_or(0, |index| (index + 1) % NAMES.len());
+        Palette::named(NAMES[index]).unwrap()
+    }
+
+    // the colour of a framebuffer pixel, where bit 0 is plane 1 and bit 1 is plane 2
+    pub fn color(&self, pixel: u8) -> [u8; 3] {
+        match pixel & 0b11 {
+            0 => self.background,
+            1 => self.foreground,
+            2 => self.plane2,
+            _ => self.both,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named() {
+        for name in NAMES {
+            assert!(Palette::named(name).is_some());
+        }
+        assert_eq!(Palette::default().background, [0xFF, 0xFF, 0xFF]);
+        assert_eq!(
+            Palette::named("amber").unwrap().foreground,
+            [0xFF, 0xB0, 0x00]
+        );
+        assert!(Palette::named("purple").is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        let palette = Palette::parse("#102030,ffffff").unwrap();
+        assert_eq!(palette.background, [0x10, 0x20, 0x30]);
+        assert_eq!(palette.color(1), [0xFF, 0xFF, 0xFF]);
+        let palette = Palette::parse("000000,111111,222222,333333").unwrap();
+        assert_eq!(palette.color(2), [0x22, 0x22, 0x22]);
+        assert_eq!(palette.color(3), [0x33, 0x33, 0x33]);
+        assert_eq!(Palette::parse("lcd"), Ok(Palette::named("lcd").unwrap()));
+        assert!(Palette::parse("000000").is_err());
+        assert!(Palette::parse("000000,12345").is_err());
+    }
+
+    #[test]
+    fn test_next() {
+        let mut palette = Palette::default();
+        for name in NAMES.iter().skip(1) {
+            palette = palette.next();
+            assert_eq!(palette, Palette::named(name).unwrap());
+        }
+        assert_eq!(palette.next(), Palette::default());
+        // a custom palette goes back to the first named one
+        assert_eq!(
+            Palette::parse("123456,654321").unwrap().next(),
+            Palette::default()
+        );
+    }
+}
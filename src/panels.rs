@@ -0,0 +1,195 @@
+use crate::instruction::Instruction;
+
+// The text of the debug panels drawn around the screen: registers, the stack and
+// disassembly in a column to the right, and memory around I underneath.
+
+// the width of the column, in characters
+pub const COLUMNS: usize = 28;
+
+// bytes per row of the memory panel
+const MEMORY_ROW: usize = 8;
+
+// the machine as the panels show it, taken every frame while they're on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugView {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub stack: Vec<u16>,
+    pub delay_timer: u32,
+    pub sound_timer: u32,
+    pub memory: Vec<u8>,
+    pub breakpoints: Vec<u16>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PanelLine {
+    pub text: String,
+    // characters drawn on a highlight, as start and length
+    pub highlight: Option<(usize, usize)>,
+    // for disassembly, so clicking the line can toggle a breakpoint there
+    pub address: Option<u16>,
+}
+
+impl PanelLine {
+    fn text(text: String) -> Self {
+        Self {
+            text,
+            highlight: None,
+            address: None,
+        }
+    }
+}
+
+impl DebugView {
+    fn word(&self, address: usize) -> u16 {
+        let byte = |address: usize| *self.memory.get(address).unwrap_or(&0) as u16;
+        (byte(address) << 8) | byte(address + 1)
+    }
+
+    // registers and the stack, then as many lines of disassembly as there's room for,
+    // starting a few instructions before PC
+    pub fn side_panel(&self, disassembly_lines: usize) -> Vec<PanelLine> {
+        let mut lines = vec![
+            PanelLine::text(format!(
+                "PC {:03X}  I {:03X}  SP {}",
+                self.pc,
+                self.i,
+                self.stack.len()
+            )),
+            PanelLine::text(format!(
+                "DT {:02X}  ST {:02X}",
+                self.delay_timer, self.sound_timer
+            )),
+        ];
+        for (row, values) in self.v.chunks(4).enumerate() {
+            let registers: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value))
+                .collect();
+            lines.push(PanelLine::text(registers.join(" ")));
+        }
+
+        // the most recent return address first
+        let mut stack = "STACK".to_owned();
+        for address in self.stack.iter().rev() {
+            if stack.len() + 4 > COLUMNS {
+                break;
+            }
+            stack += &format!(" {address:03X}");
+        }
+        lines.push(PanelLine::text(String::new()));
+        lines.push(PanelLine::text(stack));
+        lines.push(PanelLine::text(String::new()));
+
+        let start = (self.pc as usize).saturating_sub(disassembly_lines / 3 * 2);
+        for address in (start..4095).step_by(2).take(disassembly_lines) {
+            let word = self.word(address);
+            let mnemonic = match Instruction::try_decode(word) {
+                Ok(instruction) => instruction.to_string(),
+                Err(_) => "???".to_owned(),
+            };
+            let breakpoint = if self.breakpoints.contains(&(address as u16)) {
+                '*'
+            } else {
+                ' '
+            };
+            let at_pc = address == self.pc as usize;
+            let marker = if at_pc { '>' } else { ' ' };
+            let mut text = format!("{breakpoint}{marker}{address:03X} {word:04X} {mnemonic}");
+            text.truncate(COLUMNS);
+            lines.push(PanelLine {
+                highlight: at_pc.then_some((0, text.len())),
+                text,
+                address: Some(address as u16),
+            });
+        }
+        lines
+    }
+
+    // rows of memory with the byte at I highlighted, starting a couple of rows before it
+    pub fn memory_panel(&self, rows: usize) -> Vec<PanelLine> {
+        let last_row = (4096 / MEMORY_ROW).saturating_sub(rows);
+        let first_row = (self.i as usize / MEMORY_ROW)
+            .saturating_sub(2)
+            .min(last_row);
+        (first_row..first_row + rows)
+            .map(|row| {
+                let start = row * MEMORY_ROW;
+                let bytes: Vec<String> = (start..start + MEMORY_ROW)
+                    .map(|address| format!("{:02X}", self.memory.get(address).unwrap_or(&0)))
+                    .collect();
+                let i = self.i as usize;
+                PanelLine {
+                    text: format!("{start:03X}  {}", bytes.join(" ")),
+                    highlight: (start..start + MEMORY_ROW)
+                        .contains(&i)
+                        .then_some((5 + (i - start) * 3, 2)),
+                    address: None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view() -> DebugView {
+        let mut memory = vec![0; 4096];
+        memory[0x200..0x206].copy_from_slice(&[0x60, 0x01, 0xA3, 0x00, 0xD0, 0x15]);
+        memory[0x300] = 0xFF;
+        let mut v = [0; 16];
+        v[0xA] = 0x42;
+        DebugView {
+            pc: 0x202,
+            i: 0x300,
+            v,
+            stack: vec![0x206, 0x30A],
+            delay_timer: 0x3C,
+            sound_timer: 0x05,
+            memory,
+            breakpoints: vec![0x204],
+        }
+    }
+
+    #[test]
+    fn test_side_panel() {
+        let lines = view().side_panel(3);
+        let text: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "PC 202  I 300  SP 2",
+                "DT 3C  ST 05",
+                "V0 00 V1 00 V2 00 V3 00",
+                "V4 00 V5 00 V6 00 V7 00",
+                "V8 00 V9 00 VA 42 VB 00",
+                "VC 00 VD 00 VE 00 VF 00",
+                "",
+                "STACK 30A 206",
+                "",
+                "  200 6001 LD V0, 0x01",
+                " >202 A300 LD I, 0x300",
+                "* 204 D015 DRW V0, V1, 5",
+            ]
+        );
+        assert_eq!(lines[10].highlight, Some((0, 22)));
+        assert_eq!(lines[11].address, Some(0x204));
+    }
+
+    #[test]
+    fn test_memory_panel() {
+        let lines = view().memory_panel(3);
+        assert_eq!(lines[0].text, "2F0  00 00 00 00 00 00 00 00");
+        assert_eq!(lines[2].text, "300  FF 00 00 00 00 00 00 00");
+        assert_eq!(lines[2].highlight, Some((5, 2)));
+        assert_eq!(lines[0].highlight, None);
+
+        // stops at the end of memory
+        let view = DebugView { i: 0xFFF, ..view() };
+        assert_eq!(view.memory_panel(4)[3].text, "FF8  00 00 00 00 00 00 00 00");
+    }
+}
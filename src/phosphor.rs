@@ -0,0 +1,125 @@
+use crate::palette::Palette;
+
+// Games redraw sprites by XORing them off and back on, so a moving sprite is missing
+// for part of every other frame and flickers. These filters smooth that out using the
+// frames the frontend has already drawn, without touching the emulated framebuffer.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phosphor {
+    // a pixel that goes off keeps this fraction of its brightness each frame, like a CRT
+    Decay(f32),
+    // a pixel is lit if it was lit in this frame or the one before
+    LastTwo,
+}
+
+// mixes from one colour towards another, by an amount from 0 to 1
+fn mix(from: [u8; 3], to: [u8; 3], amount: f32) -> [u8; 3] {
+    let mut color = from;
+    for (channel, target) in color.iter_mut().zip(to) {
+        *channel = (*channel as f32 + (target as f32 - *channel as f32) * amount).round() as u8;
+    }
+    color
+}
+
+pub struct PhosphorFilter {
+    phosphor: Phosphor,
+    // per pixel: how lit it is from 0 to 1, and the value it was last lit with
+    brightness: Vec<f32>,
+    lit: Vec<u8>,
+    previous: Vec<u8>,
+}
+
+impl PhosphorFilter {
+    pub fn new(phosphor: Phosphor) -> Self {
+        Self {
+            phosphor,
+            brightness: Vec::new(),
+            lit: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    // adds a frame to the history and returns the colour of every pixel
+    pub fn apply(&mut self, framebuffer: &[u8], palette: &Palette) -> Vec<[u8; 3]> {
+        // start again if the screen changes size
+        if self.previous.len() != framebuffer.len() {
+            self.brightness = vec![0.0; framebuffer.len()];
+            self.lit = vec![0; framebuffer.len()];
+            self.previous = framebuffer.to_vec();
+        }
+
+        let colors = match self.phosphor {
+            Phosphor::LastTwo => framebuffer
+                .iter()
+                .zip(&self.previous)
+                .map(|(pixel, previous)| palette.color(pixel | previous))
+                .collect(),
+            Phosphor::Decay(decay) => {
+                for (i, pixel) in framebuffer.iter().enumerate() {
+                    if *pixel != 0 {
+                        self.brightness[i] = 1.0;
+                        self.lit[i] = *pixel;
+                    } else {
+                        self.brightness[i] *= decay;
+                        // too dim to change the colour
+                        if self.brightness[i] < 1.0 / 255.0 {
+                            self.brightness[i] = 0.0;
+                        }
+                    }
+                }
+                self.brightness
+                    .iter()
+                    .zip(&self.lit)
+                    .map(|(brightness, lit)| {
+                        mix(palette.background, palette.color(*lit), *brightness)
+                    })
+                    .collect()
+            }
+        };
+        self.previous.copy_from_slice(framebuffer);
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+    const BLACK: [u8; 3] = [0, 0, 0];
+
+    #[test]
+    fn test_last_two() {
+        let palette = Palette::default();
+        let mut filter = PhosphorFilter::new(Phosphor::LastTwo);
+        assert_eq!(filter.apply(&[1, 0], &palette), vec![BLACK, WHITE]);
+        // the sprite is XORed off, but still shows from the frame before
+        assert_eq!(filter.apply(&[0, 0], &palette), vec![BLACK, WHITE]);
+        assert_eq!(filter.apply(&[0, 1], &palette), vec![WHITE, BLACK]);
+    }
+
+    #[test]
+    fn test_decay() {
+        let palette = Palette::default();
+        let mut filter = PhosphorFilter::new(Phosphor::Decay(0.5));
+        assert_eq!(filter.apply(&[1, 0], &palette), vec![BLACK, WHITE]);
+        // half way back to the white background, then a quarter
+        assert_eq!(filter.apply(&[0, 0], &palette)[0], [0x80, 0x80, 0x80]);
+        assert_eq!(filter.apply(&[0, 0], &palette)[0], [0xBF, 0xBF, 0xBF]);
+        for _ in 0..8 {
+            filter.apply(&[0, 0], &palette);
+        }
+        assert_eq!(filter.apply(&[0, 0], &palette), vec![WHITE, WHITE]);
+        // lit again straight away
+        assert_eq!(filter.apply(&[1, 0], &palette)[0], BLACK);
+    }
+
+    #[test]
+    fn test_decay_keeps_plane_colour() {
+        let palette = Palette::named("high-contrast").unwrap();
+        let mut filter = PhosphorFilter::new(Phosphor::Decay(0.5));
+        filter.apply(&[2], &palette);
+        // yellow fading towards the black background
+        assert_eq!(filter.apply(&[0], &palette), vec![[0x80, 0x80, 0]]);
+    }
+}
@@ -0,0 +1,64 @@
+// Behaviours that differ between CHIP-8 interpreters.
+// The defaults are what this emulator has always done.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Quirks {
+    // AND, OR and XOR set VF to 0 (original COSMAC VIP)
+    pub logic_resets_vf: bool,
+    // BXNN jumps to XNN + VX instead of NNN + V0 (CHIP-48 and SCHIP)
+    pub jump_uses_vx: bool,
+    // sprites are cut off at the edge of the screen instead of wrapping around
+    pub clip_sprites: bool,
+}
+
+// the names --quirks takes, or none
+impl std::fmt::Display for Quirks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let names: Vec<&str> = [
+            (self.logic_resets_vf, "vf-reset"),
+            (self.jump_uses_vx, "jump-vx"),
+            (self.clip_sprites, "clip"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, name)| *name)
+        .collect();
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+impl Quirks {
+    pub fn to_bits(self) -> u8 {
+        (self.logic_resets_vf as u8)
+            | (self.jump_uses_vx as u8) << 1
+            | (self.clip_sprites as u8) << 2
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            logic_resets_vf: bits & 1 != 0,
+            jump_uses_vx: bits & 0b10 != 0,
+            clip_sprites: bits & 0b100 != 0,
+        }
+    }
+
+    // the comma separated names --quirks takes, or none, like Display writes them
+    pub fn parse(names: &str) -> Result<Self, String> {
+        let mut quirks = Quirks::default();
+        if names == "none" {
+            return Ok(quirks);
+        }
+        for name in names.split(',') {
+            match name.trim() {
+                "vf-reset" => quirks.logic_resets_vf = true,
+                "jump-vx" => quirks.jump_uses_vx = true,
+                "clip" => quirks.clip_sprites = true,
+                _ => return Err(format!("Unknown quirk '{name}'")),
+            }
+        }
+        Ok(quirks)
+    }
+}
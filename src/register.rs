@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Register {
     PC = 0, // Program counter is 16 bits
@@ -43,4 +45,46 @@ impl Register {
             _ => panic!("Invalid v register number")
         }
     }
+
+    // pc, i, dt or v0-vf in either case, as scripts and the control socket name them
+    pub fn from_name(name: &str) -> Option<Register> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "pc" => Some(Register::PC),
+            "i" => Some(Register::IR),
+            "dt" => Some(Register::DELAY_TIMER),
+            _ => {
+                let digit = name.strip_prefix('v').filter(|digit| digit.len() == 1)?;
+                u8::from_str_radix(digit, 16).ok().map(Register::v_register_from)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::PC => write!(f, "PC"),
+            Register::IR => write!(f, "I"),
+            Register::DELAY_TIMER => write!(f, "DT"),
+            // the V registers are laid out in order after V0
+            v_register => write!(f, "V{:X}", *v_register as u8 - Register::V0 as u8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Register::from_name("PC"), Some(Register::PC));
+        assert_eq!(Register::from_name("i"), Some(Register::IR));
+        assert_eq!(Register::from_name("dt"), Some(Register::DELAY_TIMER));
+        assert_eq!(Register::from_name("vA"), Some(Register::VA));
+        assert_eq!(Register::from_name("v10"), None);
+        assert_eq!(Register::from_name("v+"), None);
+        assert_eq!(Register::from_name("sp"), None);
+    }
 }
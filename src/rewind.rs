@@ -0,0 +1,244 @@
+use std::collections::VecDeque;
+
+// A bounded history of save states for rewinding.
+// Every keyframe_interval-th snapshot is stored in full, and the rest only store
+// the runs of bytes that differ from the keyframe before them, which for a
+// CHIP-8 program is usually a handful of registers and a few framebuffer rows.
+// The cycles the timers ticked at are kept with the snapshot before them, so going
+// back to a cycle between snapshots can tick them at the same points again.
+
+enum Contents {
+    Keyframe(Vec<u8>),
+    // (offset, bytes) runs that differ from the preceding keyframe
+    Delta(Vec<(usize, Vec<u8>)>),
+}
+
+struct Snapshot {
+    cycle: u64,
+    contents: Contents,
+    // when the timers ticked after this snapshot was taken
+    ticks: Vec<u64>,
+}
+
+pub struct RewindBuffer {
+    capacity: usize,
+    keyframe_interval: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+// runs of bytes in state that are different in keyframe
+fn diff(keyframe: &[u8], state: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+    for (offset, (old, new)) in keyframe.iter().zip(state).enumerate() {
+        if old == new {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((start, bytes)) if *start + bytes.len() == offset => bytes.push(*new),
+            _ => runs.push((offset, vec![*new])),
+        }
+    }
+    runs
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, keyframe_interval: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            keyframe_interval: keyframe_interval.max(1),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    // the cycle count of the newest snapshot
+    pub fn last_cycle(&self) -> Option<u64> {
+        self.snapshots.back().map(|snapshot| snapshot.cycle)
+    }
+
+    // index of the keyframe that the snapshot at index is stored against
+    fn keyframe_index(&self, index: usize) -> Option<usize> {
+        (0..=index)
+            .rev()
+            .find(|i| matches!(self.snapshots[*i].contents, Contents::Keyframe(_)))
+    }
+
+    fn keyframe_data(&self, index: usize) -> &[u8] {
+        match &self.snapshots[index].contents {
+            Contents::Keyframe(data) => data,
+            Contents::Delta(_) => unreachable!("not a keyframe"),
+        }
+    }
+
+    pub fn push(&mut self, cycle: u64, state: Vec<u8>) {
+        let contents = match self.snapshots.len().checked_sub(1) {
+            Some(newest) => match self.keyframe_index(newest) {
+                Some(keyframe)
+                    if newest - keyframe + 1 < self.keyframe_interval
+                        && self.keyframe_data(keyframe).len() == state.len() =>
+                {
+                    Contents::Delta(diff(self.keyframe_data(keyframe), &state))
+                }
+                _ => Contents::Keyframe(state),
+            },
+            None => Contents::Keyframe(state),
+        };
+        self.snapshots.push_back(Snapshot {
+            cycle,
+            contents,
+            ticks: Vec::new(),
+        });
+
+        if self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+            // deltas can't be rebuilt once their keyframe is gone
+            while matches!(
+                self.snapshots.front(),
+                Some(Snapshot {
+                    contents: Contents::Delta(_),
+                    ..
+                })
+            ) {
+                self.snapshots.pop_front();
+            }
+        }
+    }
+
+    fn rebuild(&self, index: usize) -> Vec<u8> {
+        let keyframe = self
+            .keyframe_index(index)
+            .expect("rewind buffer always starts with a keyframe");
+        let mut state = self.keyframe_data(keyframe).to_vec();
+        if let Contents::Delta(runs) = &self.snapshots[index].contents {
+            for (offset, bytes) in runs {
+                state[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        state
+    }
+
+    // the timers ticking at a cycle, which counts against the newest snapshot
+    pub fn tick(&mut self, cycle: u64) {
+        if let Some(snapshot) = self.snapshots.back_mut() {
+            snapshot.ticks.push(cycle);
+        }
+    }
+
+    // removes and returns the newest snapshot, for playing backwards
+    pub fn pop(&mut self) -> Option<(u64, Vec<u8>)> {
+        let index = self.snapshots.len().checked_sub(1)?;
+        let state = self.rebuild(index);
+        let snapshot = self.snapshots.pop_back()?;
+        Some((snapshot.cycle, state))
+    }
+
+    // the newest snapshot taken at or before the given cycle, and the cycles the timers
+    // ticked at between it and that cycle
+    pub fn nearest(&self, cycle: u64) -> Option<(u64, Vec<u8>, Vec<u64>)> {
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.cycle <= cycle)?;
+        let snapshot = &self.snapshots[index];
+        let ticks = snapshot
+            .ticks
+            .iter()
+            .copied()
+            .filter(|tick| *tick <= cycle)
+            .collect();
+        Some((snapshot.cycle, self.rebuild(index), ticks))
+    }
+
+    // drops everything newer than the given cycle, since that future is being rewritten
+    pub fn truncate_after(&mut self, cycle: u64) {
+        while matches!(self.snapshots.back(), Some(snapshot) if snapshot.cycle > cycle) {
+            self.snapshots.pop_back();
+        }
+        if let Some(snapshot) = self.snapshots.back_mut() {
+            snapshot.ticks.retain(|tick| *tick <= cycle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(value: u8) -> Vec<u8> {
+        let mut state = vec![0; 64];
+        state[10] = value;
+        state[11] = value;
+        state[40] = value / 2;
+        state
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            diff(&[1, 2, 3, 4, 5], &[1, 9, 9, 4, 8]),
+            vec![(1, vec![9, 9]), (4, vec![8])]
+        );
+    }
+
+    #[test]
+    fn test_deltas_rebuild() {
+        let mut buffer = RewindBuffer::new(100, 4);
+        for value in 0..10 {
+            buffer.push(value as u64 * 10, state(value));
+        }
+        assert!(matches!(
+            buffer.snapshots[4].contents,
+            Contents::Keyframe(_)
+        ));
+        assert!(matches!(buffer.snapshots[5].contents, Contents::Delta(_)));
+        for value in 0..10 {
+            assert_eq!(
+                buffer.nearest(value as u64 * 10 + 5).unwrap().1,
+                state(value)
+            );
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut buffer = RewindBuffer::new(6, 4);
+        for value in 0..10 {
+            buffer.push(value as u64, state(value));
+        }
+        // the oldest snapshots, and the deltas stored against them, are gone
+        assert!(buffer.snapshots.len() <= 6);
+        assert!(buffer.nearest(3).is_none());
+        assert!(matches!(
+            buffer.snapshots.front().unwrap().contents,
+            Contents::Keyframe(_)
+        ));
+        assert_eq!(buffer.nearest(9).unwrap().1, state(9));
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut buffer = RewindBuffer::new(100, 4);
+        for value in 0..6 {
+            buffer.push(value as u64, state(value));
+        }
+        for value in (0..6).rev() {
+            assert_eq!(buffer.pop(), Some((value as u64, state(value))));
+        }
+        assert!(buffer.pop().is_none());
+    }
+
+    #[test]
+    fn test_ticks() {
+        let mut buffer = RewindBuffer::new(100, 4);
+        buffer.tick(0);
+        buffer.push(0, state(0));
+        buffer.tick(10);
+        buffer.tick(20);
+        buffer.push(20, state(1));
+        buffer.tick(30);
+        assert_eq!(buffer.nearest(15).unwrap().2, vec![10]);
+        assert_eq!(buffer.nearest(20).unwrap().2, vec![]);
+        assert_eq!(buffer.nearest(35).unwrap().2, vec![30]);
+        buffer.truncate_after(25);
+        assert_eq!(buffer.nearest(35).unwrap().2, vec![]);
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+
+use crate::config;
+use crate::json::Json;
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+
+// Settings for known ROMs, looked up by the SHA-1 of the ROM file. Like the community
+// CHIP-8 database, programs are listed with the ROMs released for them, and each ROM
+// has the settings it needs:
+//
+//   [
+//     {
+//       "title": "Breakout",
+//       "authors": ["Someone"],
+//       "roms": {
+//         "<sha1 of the file>": {
+//           "platform": "chip8",                  // chip8, schip or xochip
+//           "quirks": ["vf-reset", "clip"],       // the names --quirks takes
+//           "tickrate": 15,                       // instructions per frame
+//           "keys": {"left": 4, "right": 6},      // what the program uses keys for
+//           "palette": "amber"                    // anything --palette takes
+//         }
+//       }
+//     }
+//   ]
+//
+// Everything but the title is optional. --rom-database adds entries from a file in the
+// same format, which win over the embedded ones.
+
+const EMBEDDED: &str = include_str!("rom_database.json");
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub tick_rate: Option<u32>,
+    // what each key does, in the order the database lists them
+    pub keys: Vec<(String, u8)>,
+    pub palette: Option<String>,
+}
+
+impl RomInfo {
+    pub fn options(&self) -> Vec<(String, String)> {
+        config::program_options(self.quirks, self.tick_rate, self.palette.clone())
+    }
+
+    // e.g. "left 4, right 6", or none if the database doesn't say
+    pub fn key_hints(&self) -> Option<String> {
+        let hints: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(action, key)| format!("{action} {key:X}"))
+            .collect();
+        (!hints.is_empty()).then(|| hints.join(", "))
+    }
+}
+
+// the title, authors and platform
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        if !self.authors.is_empty() {
+            write!(f, " by {}", self.authors.join(", "))?;
+        }
+        write!(f, " ({})", self.platform)
+    }
+}
+
+pub struct RomDatabase {
+    roms: Vec<(String, RomInfo)>,
+}
+
+fn string(value: &Json, name: &str) -> Result<String, String> {
+    value
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| format!("Expected {name} to be a string"))
+}
+
+fn rom_info(program: &Json, rom: &Json) -> Result<RomInfo, String> {
+    let title = string(
+        program.get("title").ok_or("Program is missing a title")?,
+        "the title",
+    )?;
+    let authors = match program.get("authors").map(Json::as_array) {
+        None => Vec::new(),
+        Some(Some(authors)) => authors
+            .iter()
+            .map(|author| string(author, "an author"))
+            .collect::<Result<_, _>>()?,
+        Some(None) => return Err(format!("{title}: Expected a list of authors")),
+    };
+
+    let platform = match rom.get("platform").map(Json::as_str) {
+        None | Some(Some("chip8")) => Platform::Chip8,
+        Some(Some("schip")) => Platform::Schip,
+        Some(Some("xochip")) => Platform::XoChip,
+        _ => return Err(format!("{title}: Unknown platform")),
+    };
+    let quirks = match rom.get("quirks").map(Json::as_array) {
+        None => Quirks::default(),
+        Some(Some(names)) => {
+            let names = names
+                .iter()
+                .map(|name| string(name, "a quirk"))
+                .collect::<Result<Vec<_>, _>>()?;
+            let names = if names.is_empty() {
+                "none".to_owned()
+            } else {
+                names.join(",")
+            };
+            Quirks::parse(&names).map_err(|error| format!("{title}: {error}"))?
+        }
+        Some(None) => return Err(format!("{title}: Expected a list of quirks")),
+    };
+    let tick_rate = match rom.get("tickrate") {
+        None => None,
+        Some(tick_rate) => match tick_rate.as_u64() {
+            Some(tick_rate) if (1..=u32::MAX as u64).contains(&tick_rate) => Some(tick_rate as u32),
+            _ => return Err(format!("{title}: Invalid tick rate")),
+        },
+    };
+    let keys = match rom.get("keys") {
+        None => Vec::new(),
+        Some(Json::Object(keys)) => keys
+            .iter()
+            .map(|(action, key)| match key.as_u64() {
+                Some(key) if key < 16 => Ok((action.clone(), key as u8)),
+                _ => Err(format!("{title}: Invalid key for '{action}'")),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(format!("{title}: Expected keys to be an object")),
+    };
+    let palette = match rom.get("palette") {
+        None => None,
+        Some(palette) => {
+            let palette = string(palette, "the palette")?;
+            Palette::parse(&palette).map_err(|error| format!("{title}: {error}"))?;
+            Some(palette)
+        }
+    };
+
+    Ok(RomInfo {
+        title,
+        authors,
+        platform,
+        quirks,
+        tick_rate,
+        keys,
+        palette,
+    })
+}
+
+impl RomDatabase {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json = Json::parse(text)?;
+        let programs = json.as_array().ok_or("Expected a list of programs")?;
+        let mut roms = Vec::new();
+        for program in programs {
+            match program.get("roms") {
+                Some(Json::Object(entries)) => {
+                    for (sha1, rom) in entries {
+                        roms.push((sha1.to_ascii_lowercase(), rom_info(program, rom)?));
+                    }
+                }
+                _ => return Err("Expected each program to have an object of roms".to_owned()),
+            }
+        }
+        Ok(RomDatabase { roms })
+    }
+
+    pub fn load(filepath: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(filepath)
+            .map_err(|error| format!("Unable to read {filepath}: {error}"))?;
+        RomDatabase::parse(&text).map_err(|error| format!("{filepath}: {error}"))
+    }
+
+    pub fn embedded() -> Self {
+        RomDatabase::parse(EMBEDDED).expect("the embedded ROM database is valid")
+    }
+
+    // another database's entries, which take priority over these
+    pub fn extend(&mut self, database: RomDatabase) {
+        self.roms.splice(0..0, database.roms);
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<&RomInfo> {
+        let sha1 = sha1.to_ascii_lowercase();
+        self.roms
+            .iter()
+            .find(|(hash, _)| *hash == sha1)
+            .map(|(_, info)| info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = r#"[
+        {
+            "title": "Paddles",
+            "authors": ["A", "B"],
+            "roms": {
+                "AAAA": {
+                    "platform": "schip",
+                    "quirks": ["vf-reset", "clip"],
+                    "tickrate": 15,
+                    "keys": {"left": 4, "right": 6, "serve": 10},
+                    "palette": "amber"
+                },
+                "bbbb": {}
+            }
+        }
+    ]"#;
+
+    #[test]
+    fn test_lookup() {
+        let database = RomDatabase::parse(DATABASE).unwrap();
+        let rom = database.lookup("aaaa").unwrap();
+        assert_eq!(rom.to_string(), "Paddles by A, B (SCHIP)");
+        assert_eq!(rom.key_hints().as_deref(), Some("left 4, right 6, serve A"));
+        assert_eq!(
+            rom.options(),
+            vec![
+                ("quirks".to_owned(), "vf-reset,clip".to_owned()),
+                ("tick-rate".to_owned(), "15".to_owned()),
+                ("palette".to_owned(), "amber".to_owned()),
+            ]
+        );
+
+        // anything left out is the default
+        let plain = database.lookup("BBBB").unwrap();
+        assert_eq!(plain.platform, Platform::Chip8);
+        assert_eq!(plain.key_hints(), None);
+        assert_eq!(
+            plain.options(),
+            vec![("quirks".to_owned(), "none".to_owned())]
+        );
+        assert!(database.lookup("cccc").is_none());
+
+        let mut database = RomDatabase::embedded();
+        database.extend(RomDatabase::parse(DATABASE).unwrap());
+        assert!(database.lookup("aaaa").is_some());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |rom: &str| {
+            let text = format!(r#"[{{"title": "T", "roms": {{"aaaa": {rom}}}}}]"#);
+            RomDatabase::parse(&text).err().unwrap()
+        };
+        assert_eq!(error(r#"{"platform": "nes"}"#), "T: Unknown platform");
+        assert_eq!(error(r#"{"quirks": ["wrap"]}"#), "T: Unknown quirk 'wrap'");
+        assert_eq!(error(r#"{"tickrate": 0}"#), "T: Invalid tick rate");
+        assert_eq!(error(r#"{"keys": {"up": 16}}"#), "T: Invalid key for 'up'");
+        assert!(error(r#"{"palette": "plaid"}"#).starts_with("T: Unknown palette"));
+        assert!(RomDatabase::parse(r#"[{"roms": {"aaaa": {}}}]"#).is_err());
+        assert!(RomDatabase::parse("{}").is_err());
+    }
+}
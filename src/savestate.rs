@@ -0,0 +1,265 @@
+use crate::quirks::Quirks;
+
+// Save state format, version 2. All numbers are big endian, like CHIP-8 itself.
+//
+//  offset  size  contents
+//  0       8     magic "CH8STATE"
+//  8       2     format version
+//  10      4     payload length in bytes
+//  14      4     CRC-32 (IEEE) of the payload
+//  18            payload:
+//                  4096  memory, which includes PC, I and V0-VF at their register addresses
+//                  2048  framebuffer, one byte per pixel
+//                  4     delay timer
+//                  4     sound timer
+//                  1     stack depth n, followed by n 2 byte return addresses
+//                  2     keypad, bit k set when key k is held down
+//                  4     random number generator state
+//                  1     quirks (bit 0 logic_resets_vf, bit 1 jump_uses_vx, bit 2 clip_sprites)
+//                  8     cycles executed
+//
+// Readers reject any other version, so bump it whenever the payload changes.
+
+const MAGIC: &[u8; 8] = b"CH8STATE";
+const VERSION: u16 = 2;
+const HEADER_LENGTH: usize = 18;
+// CALL overflows past this many return addresses, so no state can have more
+const MAXIMUM_STACK_DEPTH: u8 = 16;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MachineState {
+    pub memory: Vec<u8>,
+    pub framebuffer: Vec<u8>,
+    pub delay_timer: u32,
+    pub sound_timer: u32,
+    pub stack: Vec<u16>,
+    pub keys: [bool; 16],
+    pub rng_state: u32,
+    pub quirks: Quirks,
+    pub cycles: u64,
+}
+
+// where the SDL frontend keeps its numbered save slots
+pub fn slot_path(slot: u8) -> String {
+    format!("slot{slot}.ch8state")
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn save(state: &MachineState) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&state.memory);
+    payload.extend_from_slice(&state.framebuffer);
+    payload.extend_from_slice(&state.delay_timer.to_be_bytes());
+    payload.extend_from_slice(&state.sound_timer.to_be_bytes());
+    payload.push(state.stack.len() as u8);
+    for address in &state.stack {
+        payload.extend_from_slice(&address.to_be_bytes());
+    }
+    let keys = (0..16)
+        .filter(|key| state.keys[*key])
+        .fold(0u16, |bits, key| bits | 1 << key);
+    payload.extend_from_slice(&keys.to_be_bytes());
+    payload.extend_from_slice(&state.rng_state.to_be_bytes());
+    payload.push(state.quirks.to_bits());
+    payload.extend_from_slice(&state.cycles.to_be_bytes());
+
+    let mut data = Vec::with_capacity(HEADER_LENGTH + payload.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_be_bytes());
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    data.extend_from_slice(&crc32(&payload).to_be_bytes());
+    data.extend_from_slice(&payload);
+    data
+}
+
+// reads the payload front to back
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err("Save state is truncated".to_owned());
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let high = self.u32()? as u64;
+        let low = self.u32()? as u64;
+        Ok((high << 32) | low)
+    }
+}
+
+// how much of the data is the save state, going by its header, for when it has been
+// written into a bigger buffer
+pub fn saved_length(data: &[u8]) -> Option<usize> {
+    if data.len() < HEADER_LENGTH || &data[..8] != MAGIC {
+        return None;
+    }
+    let length = u32::from_be_bytes([data[10], data[11], data[12], data[13]]) as usize;
+    Some(HEADER_LENGTH + length)
+}
+
+pub fn load(data: &[u8]) -> Result<MachineState, String> {
+    if data.len() < HEADER_LENGTH || &data[..8] != MAGIC {
+        return Err("Not a save state".to_owned());
+    }
+    let mut header = Reader { data, position: 8 };
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(format!(
+            "Unsupported save state version {version} (expected {VERSION})"
+        ));
+    }
+    let length = header.u32()? as usize;
+    let checksum = header.u32()?;
+    let payload = &data[HEADER_LENGTH..];
+    if payload.len() != length {
+        return Err("Save state is truncated".to_owned());
+    }
+    if crc32(payload) != checksum {
+        return Err("Save state checksum does not match".to_owned());
+    }
+
+    let mut reader = Reader {
+        data: payload,
+        position: 0,
+    };
+    let memory = reader.take(4096)?.to_vec();
+    let framebuffer = reader.take(64 * 32)?.to_vec();
+    let delay_timer = reader.u32()?;
+    let sound_timer = reader.u32()?;
+    let depth = reader.u8()?;
+    if depth > MAXIMUM_STACK_DEPTH {
+        return Err(format!(
+            "Save state has a stack {depth} deep, more than {MAXIMUM_STACK_DEPTH}"
+        ));
+    }
+    let mut stack = Vec::new();
+    for _ in 0..depth {
+        stack.push(reader.u16()?);
+    }
+    let key_bits = reader.u16()?;
+    let mut keys = [false; 16];
+    for (key, pressed) in keys.iter_mut().enumerate() {
+        *pressed = key_bits & (1 << key) != 0;
+    }
+    let rng_state = reader.u32()?;
+    let quirks = Quirks::from_bits(reader.u8()?);
+    let cycles = reader.u64()?;
+
+    Ok(MachineState {
+        memory,
+        framebuffer,
+        delay_timer,
+        sound_timer,
+        stack,
+        keys,
+        rng_state,
+        quirks,
+        cycles,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_state() -> MachineState {
+        let mut keys = [false; 16];
+        keys[3] = true;
+        keys[0xF] = true;
+        MachineState {
+            memory: (0..4096).map(|i| i as u8).collect(),
+            framebuffer: (0..2048).map(|i| (i % 2) as u8).collect(),
+            delay_timer: 42,
+            sound_timer: 7,
+            stack: vec![0x204, 0x310],
+            keys,
+            rng_state: 0xDEADBEEF,
+            quirks: Quirks {
+                jump_uses_vx: true,
+                ..Quirks::default()
+            },
+            cycles: 123_456_789_012,
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let state = example_state();
+        let data = save(&state);
+        assert_eq!(&data[..8], MAGIC);
+        assert_eq!(load(&data).unwrap(), state);
+
+        let mut padded = data.clone();
+        padded.resize(data.len() + 32, 0);
+        assert_eq!(saved_length(&padded), Some(data.len()));
+        assert_eq!(saved_length(b"short"), None);
+    }
+
+    #[test]
+    fn test_rejects_bad_data() {
+        let data = save(&example_state());
+
+        let mut corrupted = data.clone();
+        corrupted[HEADER_LENGTH + 100] ^= 1;
+        assert!(load(&corrupted).unwrap_err().contains("checksum"));
+
+        let mut wrong_version = data.clone();
+        wrong_version[9] = 99;
+        assert!(load(&wrong_version).unwrap_err().contains("version"));
+
+        assert!(load(&data[..data.len() - 1])
+            .unwrap_err()
+            .contains("truncated"));
+        assert!(load(b"not a state at all").is_err());
+
+        let deep = MachineState {
+            stack: vec![0x200; 20],
+            ..example_state()
+        };
+        assert!(load(&save(&deep)).unwrap_err().contains("stack 20 deep"));
+        let full = MachineState {
+            stack: vec![0x200; 16],
+            ..example_state()
+        };
+        assert!(load(&save(&full)).is_ok());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::palette::Palette;
+use crate::savestate::crc32;
+
+// Images of the framebuffer, scaled up by a whole number so each pixel stays square.
+// PNG is written without any dependencies: an indexed image with one palette entry
+// per colour, compressed with deflate's fixed Huffman codes. Scaled up CHIP-8 screens
+// are mostly repeats of the byte before or the row above, so matching just those two
+// distances is enough to make them small.
+
+// image pixels per CHIP-8 pixel, unless --scale says otherwise
+pub const DEFAULT_SCALE: usize = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    // by file extension, defaulting to PNG
+    pub fn from_path(filepath: &str) -> Self {
+        if filepath.to_ascii_lowercase().ends_with(".ppm") {
+            ImageFormat::Ppm
+        } else {
+            ImageFormat::Png
+        }
+    }
+}
+
+// one byte per pixel in rows of width, as in Memory::framebuffer
+pub struct Screen<'a> {
+    pub framebuffer: &'a [u8],
+    pub width: usize,
+}
+
+impl Screen<'_> {
+    pub fn height(&self) -> usize {
+        self.framebuffer.len() / self.width
+    }
+
+    // rows of 0 (background) or 1 (foreground), scaled up
+    pub fn scaled_rows(&self, scale: usize) -> Vec<Vec<u8>> {
+        let mut rows = Vec::new();
+        for row in self.framebuffer.chunks(self.width) {
+            let scaled: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| std::iter::repeat_n((*pixel != 0) as u8, scale))
+                .collect();
+            for _ in 0..scale {
+                rows.push(scaled.clone());
+            }
+        }
+        rows
+    }
+}
+
+pub fn encode(screen: &Screen, scale: usize, palette: &Palette, format: ImageFormat) -> Vec<u8> {
+    let scale = scale.max(1);
+    match format {
+        ImageFormat::Png => png(screen, scale, palette),
+        ImageFormat::Ppm => ppm(screen, scale, palette),
+    }
+}
+
+pub fn save(
+    filepath: &str,
+    screen: &Screen,
+    scale: usize,
+    palette: &Palette,
+) -> Result<(), String> {
+    let data = encode(screen, scale, palette, ImageFormat::from_path(filepath));
+    std::fs::write(filepath, data).map_err(|error| format!("Unable to save {filepath}: {error}"))
+}
+
+fn ppm(screen: &Screen, scale: usize, palette: &Palette) -> Vec<u8> {
+    let mut data = format!(
+        "P6\n{} {}\n255\n",
+        screen.width * scale,
+        screen.height() * scale
+    )
+    .into_bytes();
+    for row in screen.scaled_rows(scale) {
+        for pixel in row {
+            data.extend_from_slice(&palette.color(pixel));
+        }
+    }
+    data
+}
+
+fn png(screen: &Screen, scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = (screen.width * scale) as u32;
+    let height = (screen.height() * scale) as u32;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per pixel, indexed colour, deflate, no filtering, not interlaced
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let mut colors = Vec::new();
+    colors.extend_from_slice(&palette.background);
+    colors.extend_from_slice(&palette.foreground);
+
+    // every row starts with its filter type, which is always "none"
+    let mut pixels = Vec::new();
+    for row in screen.scaled_rows(scale) {
+        pixels.push(0);
+        pixels.extend_from_slice(&row);
+    }
+    let row_length = width as usize + 1;
+
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut data, b"IHDR", &header);
+    write_chunk(&mut data, b"PLTE", &colors);
+    write_chunk(&mut data, b"IDAT", &zlib(&pixels, row_length));
+    write_chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+fn write_chunk(data: &mut Vec<u8>, kind: &[u8; 4], contents: &[u8]) {
+    data.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+    let start = data.len();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(contents);
+    let crc = crc32(&data[start..]);
+    data.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// deflate streams are packed starting from the least significant bit
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        for i in 0..count {
+            if self.bit == 0 {
+                self.bytes.push(0);
+            }
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= (((value >> i) & 1) as u8) << self.bit;
+            self.bit = (self.bit + 1) % 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write(reversed, length);
+    }
+
+    fn write_symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+}
+
+const LENGTH_BASES: [u32; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u32; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// the largest index with a base no bigger than value
+fn code_index(bases: &[u32], value: u32) -> usize {
+    bases.iter().rposition(|base| *base <= value).unwrap_or(0)
+}
+
+fn match_length(data: &[u8], position: usize, distance: usize) -> usize {
+    if distance == 0 || distance > position || distance > 32768 {
+        return 0;
+    }
+    (0..258)
+        .take_while(|i| {
+            position + i < data.len() && data[position + i] == data[position + i - distance]
+        })
+        .count()
+}
+
+// a zlib stream in a single fixed Huffman block, only looking back 1 byte or 1 row
+fn zlib(data: &[u8], row_length: usize) -> Vec<u8> {
+    let mut writer = BitWriter {
+        bytes: vec![0x78, 0x01],
+        bit: 0,
+    };
+    // last block, fixed Huffman codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = [row_length, 1]
+            .iter()
+            .map(|distance| (match_length(data, position, *distance), *distance))
+            .max_by_key(|(length, _)| *length)
+            .unwrap_or((0, 1));
+        if length < 3 {
+            writer.write_symbol(data[position] as u32);
+            position += 1;
+            continue;
+        }
+
+        let index = code_index(&LENGTH_BASES, length as u32);
+        writer.write_symbol(257 + index as u32);
+        writer.write(
+            length as u32 - LENGTH_BASES[index],
+            LENGTH_EXTRA_BITS[index],
+        );
+        let index = code_index(&DISTANCE_BASES, distance as u32);
+        writer.write_code(index as u32, 5);
+        writer.write(
+            distance as u32 - DISTANCE_BASES[index],
+            DISTANCE_EXTRA_BITS[index],
+        );
+        position += length;
+    }
+    writer.write_symbol(256);
+
+    let mut bytes = writer.bytes;
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha1::sha1_hex;
+
+    // a 4x2 screen with a checkerboard
+    const CHECKERBOARD: [u8; 8] = [1, 0, 1, 0, 0, 1, 0, 1];
+
+    fn screen() -> Screen<'static> {
+        Screen {
+            framebuffer: &CHECKERBOARD,
+            width: 4,
+        }
+    }
+
+    #[test]
+    fn test_ppm() {
+        let data = encode(&screen(), 2, &Palette::default(), ImageFormat::Ppm);
+        let header = b"P6\n8 4\n255\n";
+        assert_eq!(&data[..header.len()], header);
+        assert_eq!(data.len(), header.len() + 8 * 4 * 3);
+        // the first two pixels are foreground (black), the next two background (white)
+        assert_eq!(
+            &data[header.len()..header.len() + 12],
+            &[0, 0, 0, 0, 0, 0, 255, 255, 255, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn test_png_chunks() {
+        let data = encode(&screen(), 3, &Palette::default(), ImageFormat::Png);
+        assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&data[12..16], b"IHDR");
+        assert_eq!(
+            u32::from_be_bytes([data[16], data[17], data[18], data[19]]),
+            12
+        );
+        assert_eq!(
+            u32::from_be_bytes([data[20], data[21], data[22], data[23]]),
+            6
+        );
+        // every chunk's CRC covers its type and contents
+        let mut position = 8;
+        while position < data.len() {
+            let length = u32::from_be_bytes([
+                data[position],
+                data[position + 1],
+                data[position + 2],
+                data[position + 3],
+            ]) as usize;
+            let end = position + 8 + length;
+            let crc = u32::from_be_bytes([data[end], data[end + 1], data[end + 2], data[end + 3]]);
+            assert_eq!(crc32(&data[position + 4..end]), crc);
+            position = end + 4;
+        }
+        assert_eq!(&data[data.len() - 8..data.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn test_png_golden() {
+        // checked against a standard PNG decoder
+        let data = encode(&screen(), 8, &Palette::default(), ImageFormat::Png);
+        assert_eq!(sha1_hex(&data), "afc835c51d1d268c93908122351330fc16ed1258");
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+}
@@ -0,0 +1,383 @@
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, Scope, AST};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use crate::emulator::Chip8;
+use crate::key::Key;
+use crate::memory::Access;
+use crate::register::Register;
+
+// Rhai scripts that run alongside a program, for bots, auto-testers, trainers and
+// anything else that wants to watch or poke at the machine. A script registers its
+// hooks when it's loaded:
+//
+//   on_frame(|frame| text(`V0 = ${reg("v0")}`));
+//   on_pc(0x21A, |pc| set_reg("v3", 9));         // before the instruction at 21A is fetched
+//   on_write(0x300, |address, value| print(value));
+//
+// and they can use peek(address), poke(address, value), reg(name), set_reg(name, value)
+// for pc, i, v0-vf and dt, press(key), release(key) and text(line) to draw over the
+// screen for a frame. The file is loaded again whenever it changes. Keys pressed with
+// press and release are left out of --record movies.
+
+// enough for any reasonable hook, but stops an infinite loop freezing the emulator
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// the machine as a hook sees it: a copy of memory taken before it runs, with the
+// changes it makes copied back afterwards
+#[derive(Default)]
+struct Bridge {
+    memory: Vec<u8>,
+    delay_timer: u32,
+    writes: Vec<(usize, u8)>,
+    keys: Vec<(Key, bool)>,
+    overlay: Vec<String>,
+    frame_hooks: Vec<FnPtr>,
+    pc_hooks: Vec<(u16, FnPtr)>,
+    write_hooks: Vec<(u16, FnPtr)>,
+}
+
+type Shared = Rc<RefCell<Bridge>>;
+
+fn address(value: i64) -> Result<usize, Box<EvalAltResult>> {
+    match usize::try_from(value) {
+        Ok(address) if address < 4096 => Ok(address),
+        _ => Err(format!("Invalid address {value}").into()),
+    }
+}
+
+fn key(value: i64) -> Result<Key, Box<EvalAltResult>> {
+    u8::try_from(value)
+        .map_err(|_| format!("Invalid key {value}"))
+        .and_then(Key::try_from)
+        .map_err(Into::into)
+}
+
+// where a register lives in memory, and how many bytes it takes, or None for dt
+fn register(name: &str) -> Result<Option<(usize, usize)>, Box<EvalAltResult>> {
+    match Register::from_name(name) {
+        Some(Register::DELAY_TIMER) => Ok(None),
+        Some(register @ (Register::PC | Register::IR)) => Ok(Some((register as usize, 2))),
+        Some(register) => Ok(Some((register as usize, 1))),
+        None => Err(format!("Unknown register '{name}'").into()),
+    }
+}
+
+impl Bridge {
+    fn poke(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+        self.writes.push((address, value));
+    }
+}
+
+fn engine(bridge: &Shared) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let shared = bridge.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        shared.borrow_mut().frame_hooks.push(callback);
+    });
+    let shared = bridge.clone();
+    engine.register_fn("on_pc", move |pc: i64, callback: FnPtr| {
+        let pc = address(pc)? as u16;
+        shared.borrow_mut().pc_hooks.push((pc, callback));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let shared = bridge.clone();
+    engine.register_fn("on_write", move |location: i64, callback: FnPtr| {
+        let location = address(location)? as u16;
+        shared.borrow_mut().write_hooks.push((location, callback));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+
+    let shared = bridge.clone();
+    engine.register_fn("peek", move |location: i64| {
+        Ok::<_, Box<EvalAltResult>>(shared.borrow().memory[address(location)?] as i64)
+    });
+    let shared = bridge.clone();
+    engine.register_fn("poke", move |location: i64, value: i64| {
+        shared.borrow_mut().poke(address(location)?, value as u8);
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let shared = bridge.clone();
+    engine.register_fn("reg", move |name: &str| {
+        let bridge = shared.borrow();
+        let value = match register(name)? {
+            Some((location, 1)) => bridge.memory[location] as i64,
+            Some((location, _)) => {
+                u16::from_be_bytes([bridge.memory[location], bridge.memory[location + 1]]) as i64
+            }
+            None => bridge.delay_timer as i64,
+        };
+        Ok::<_, Box<EvalAltResult>>(value)
+    });
+    let shared = bridge.clone();
+    engine.register_fn("set_reg", move |name: &str, value: i64| {
+        let mut bridge = shared.borrow_mut();
+        match register(name)? {
+            Some((location, 1)) => bridge.poke(location, value as u8),
+            Some((location, _)) => {
+                let [high, low] = (value as u16).to_be_bytes();
+                bridge.poke(location, high);
+                bridge.poke(location + 1, low);
+            }
+            None => bridge.delay_timer = value.clamp(0, 0xFF) as u32,
+        }
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+
+    let shared = bridge.clone();
+    engine.register_fn("press", move |value: i64| {
+        shared.borrow_mut().keys.push((key(value)?, true));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let shared = bridge.clone();
+    engine.register_fn("release", move |value: i64| {
+        shared.borrow_mut().keys.push((key(value)?, false));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let shared = bridge.clone();
+    engine.register_fn("text", move |line: &str| {
+        shared.borrow_mut().overlay.push(line.to_owned());
+    });
+    engine
+}
+
+fn modified(filepath: &str) -> Option<SystemTime> {
+    std::fs::metadata(filepath)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+pub struct Script {
+    filepath: String,
+    modified: Option<SystemTime>,
+    bridge: Shared,
+    engine: Engine,
+    ast: AST,
+    // hooks stop after an error, until the file changes
+    failed: bool,
+}
+
+impl Script {
+    pub fn load(filepath: &str) -> Result<Self, String> {
+        let bridge = Shared::default();
+        let engine = engine(&bridge);
+        let mut script = Self {
+            filepath: filepath.to_owned(),
+            modified: modified(filepath),
+            bridge,
+            engine,
+            ast: AST::empty(),
+            failed: false,
+        };
+        script.compile()?;
+        Ok(script)
+    }
+
+    // runs the top level of the script, which registers its hooks
+    fn compile(&mut self) -> Result<(), String> {
+        let source = std::fs::read_to_string(&self.filepath)
+            .map_err(|error| format!("Unable to read {}: {error}", self.filepath))?;
+        let ast = self
+            .engine
+            .compile(source)
+            .map_err(|error| format!("{}: {error}", self.filepath))?;
+        // the top level only sees blank memory, since it isn't running at any point
+        let previous = std::mem::replace(
+            &mut *self.bridge.borrow_mut(),
+            Bridge {
+                memory: vec![0; 4096],
+                ..Bridge::default()
+            },
+        );
+        if let Err(error) = self.engine.run_ast_with_scope(&mut Scope::new(), &ast) {
+            *self.bridge.borrow_mut() = previous;
+            return Err(format!("{}: {error}", self.filepath));
+        }
+        self.ast = ast;
+        self.failed = false;
+        Ok(())
+    }
+
+    // write hooks need memory tracked while the instruction runs
+    pub fn watches_writes(&self) -> bool {
+        !self.failed && !self.bridge.borrow().write_hooks.is_empty()
+    }
+
+    fn call(&mut self, chip8: &mut Chip8, hook: &FnPtr, args: impl rhai::FuncArgs) {
+        {
+            let mut bridge = self.bridge.borrow_mut();
+            bridge.writes.clear();
+            bridge.keys.clear();
+            bridge.memory.clear();
+            bridge.memory.extend_from_slice(chip8.memory().data());
+            bridge.delay_timer = chip8.delay_timer();
+        }
+        let result = hook.call::<Dynamic>(&self.engine, &self.ast, args);
+
+        let mut bridge = self.bridge.borrow_mut();
+        for (address, value) in bridge.writes.drain(..) {
+            chip8.set8(address, value);
+        }
+        for (key, pressed) in bridge.keys.drain(..) {
+            chip8.set_key(key, pressed);
+        }
+        chip8.set_delay_timer(bridge.delay_timer);
+        drop(bridge);
+
+        if let Err(error) = result {
+            self.failed = true;
+            chip8.notify(&format!("Script error, stopping until it changes: {error}"));
+        }
+    }
+
+    // before the instruction at pc runs
+    pub fn at_pc(&mut self, chip8: &mut Chip8, pc: u16) {
+        if self.failed {
+            return;
+        }
+        let hooks: Vec<FnPtr> = self
+            .bridge
+            .borrow()
+            .pc_hooks
+            .iter()
+            .filter(|(address, _)| *address == pc)
+            .map(|(_, hook)| hook.clone())
+            .collect();
+        for hook in hooks {
+            self.call(chip8, &hook, (pc as i64,));
+        }
+    }
+
+    // after an instruction, with the memory it accessed
+    pub fn after_accesses(&mut self, chip8: &mut Chip8, accesses: &[Access]) {
+        for access in accesses.iter().filter(|access| access.write) {
+            let hooks: Vec<FnPtr> = self
+                .bridge
+                .borrow()
+                .write_hooks
+                .iter()
+                .filter(|(address, _)| *address as usize == access.address)
+                .map(|(_, hook)| hook.clone())
+                .collect();
+            for hook in hooks {
+                if self.failed {
+                    return;
+                }
+                let value = chip8.memory().peek8(access.address) as i64;
+                self.call(chip8, &hook, (access.address as i64, value));
+            }
+        }
+    }
+
+    // reloads the file if it's changed, then runs the frame hooks and draws their text
+    pub fn end_frame(&mut self, chip8: &mut Chip8, frame: u64) {
+        let modified = modified(&self.filepath);
+        if modified != self.modified {
+            self.modified = modified;
+            match self.compile() {
+                Ok(()) => chip8.notify(&format!("Reloaded {}", self.filepath)),
+                Err(error) => chip8.notify(&error),
+            }
+        }
+
+        if !self.failed {
+            let hooks = self.bridge.borrow().frame_hooks.clone();
+            for hook in hooks {
+                self.call(chip8, &hook, (frame as i64,));
+                if self.failed {
+                    break;
+                }
+            }
+        }
+        let overlay = std::mem::take(&mut self.bridge.borrow_mut().overlay);
+        chip8.show_overlay(&overlay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"
+    200: 6001 // LD V0, 1
+    202: 7001 // ADD V0, 1
+    204: A300 // LD I, 300
+    206: 1202 // JP 202
+    "#;
+
+    fn load(name: &str, source: &str) -> (String, Result<Script, String>) {
+        let filepath = std::env::temp_dir().join(name);
+        let filepath = filepath.to_string_lossy().into_owned();
+        std::fs::write(&filepath, source).unwrap();
+        let script = Script::load(&filepath);
+        (filepath, script)
+    }
+
+    #[test]
+    fn test_hooks() {
+        let (filepath, script) = load(
+            "chip8_script_test.rhai",
+            r#"
+            on_pc(0x204, |pc| set_reg("v5", reg("v5") + 1));
+            on_write(0x04, |address, value| poke(0x310, value));
+            on_frame(|frame| {
+                press(0xA);
+                set_reg("dt", 30);
+                text(`frame ${frame}`);
+            });
+            "#,
+        );
+        let mut chip8 = Chip8::load_from_text(PROGRAM).setup_script(script.unwrap());
+        for _ in 0..7 {
+            chip8.run_frame();
+        }
+        // 204 ran twice, and V0 was last written with 3
+        assert_eq!(chip8.get8(Register::V5 as usize), 2);
+        assert_eq!(chip8.get8(0x310), 3);
+        let state = crate::savestate::load(&chip8.save_state()).unwrap();
+        assert!(state.keys[Key::CodeA as usize]);
+        assert_eq!(chip8.delay_timer(), 30);
+        std::fs::remove_file(filepath).unwrap();
+    }
+
+    #[test]
+    fn test_pc_hook_runs_first() {
+        let (filepath, script) = load(
+            "chip8_script_pc_test.rhai",
+            r#"
+            on_pc(0x200, |pc| poke(0x201, 7));
+            on_pc(0x202, |pc| set_reg("pc", 0x204));
+            "#,
+        );
+        let mut chip8 = Chip8::load_from_text(PROGRAM).setup_script(script.unwrap());
+        chip8.cycle();
+        chip8.cycle();
+        // LD V0, 7 ran as patched, then LD I, 300 instead of ADD V0, 1
+        assert_eq!(chip8.get8(Register::V0 as usize), 7);
+        assert_eq!(chip8.pc(), 0x206);
+        std::fs::remove_file(filepath).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let (filepath, script) = load("chip8_script_error_test.rhai", "on_pc(");
+        assert!(script.is_err());
+        let (_, script) = load("chip8_script_error_test.rhai", "on_pc(5000, |pc| 0);");
+        assert!(script.is_err_and(|error| error.contains("Invalid address 5000")));
+
+        // a hook that fails stops the hooks rather than the emulator
+        let (_, script) = load(
+            "chip8_script_error_test.rhai",
+            r#"on_frame(|frame| { poke(0x300, frame); peek(9999); });"#,
+        );
+        let mut chip8 = Chip8::load_from_text(PROGRAM).setup_script(script.unwrap());
+        chip8.run_frame();
+        chip8.run_frame();
+        assert_eq!(chip8.get8(0x300), 1);
+        std::fs::remove_file(filepath).unwrap();
+    }
+}
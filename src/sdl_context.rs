@@ -1,61 +1,113 @@
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
 use sdl2::{Sdl, TimerSubsystem};
+use std::time::Instant;
 
-use crate::graphics::Graphics;
-use crate::key::Key;
+use crate::controller::{ButtonMap, Controllers};
+use crate::frontend::{Frontend, InputEvent, Stats};
+use crate::graphics::{DisplayOptions, Graphics};
+use crate::keymap::Keymap;
 use crate::memory::Memory;
+use crate::osd::Osd;
+use crate::palette::Palette;
+use crate::panels::DebugView;
+
+// the save slot for a function key, if it is one of F1 to F9
+fn save_slot(keycode: Keycode) -> Option<u8> {
+    let slot = match keycode {
+        Keycode::F1 => 1,
+        Keycode::F2 => 2,
+        Keycode::F3 => 3,
+        Keycode::F4 => 4,
+        Keycode::F5 => 5,
+        Keycode::F6 => 6,
+        Keycode::F7 => 7,
+        Keycode::F8 => 8,
+        Keycode::F9 => 9,
+        _ => return None,
+    };
+    Some(slot)
+}
 
 pub struct SdlContext {
     _sdl_context: Sdl,
     _timer: TimerSubsystem,
     graphics: Graphics,
+    keymap: Keymap<Keycode>,
+    controllers: Controllers,
+    osd: Osd,
+    debug_view: Option<DebugView>,
     waiting_for_keypress: bool,
-    last_frame_time: u64,
 }
 
 impl SdlContext {
-    pub fn new() -> Self {
+    pub fn new(display: DisplayOptions, keymap: Keymap<Keycode>, buttons: ButtonMap) -> Self {
         let _sdl_context = sdl2::init().expect("Unable to initialise sdl2");
         let _timer = _sdl_context
             .timer()
             .expect("Unable to initialise timer subsystem");
-        let last_frame_time = _timer.ticks64();
         Self {
-            graphics: Graphics::new(&_sdl_context),
+            graphics: Graphics::new(&_sdl_context, display),
+            keymap,
+            controllers: Controllers::new(&_sdl_context, buttons),
+            osd: Osd::new(display.stats),
+            debug_view: None,
             waiting_for_keypress: false,
             _sdl_context,
             _timer,
-            last_frame_time,
         }
     }
 
     pub fn get_ticks(&self) -> u64 {
         self._timer.ticks64()
     }
+}
+
+impl Frontend for SdlContext {
+    fn render_graphics(&mut self, memory: &Memory, palette: &Palette) {
+        let text = self.osd.lines(Instant::now());
+        self.graphics
+            .render(memory, palette, &text, self.debug_view.as_ref());
+    }
+
+    fn show_overlay(&mut self, lines: &[String]) {
+        self.osd.set_overlay(lines);
+    }
+
+    fn shows_debug_view(&self) -> bool {
+        self.graphics.shows_panels()
+    }
+
+    fn show_debug(&mut self, view: &DebugView) {
+        self.debug_view = Some(view.clone());
+    }
 
-    // for use only in event loop
-    pub fn get_delta_time(&mut self) -> f32 {
-        let current_frame_time = self._timer.ticks64();
-        let delta_time_ms = current_frame_time - self.last_frame_time;
-        let delta_time = delta_time_ms as f32 / 1000.0;
-        self.last_frame_time = current_frame_time;
-        delta_time
+    fn show_message(&mut self, message: &str) {
+        self.osd.message(message, Instant::now());
     }
 
-    pub fn render_graphics(&mut self, memory: &Memory) {
-        self.graphics.render(memory);
+    fn show_stats(&mut self, stats: &Stats) {
+        self.osd.set_stats(stats);
     }
 
-    pub fn wait_for_keypress(&mut self) {
+    fn wait_for_keypress(&mut self) {
         self.waiting_for_keypress = true;
     }
 
-    pub fn handle_input(&mut self) -> Result<(), &str> {
+    fn handle_input(&mut self) -> Result<Vec<InputEvent>, &str> {
         let mut event_pump = self._sdl_context.event_pump().unwrap();
+        let mut events = Vec::new();
 
         'waiting: loop {
             for event in event_pump.poll_iter() {
+                if let Some(input) = self.controllers.handle_event(&event) {
+                    if matches!(input, InputEvent::Key(_, true)) {
+                        self.waiting_for_keypress = false;
+                    }
+                    events.push(input);
+                    continue;
+                }
                 match event {
                     Event::Quit { .. }
                     | Event::KeyDown {
@@ -63,11 +115,93 @@ impl SdlContext {
                         ..
                     } => return Err("QUIT"),
                     Event::KeyDown {
-                        keycode: Some(key), ..
+                        keycode: Some(keycode),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if save_slot(keycode).is_some() => {
+                        let slot = save_slot(keycode).unwrap();
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            events.push(InputEvent::SaveState(slot));
+                        } else {
+                            events.push(InputEvent::LoadState(slot));
+                        }
+                    }
+                    // only the window changes for these, not the emulator
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F11),
+                        repeat: false,
+                        ..
+                    } => self.graphics.toggle_fullscreen(),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::G),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                        self.graphics.toggle_grid()
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::I),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                        self.osd.toggle_stats()
+                    }
+                    Event::MouseButtonDown {
+                        mouse_btn: MouseButton::Left,
+                        x,
+                        y,
+                        ..
+                    } => {
+                        if let Some(address) = self.graphics.address_at(x, y) {
+                            events.push(InputEvent::ToggleBreakpoint(address));
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Tab),
+                        repeat: false,
+                        ..
+                    } => events.push(InputEvent::NextPalette),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F10),
+                        repeat: false,
+                        ..
+                    } => events.push(InputEvent::ToggleCapture),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        repeat: false,
+                        ..
+                    } => events.push(InputEvent::Screenshot),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => events.push(InputEvent::Rewind(true)),
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => events.push(InputEvent::Rewind(false)),
+                    Event::KeyUp {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(key) = self.keymap.get(keycode) {
+                            events.push(InputEvent::Key(key, false));
+                        }
+                    }
+                    // keys that aren't on the keypad are ignored, and don't end the wait
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        repeat,
+                        ..
                     } => {
-                        if self.waiting_for_keypress {
-                            let the_key = Key::try_from(key).unwrap();
-                            println!("{}: {} ({:?})", key, key.into_i32(), the_key);
+                        if let Some(hotkey) = self.keymap.hotkey(keycode) {
+                            if !repeat {
+                                events.push(hotkey);
+                            }
+                        } else if let Some(key) = self.keymap.get(keycode) {
+                            events.push(InputEvent::Key(key, true));
                             self.waiting_for_keypress = false;
                         }
                     }
@@ -80,6 +214,6 @@ impl SdlContext {
             }
         }
 
-        Ok(())
+        Ok(events)
     }
 }
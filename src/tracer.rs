@@ -0,0 +1,281 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceFormat {
+    Text,
+    Csv,
+}
+
+// one executed instruction, along with the state it left the machine in
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub kind: String,
+    pub instruction: String,
+    pub changed_registers: Vec<(u8, u8)>, // (register number, new value)
+    pub index_register: u16,
+    pub delay_timer: u32,
+    pub sound_timer: u32,
+    pub stack_depth: usize,
+}
+
+impl TraceEntry {
+    fn format(&self, format: TraceFormat) -> String {
+        let changes: Vec<String> = self
+            .changed_registers
+            .iter()
+            .map(|(number, value)| format!("V{:X}={:02X}", number, value))
+            .collect();
+        match format {
+            TraceFormat::Text => format!(
+                "{:>8} {:03X}: {:04X}  {:<18} {:<12} I={:03X} DT={:02X} ST={:02X} SP={}",
+                self.cycle,
+                self.pc,
+                self.opcode,
+                self.instruction,
+                changes.join(" "),
+                self.index_register,
+                self.delay_timer,
+                self.sound_timer,
+                self.stack_depth
+            ),
+            TraceFormat::Csv => format!(
+                "{},{:03X},{:04X},\"{}\",{},{:03X},{},{},{}",
+                self.cycle,
+                self.pc,
+                self.opcode,
+                self.instruction,
+                changes.join(" "),
+                self.index_register,
+                self.delay_timer,
+                self.sound_timer,
+                self.stack_depth
+            ),
+        }
+    }
+}
+
+const CSV_HEADER: &str = "cycle,pc,opcode,instruction,changed,i,dt,st,sp";
+
+pub struct Tracer {
+    output: Box<dyn Write>,
+    format: TraceFormat,
+    address_range: Option<(u16, u16)>,
+    kinds: BTreeSet<String>,
+    // when set, only the last N entries are kept and written out when the program stops
+    // running or the emulator panics
+    ring: Option<(usize, VecDeque<String>)>,
+    wrote_header: bool,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            format: TraceFormat::Text,
+            address_range: None,
+            kinds: BTreeSet::new(),
+            ring: None,
+            wrote_header: false,
+        }
+    }
+
+    pub fn to_stderr() -> Self {
+        Tracer::new(Box::new(io::stderr()))
+    }
+
+    pub fn to_file(filepath: &str) -> Result<Self, String> {
+        let file = File::create(filepath)
+            .map_err(|error| format!("Unable to create trace file {filepath}: {error}"))?;
+        Ok(Tracer::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub fn format(mut self, format: TraceFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    // only trace instructions with a PC between start and end (inclusive)
+    pub fn address_range(mut self, start: u16, end: u16) -> Self {
+        self.address_range = Some((start, end));
+        self
+    }
+
+    // only trace these kinds of instruction, e.g. "DRW" or "JP"
+    pub fn kinds(mut self, kinds: &[&str]) -> Self {
+        self.kinds = kinds.iter().map(|kind| kind.to_ascii_uppercase()).collect();
+        self
+    }
+
+    pub fn ring_buffer(mut self, capacity: usize) -> Self {
+        self.ring = Some((capacity, VecDeque::with_capacity(capacity)));
+        self
+    }
+
+    pub fn wants(&self, pc: u16, kind: &str) -> bool {
+        let in_range = match self.address_range {
+            Some((start, end)) => (start..=end).contains(&pc),
+            None => true,
+        };
+        in_range && (self.kinds.is_empty() || self.kinds.contains(kind))
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) {
+        if !self.wants(entry.pc, &entry.kind) {
+            return;
+        }
+        let line = entry.format(self.format);
+        match &mut self.ring {
+            Some((capacity, lines)) => {
+                if lines.len() == *capacity {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+            None => self.write_line(&line),
+        }
+    }
+
+    // the entries currently held in the ring buffer
+    pub fn buffered(&self) -> Vec<String> {
+        match &self.ring {
+            Some((_, lines)) => lines.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // writes out (and empties) the ring buffer
+    pub fn dump(&mut self) {
+        let lines = match &mut self.ring {
+            Some((_, lines)) => lines.drain(..).collect::<Vec<String>>(),
+            None => return,
+        };
+        for line in lines {
+            self.write_line(&line);
+        }
+        self.output.flush().unwrap_or(());
+    }
+
+    // writes out what's left in the ring buffer under a heading, once the program has
+    // stopped or crashed
+    pub fn dump_last(&mut self) {
+        let count = self.buffered().len();
+        if count > 0 {
+            writeln!(self.output, "last {count} instructions:").unwrap_or(());
+            self.dump();
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        // a trace that can't be written shouldn't stop the emulator
+        if self.format == TraceFormat::Csv && !self.wrote_header {
+            writeln!(self.output, "{CSV_HEADER}").unwrap_or(());
+            self.wrote_header = true;
+        }
+        writeln!(self.output, "{line}").unwrap_or(());
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.dump_last();
+        }
+        self.output.flush().unwrap_or(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Chip8;
+
+    const PROGRAM: &str = r#"
+    200: 6001 // LD V0, 1
+    202: 6102 // LD V1, 2
+    204: A300 // LD I, 300
+    206: D015 // DRW V0, V1, 5
+    208: 7001 // ADD V0, 1
+    "#;
+
+    fn run_traced(tracer: Tracer) -> Vec<String> {
+        let mut chip8 = Chip8::load_from_text(PROGRAM).setup_tracer(tracer);
+        // stopping at the end of the program would write out the ring buffer
+        for _ in 0..5 {
+            chip8.cycle();
+        }
+        chip8.tracer().unwrap().buffered()
+    }
+
+    #[test]
+    fn test_trace_entries() {
+        let lines = run_traced(Tracer::new(Box::new(io::sink())).ring_buffer(10));
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].contains("200: 6001  LD V0, 0x01"));
+        assert!(lines[0].contains("V0=01"));
+        assert!(lines[2].contains("I=300"));
+        assert!(lines[2].contains("DT=00 ST=00 SP=0"));
+        assert!(lines[3].contains("DRW V0, V1, 5"));
+    }
+
+    #[test]
+    fn test_trace_ring_buffer() {
+        let lines = run_traced(Tracer::new(Box::new(io::sink())).ring_buffer(2));
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("206: D015"));
+        assert!(lines[1].contains("208: 7001"));
+    }
+
+    #[test]
+    fn test_trace_filters() {
+        let tracer = Tracer::new(Box::new(io::sink()))
+            .ring_buffer(10)
+            .address_range(0x202, 0x208)
+            .kinds(&["ld"]);
+        let lines = run_traced(tracer);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("202: 6102"));
+        assert!(lines[1].contains("204: A300"));
+    }
+
+    #[test]
+    fn test_trace_csv_file() {
+        let filepath = std::env::temp_dir().join("chip8_tracer_test.csv");
+        let filepath = filepath.to_str().unwrap();
+        {
+            let tracer = Tracer::to_file(filepath).unwrap().format(TraceFormat::Csv);
+            let mut chip8 = Chip8::load_from_text(PROGRAM).setup_tracer(tracer);
+            while chip8.cycle() != -1 {}
+        }
+        let contents = std::fs::read_to_string(filepath).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "1,200,6001,\"LD V0, 0x01\",V0=01,000,0,0,0");
+        assert_eq!(lines.len(), 6);
+        std::fs::remove_file(filepath).unwrap();
+    }
+
+    #[test]
+    fn test_trace_dumped_when_stopped() {
+        let filepath = std::env::temp_dir().join("chip8_tracer_dump_test.txt");
+        let filepath = filepath.to_str().unwrap();
+        let tracer = Tracer::to_file(filepath).unwrap().ring_buffer(2);
+        let code = "200: 6005\n202: F018\n204: 7001";
+        let mut chip8 = Chip8::load_from_text(code).setup_tracer(tracer);
+        while chip8.cycle() != -1 {}
+        // written when the program stopped, rather than when the emulator went away
+        assert!(chip8.tracer().unwrap().buffered().is_empty());
+        drop(chip8);
+        let contents = std::fs::read_to_string(filepath).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "last 2 instructions:");
+        assert!(lines[1].contains("202: F018") && lines[1].contains("ST=05"));
+        assert!(lines[2].contains("204: 7001"));
+        std::fs::remove_file(filepath).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::frontend::{Frontend, InputEvent};
+use crate::key::Key;
+use crate::keymap::Keymap;
+use crate::memory::Memory;
+use crate::palette::Palette;
+
+// A frontend for terminals without a display, e.g. over SSH.
+// The screen is drawn with Unicode block or braille characters and redrawn in place
+// with ANSI escape codes, and the keypad is read from stdin in raw mode.
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TtyStyle {
+    // one column per pixel and two rows per line, so a 128 pixel wide screen fits in 132 columns
+    HalfBlocks,
+    // 2x4 pixels per character, for small terminals
+    Braille,
+}
+
+const WIDTH: usize = 64;
+
+// Terminals only send key presses, repeated while the key is held down, so a key
+// counts as released once it hasn't been seen for this long.
+const RELEASE_AFTER: Duration = Duration::from_millis(250);
+
+// the lines of text that draw a framebuffer, one byte per pixel in rows of width
+pub fn render_lines(framebuffer: &[u8], width: usize, style: TtyStyle) -> Vec<String> {
+    let height = framebuffer.len() / width;
+    let pixel = |x: usize, y: usize| y < height && framebuffer[y * width + x] != 0;
+    match style {
+        TtyStyle::HalfBlocks => (0..height)
+            .step_by(2)
+            .map(|y| {
+                (0..width)
+                    .map(|x| match (pixel(x, y), pixel(x, y + 1)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    })
+                    .collect()
+            })
+            .collect(),
+        TtyStyle::Braille => (0..height)
+            .step_by(4)
+            .map(|y| {
+                (0..width)
+                    .step_by(2)
+                    .map(|x| {
+                        // the dot numbering of a braille cell, column by column
+                        const DOTS: [(usize, usize, u32); 8] = [
+                            (0, 0, 0x01),
+                            (0, 1, 0x02),
+                            (0, 2, 0x04),
+                            (1, 0, 0x08),
+                            (1, 1, 0x10),
+                            (1, 2, 0x20),
+                            (0, 3, 0x40),
+                            (1, 3, 0x80),
+                        ];
+                        let bits = DOTS
+                            .iter()
+                            .filter(|(dx, dy, _)| x + dx < width && pixel(x + dx, y + dy))
+                            .fold(0, |bits, (_, _, bit)| bits | bit);
+                        char::from_u32(0x2800 + bits).unwrap_or(' ')
+                    })
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+// when each keypad key was last seen, while it's held down
+struct HeldKeys([Option<Instant>; 16]);
+
+impl HeldKeys {
+    // true if the key wasn't already held down
+    fn press(&mut self, key: Key, now: Instant) -> bool {
+        self.0[key as usize].replace(now).is_none()
+    }
+
+    fn release_expired(&mut self, now: Instant) -> Vec<Key> {
+        let mut released = Vec::new();
+        for (value, seen) in self.0.iter_mut().enumerate() {
+            if matches!(seen, Some(time) if now.duration_since(*time) >= RELEASE_AFTER) {
+                *seen = None;
+                released.extend(Key::try_from(value as u8));
+            }
+        }
+        released
+    }
+}
+
+// the keypad key for a byte typed on the terminal, from the same names as the SDL window's
+fn key_from_byte(byte: u8, keymap: &Keymap<u8>) -> Option<Key> {
+    keymap.get(byte.to_ascii_lowercase())
+}
+
+fn hotkey_from_byte(byte: u8, keymap: &Keymap<u8>) -> Option<InputEvent> {
+    keymap.hotkey(byte.to_ascii_lowercase())
+}
+
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|error| format!("Unable to run stty: {error}"))?;
+    if !output.status.success() {
+        return Err("stdin is not a terminal".to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+pub struct TtyContext {
+    style: TtyStyle,
+    keymap: Keymap<u8>,
+    input: Receiver<Vec<u8>>,
+    held: HeldKeys,
+    saved_settings: String,
+    last_frame: (Vec<String>, Option<Palette>),
+}
+
+impl TtyContext {
+    pub fn new(style: TtyStyle, keymap: Keymap<u8>) -> Result<Self, String> {
+        let saved_settings = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        // reads happen on their own thread so the emulator never blocks on the keyboard
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 32];
+            while let Ok(length @ 1..) = io::stdin().read(&mut buffer) {
+                if sender.send(buffer[..length].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // hide the cursor and clear the screen
+        print!("\x1b[?25l\x1b[2J");
+        Ok(Self {
+            style,
+            keymap,
+            input,
+            held: HeldKeys([None; 16]),
+            saved_settings,
+            last_frame: (Vec::new(), None),
+        })
+    }
+}
+
+impl Frontend for TtyContext {
+    fn render_graphics(&mut self, memory: &Memory, palette: &Palette) {
+        let frame = (
+            render_lines(memory.framebuffer(), WIDTH, self.style),
+            Some(*palette),
+        );
+        if frame == self.last_frame {
+            return;
+        }
+        // 24 bit colour, which most terminals support, and raw mode doesn't turn \n into \r\n
+        let [fr, fg, fb] = palette.foreground;
+        let [br, bg, bb] = palette.background;
+        print!(
+            "\x1b[H\x1b[38;2;{fr};{fg};{fb}m\x1b[48;2;{br};{bg};{bb}m{}\x1b[0m",
+            frame.0.join("\r\n")
+        );
+        io::stdout().flush().unwrap_or(());
+        self.last_frame = frame;
+    }
+
+    // on the line under the screen, replacing the one before
+    fn show_message(&mut self, message: &str) {
+        let row = self.last_frame.0.len() + 1;
+        print!("\x1b[{row};1H\x1b[2K{message}");
+        io::stdout().flush().unwrap_or(());
+    }
+
+    fn handle_input(&mut self) -> Result<Vec<InputEvent>, &str> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        while let Ok(bytes) = self.input.try_recv() {
+            // Escape on its own or Ctrl+C quits, and other escape sequences
+            // (arrow and function keys) are ignored
+            if bytes == [0x1B] || bytes.contains(&0x03) {
+                return Err("QUIT");
+            }
+            if bytes[0] == 0x1B {
+                continue;
+            }
+            for byte in bytes {
+                if let Some(hotkey) = hotkey_from_byte(byte, &self.keymap) {
+                    events.push(hotkey);
+                } else if let Some(key) = key_from_byte(byte, &self.keymap) {
+                    if self.held.press(key, now) {
+                        events.push(InputEvent::Key(key, true));
+                    }
+                }
+            }
+        }
+        for key in self.held.release_expired(now) {
+            events.push(InputEvent::Key(key, false));
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for TtyContext {
+    fn drop(&mut self) {
+        // show the cursor again and put the terminal back how it was
+        print!("\x1b[?25h\r\n");
+        io::stdout().flush().unwrap_or(());
+        stty(&[&self.saved_settings]).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an 8x4 screen with a diagonal line
+    fn diagonal() -> Vec<u8> {
+        let mut framebuffer = vec![0; 8 * 4];
+        for i in 0..4 {
+            framebuffer[i * 8 + i] = 1;
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let lines = render_lines(&diagonal(), 8, TtyStyle::HalfBlocks);
+        assert_eq!(lines, vec!["▀▄      ", "  ▀▄    "]);
+    }
+
+    #[test]
+    fn test_braille() {
+        let lines = render_lines(&diagonal(), 8, TtyStyle::Braille);
+        // dots 1 and 5 in the first cell, 3 and 8 in the second
+        assert_eq!(lines, vec!["\u{2811}\u{2884}\u{2800}\u{2800}"]);
+    }
+
+    #[test]
+    fn test_held_keys() {
+        let start = Instant::now();
+        let mut held = HeldKeys([None; 16]);
+        assert!(held.press(Key::Code5, start));
+        // a repeat while held down
+        assert!(!held.press(Key::Code5, start + Duration::from_millis(100)));
+        assert!(held
+            .release_expired(start + Duration::from_millis(300))
+            .is_empty());
+        assert_eq!(
+            held.release_expired(start + Duration::from_millis(400)),
+            vec![Key::Code5]
+        );
+        assert!(held.press(Key::Code5, start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_key_from_byte() {
+        let keymap = Keymap::default();
+        assert_eq!(key_from_byte(b'w', &keymap), Some(Key::Code5));
+        assert_eq!(key_from_byte(b'W', &keymap), Some(Key::Code5));
+        assert_eq!(key_from_byte(b'x', &keymap), Some(Key::Code0));
+        assert_eq!(key_from_byte(b'p', &keymap), None);
+        let keymap = Keymap::preset("azerty").unwrap();
+        assert_eq!(key_from_byte(b'&', &keymap), Some(Key::Code1));
+        assert_eq!(key_from_byte(b' ', &keymap), None);
+        assert_eq!(hotkey_from_byte(b' ', &keymap), Some(InputEvent::Pause));
+    }
+}
@@ -0,0 +1,152 @@
+use wasm_bindgen::prelude::*;
+
+use crate::emulator::Chip8;
+use crate::key::Key;
+use crate::register::Register;
+
+// The JavaScript API of the wasm build, for stepping through programs on a web page:
+//
+//   const emulator = new Emulator(bytes);
+//   emulator.setKey(5, true);
+//   emulator.runCycles(100);
+//   draw(emulator.framebuffer());  // a Uint8Array of 64 x 32 pixels, row by row
+//
+// Build it with
+// `cargo build --lib --release --target wasm32-unknown-unknown --no-default-features --features wasm`
+// and run wasm-bindgen on the .wasm file for the JavaScript side.
+
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: Chip8,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Emulator, String> {
+        let chip8 = Chip8::load_from_bytes(rom)?;
+        Ok(Emulator { chip8 })
+    }
+
+    // there's no clock to seed the random numbers from, so pages that want them
+    // different every time pass one in
+    pub fn seed(&mut self, seed: u32) {
+        self.chip8.seed_rng(seed);
+    }
+
+    // false once the program has halted, which it does on a zero opcode
+    #[wasm_bindgen(js_name = runCycles)]
+    pub fn run_cycles(&mut self, cycles: u32) -> bool {
+        (0..cycles).all(|_| self.chip8.cycle() != -1)
+    }
+
+    // counts the delay timer down, which should happen 60 times a second
+    #[wasm_bindgen(js_name = tickTimers)]
+    pub fn tick_timers(&mut self) {
+        self.chip8.tick_timers();
+    }
+
+    pub fn reset(&mut self) {
+        self.chip8.reset();
+    }
+
+    // one byte per pixel, 0 when it's off
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.chip8.memory().framebuffer().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = setKey)]
+    pub fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), String> {
+        self.chip8.set_key(Key::try_from(key)?, pressed);
+        Ok(())
+    }
+
+    pub fn v(&self, x: u8) -> Result<u8, String> {
+        Ok(self.chip8.memory().peek8(v_register(x)? as usize))
+    }
+
+    #[wasm_bindgen(js_name = setV)]
+    pub fn set_v(&mut self, x: u8, value: u8) -> Result<(), String> {
+        self.chip8.set8(v_register(x)? as usize, value);
+        Ok(())
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.chip8.pc()
+    }
+
+    #[wasm_bindgen(js_name = setPc)]
+    pub fn set_pc(&mut self, value: u16) -> Result<(), String> {
+        self.set_address(Register::PC, value)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.chip8.memory().peek16(Register::IR as usize)
+    }
+
+    #[wasm_bindgen(js_name = setIndex)]
+    pub fn set_index(&mut self, value: u16) -> Result<(), String> {
+        self.set_address(Register::IR, value)
+    }
+
+    #[wasm_bindgen(js_name = delayTimer)]
+    pub fn delay_timer(&self) -> u32 {
+        self.chip8.delay_timer()
+    }
+
+    #[wasm_bindgen(js_name = setDelayTimer)]
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.chip8.set_delay_timer(value as u32);
+    }
+
+    fn set_address(&mut self, register: Register, value: u16) -> Result<(), String> {
+        if value >= 4096 {
+            return Err(format!(
+                "{register} can't be set to {value:#X}, past the end of memory"
+            ));
+        }
+        self.chip8.set16(register as usize, value);
+        Ok(())
+    }
+}
+
+fn v_register(x: u8) -> Result<Register, String> {
+    if x > 0xF {
+        return Err(format!("There's no register V{x:X}"));
+    }
+    Ok(Register::v_register_from(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emulator() {
+        // V0 = 5, I = 20A, draw 1 row at (V0, V0), then halt
+        let mut emulator = Emulator::new(&[
+            0x60, 0x05, 0xA2, 0x0A, 0xD0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x80,
+        ])
+        .unwrap();
+        assert!(emulator.run_cycles(3));
+        assert_eq!(emulator.v(0), Ok(5));
+        assert_eq!(emulator.index(), 0x20A);
+        let framebuffer = emulator.framebuffer();
+        assert_eq!(framebuffer.len(), 64 * 32);
+        assert_eq!(framebuffer.iter().filter(|pixel| **pixel != 0).count(), 1);
+        assert!(!emulator.run_cycles(1));
+
+        emulator.set_v(0xF, 9).unwrap();
+        assert_eq!(emulator.v(0xF), Ok(9));
+        assert!(emulator.v(16).is_err());
+        emulator.set_pc(0x200).unwrap();
+        assert!(emulator.set_index(0x1000).is_err());
+        assert!(emulator.set_key(0xF, true).is_ok());
+        assert!(emulator.set_key(16, true).is_err());
+        emulator.set_delay_timer(3);
+        emulator.tick_timers();
+        assert_eq!(emulator.delay_timer(), 2);
+
+        assert!(Emulator::new(&[0; 4096]).is_err());
+    }
+}
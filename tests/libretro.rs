@@ -0,0 +1,289 @@
+// A minimal libretro frontend: loads the core's shared library the way RetroArch would
+// and drives it through its entry points.
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const RTLD_NOW: c_int = 2;
+
+#[link(name = "dl")]
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+}
+
+#[repr(C)]
+struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SystemAvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+static OPTIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static VIDEO: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+static AUDIO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+// the loudest sample in the last frame of audio
+static AUDIO_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        // SET_PIXEL_FORMAT
+        10 => unsafe { *(data as *const c_uint) == 1 },
+        // GET_VARIABLE, running at 8 frames per frame
+        15 => unsafe {
+            let variable = &mut *(data as *mut Variable);
+            if CStr::from_ptr(variable.key) != c"chip8_speed" {
+                return false;
+            }
+            variable.value = c"8x".as_ptr();
+            true
+        },
+        // SET_VARIABLES
+        16 => {
+            let mut variable = data as *const Variable;
+            let mut options = OPTIONS.lock().unwrap();
+            unsafe {
+                while !(*variable).key.is_null() {
+                    options.push(
+                        CStr::from_ptr((*variable).key)
+                            .to_string_lossy()
+                            .into_owned(),
+                    );
+                    variable = variable.add(1);
+                }
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    assert_eq!((width, height, pitch), (64, 32, 256));
+    let pixels = unsafe { std::slice::from_raw_parts(data as *const u32, 64 * 32) };
+    *VIDEO.lock().unwrap() = pixels.to_vec();
+}
+
+extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let samples = unsafe { std::slice::from_raw_parts(data, frames * 2) };
+    let peak = samples.iter().map(|sample| sample.unsigned_abs()).max();
+    AUDIO_PEAK.store(peak.unwrap_or(0) as usize, Ordering::SeqCst);
+    AUDIO_FRAMES.fetch_add(frames, Ordering::SeqCst);
+    frames
+}
+
+extern "C" fn input_poll() {}
+
+// A on the first pad, which is key 6
+extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    (port == 0 && device == 1 && id == 8) as i16
+}
+
+struct Library(*mut c_void);
+
+impl Library {
+    fn open() -> Self {
+        // the test binary is in target/<profile>/deps, next to the core
+        let mut path = PathBuf::from(std::env::current_exe().unwrap().parent().unwrap());
+        path.push("libchip8_emulator.so");
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let handle = unsafe { dlopen(path.as_ptr(), RTLD_NOW) };
+        assert!(!handle.is_null(), "couldn't load {path:?}");
+        Library(handle)
+    }
+
+    // the symbol, as a function of type F
+    unsafe fn get<F: Copy>(&self, name: &CStr) -> F {
+        let symbol = dlsym(self.0, name.as_ptr());
+        assert!(!symbol.is_null(), "missing {name:?}");
+        std::mem::transmute_copy(&symbol)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { dlclose(self.0) };
+    }
+}
+
+#[test]
+fn test_core() {
+    // sound for 10 frames, CLS, V0 = 0, V1 = 0, I = 212, draw 1 row, wait for a key into V2,
+    // loop forever
+    let rom: [u8; 19] = [
+        0x60, 0x0A, 0xF0, 0x18, 0x00, 0xE0, 0x60, 0x00, 0x61, 0x00, 0xA2, 0x12, 0xD0, 0x11, 0xF2,
+        0x0A, 0x12, 0x10, 0xFF,
+    ];
+
+    let core = Library::open();
+    unsafe {
+        let api_version: extern "C" fn() -> c_uint = core.get(c"retro_api_version");
+        assert_eq!(api_version(), 1);
+
+        let get_system_info: extern "C" fn(*mut SystemInfo) = core.get(c"retro_get_system_info");
+        let mut info: SystemInfo = std::mem::zeroed();
+        get_system_info(&mut info);
+        assert_eq!(CStr::from_ptr(info.library_name), c"chip8-emulator");
+        assert_eq!(CStr::from_ptr(info.valid_extensions), c"ch8");
+        assert!(!info.need_fullpath);
+
+        let set_environment: extern "C" fn(extern "C" fn(c_uint, *mut c_void) -> bool) =
+            core.get(c"retro_set_environment");
+        set_environment(environment);
+        assert_eq!(
+            *OPTIONS.lock().unwrap(),
+            [
+                "chip8_vf_reset",
+                "chip8_jump_vx",
+                "chip8_clip",
+                "chip8_speed"
+            ]
+        );
+        let set_video_refresh: extern "C" fn(extern "C" fn(*const c_void, c_uint, c_uint, usize)) =
+            core.get(c"retro_set_video_refresh");
+        set_video_refresh(video_refresh);
+        let set_audio_sample_batch: extern "C" fn(extern "C" fn(*const i16, usize) -> usize) =
+            core.get(c"retro_set_audio_sample_batch");
+        set_audio_sample_batch(audio_sample_batch);
+        let set_input_poll: extern "C" fn(extern "C" fn()) = core.get(c"retro_set_input_poll");
+        set_input_poll(input_poll);
+        let set_input_state: extern "C" fn(extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16) =
+            core.get(c"retro_set_input_state");
+        set_input_state(input_state);
+
+        let init: extern "C" fn() = core.get(c"retro_init");
+        init();
+        let load_game: extern "C" fn(*const GameInfo) -> bool = core.get(c"retro_load_game");
+        let game = GameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        assert!(load_game(&game));
+
+        let get_av_info: extern "C" fn(*mut SystemAvInfo) = core.get(c"retro_get_system_av_info");
+        let mut av_info = SystemAvInfo::default();
+        get_av_info(&mut av_info);
+        assert_eq!((av_info.base_width, av_info.base_height), (64, 32));
+        assert_eq!((av_info.fps, av_info.sample_rate), (60.0, 44100.0));
+
+        // at 8x the first frame runs the whole program, with A held down the whole time
+        let run: extern "C" fn() = core.get(c"retro_run");
+        run();
+        let video = VIDEO.lock().unwrap().clone();
+        assert_eq!(video[..9], [0, 0, 0, 0, 0, 0, 0, 0, 0xFFFFFF]);
+        assert!(video[64..].iter().all(|pixel| *pixel == 0xFFFFFF));
+        assert_eq!(AUDIO_FRAMES.load(Ordering::SeqCst), 735);
+        // the beep is still going, and has stopped 8 frames later
+        assert_eq!(AUDIO_PEAK.load(Ordering::SeqCst), 0x1000);
+        run();
+        assert_eq!(AUDIO_PEAK.load(Ordering::SeqCst), 0);
+
+        // V2 is the byte at address 6 of memory, straight after the 18 byte header
+        let serialize_size: extern "C" fn() -> usize = core.get(c"retro_serialize_size");
+        let serialize: extern "C" fn(*mut c_void, usize) -> bool = core.get(c"retro_serialize");
+        let unserialize: extern "C" fn(*const c_void, usize) -> bool =
+            core.get(c"retro_unserialize");
+        let size = serialize_size();
+        let mut state = vec![0xAAu8; size];
+        assert!(serialize(state.as_mut_ptr() as *mut c_void, size));
+        assert_eq!(&state[..8], b"CH8STATE");
+        assert_eq!(state[18 + 6], 6);
+        assert_eq!(state[size - 1], 0);
+
+        let reset: extern "C" fn() = core.get(c"retro_reset");
+        reset();
+        let mut after_reset = vec![0u8; size];
+        assert!(serialize(after_reset.as_mut_ptr() as *mut c_void, size));
+        assert_eq!(after_reset[18 + 6], 0);
+
+        assert!(unserialize(state.as_ptr() as *const c_void, size));
+        let mut restored = vec![0u8; size];
+        assert!(serialize(restored.as_mut_ptr() as *mut c_void, size));
+        assert_eq!(restored, state);
+        assert!(!unserialize(b"garbage".as_ptr() as *const c_void, 7));
+
+        let unload_game: extern "C" fn() = core.get(c"retro_unload_game");
+        unload_game();
+        assert!(!serialize(state.as_mut_ptr() as *mut c_void, size));
+        let deinit: extern "C" fn() = core.get(c"retro_deinit");
+        deinit();
+    }
+}
+
+#[test]
+fn test_halting() {
+    let core = Library::open();
+    unsafe {
+        let init: extern "C" fn() = core.get(c"retro_init");
+        let load_game: extern "C" fn(*const GameInfo) -> bool = core.get(c"retro_load_game");
+        let run: extern "C" fn() = core.get(c"retro_run");
+        let serialize_size: extern "C" fn() -> usize = core.get(c"retro_serialize_size");
+        let serialize: extern "C" fn(*mut c_void, usize) -> bool = core.get(c"retro_serialize");
+        let deinit: extern "C" fn() = core.get(c"retro_deinit");
+        init();
+
+        // PC and V0, from the start of memory after the 18 byte header
+        let registers = || {
+            let size = serialize_size();
+            let mut state = vec![0u8; size];
+            assert!(serialize(state.as_mut_ptr() as *mut c_void, size));
+            (u16::from_be_bytes([state[18], state[19]]), state[18 + 4])
+        };
+
+        // LD V0, 1, then the end of the program, and then an instruction that can't run
+        for (rom, pc) in [
+            ([0x60u8, 0x01, 0x00, 0x00], 0x204),
+            ([0x60, 0x01, 0xFF, 0xFF], 0x202),
+        ] {
+            let game = GameInfo {
+                path: std::ptr::null(),
+                data: rom.as_ptr() as *const c_void,
+                size: rom.len(),
+                meta: std::ptr::null(),
+            };
+            assert!(load_game(&game));
+            run();
+            run();
+            assert_eq!(registers(), (pc, 1));
+            // and stays where it stopped, however many more frames the frontend runs
+            for _ in 0..5 {
+                run();
+            }
+            assert_eq!(registers(), (pc, 1));
+        }
+        deinit();
+    }
+}